@@ -0,0 +1,83 @@
+//! The address bar: editing and drawing the URL the user types.
+//!
+//! The client hands the key codes typed while the address bar is open to
+//! [`edit`]. The first left button leaves the bar without navigating and the
+//! first right button clears the line, since the bar opens with the current
+//! URL filled in.
+
+use alloc::format;
+use alloc::string::String;
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+
+use crate::keyboard::Keycode;
+use crate::style::{Run, FONT, FONT_BOLD};
+use crate::view::{columns, MARGIN_LEFT, MARGIN_TOP, SPACING};
+
+/// Characters that may appear in a URL, see RFC 3986.
+pub fn is_url_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-._~:/?#[]@!$&'()*+,;=%".contains(c)
+}
+
+/// What a key press did to the address being typed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    /// Still typing.
+    Typing,
+    /// Navigate to the typed URL.
+    Submit,
+    /// Leave the address bar without navigating.
+    Cancel,
+}
+
+/// Applies one key press to `url`.
+pub fn edit(url: &mut String, code: Keycode) -> Edit {
+    match code {
+        Keycode::Enter | Keycode::Select => return Edit::Submit,
+        Keycode::Button(0) => return Edit::Cancel,
+        Keycode::Button(1) => url.clear(),
+        Keycode::Backspace => {
+            url.pop();
+        }
+        Keycode::Char(c) if is_url_char(c) => url.push(c),
+        _ => {}
+    }
+    Edit::Typing
+}
+
+/// The last `count` characters of `text`.
+fn tail(text: &str, count: usize) -> &str {
+    let skip = text.chars().count().saturating_sub(count);
+    match text.char_indices().nth(skip) {
+        Some((start, _)) => &text[start..],
+        None => "",
+    }
+}
+
+/// Draws the address bar with `url` being typed over the page at `current`.
+/// The target is expected to be clear.
+pub fn draw<D>(current: &str, url: &str, target: &mut D) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let plain = Run::plain(FONT);
+    let bold = Run::plain(FONT_BOLD);
+    let size = FONT.character_size;
+    let visible = columns(size.width);
+    let mut y = MARGIN_TOP;
+    let mut line = |run: &Run, text: &str, target: &mut D| {
+        let result = run.draw(tail(text, visible), Point::new(MARGIN_LEFT, y), target);
+        y += (size.height + SPACING) as i32;
+        result
+    };
+
+    line(&bold, "Go to:", target)?;
+    // Keep the cursor in view by only showing the end of long URLs.
+    line(&plain, &format!("{}_", url), target)?;
+    line(&plain, "", target)?;
+    line(&plain, "Current page:", target)?;
+    line(&plain, current, target)?;
+    line(&plain, "", target)?;
+    line(&plain, "Left: cancel  Right: clear", target)
+}
@@ -0,0 +1,462 @@
+//! Pages kept in flash, so they can be shown again without the server.
+//!
+//! Every cached page takes a run of whole flash pages: a header with its
+//! URL, then the payload of the [`Page`](protocol::MessageType::Page)
+//! message. The size of the region is the budget: when a new page does not
+//! fit, the page used least recently is dropped, until there is a run of free
+//! flash pages large enough. Dropping a page only erases its header.
+//!
+//! Pages are written while they download, see [`Cache::begin`], and only
+//! count once [`Cache::commit`] wrote the last word of the header, with the
+//! [`etag`](protocol::etag) of the payload. The ETag is checked again when
+//! the cache is opened, so a page cut short by a reset or worn flash is never
+//! shown. A page written again keeps its old copy until then, and should a
+//! reset come between committing the new copy and dropping the old one, the
+//! newer is kept when the cache is opened.
+//!
+//! When a page was last used is kept in a few spare words of its header,
+//! which are written one at a time. Once they are used up it is only kept in
+//! memory, so a page shown very often may look older than it is after a
+//! reset.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use protocol::Crc32;
+
+use crate::settings::{Flash, WORD};
+
+/// Longest URL cached, in bytes.
+pub const MAX_URL_LEN: usize = 256;
+
+/// Marks the first flash page of a cached page.
+const MAGIC: u32 = 0x4548_4342;
+/// Words in the header, in front of the URL.
+const HEADER_WORDS: u32 = 14;
+const LEN_WORD: u32 = 1;
+const URL_LEN_WORD: u32 = 2;
+/// The ETag, followed by the commit word.
+const ETAG_WORD: u32 = 3;
+const CREATED_WORD: u32 = 5;
+const TOUCH_WORDS: u32 = 6;
+/// How often using a page is written down.
+const TOUCHES: u32 = HEADER_WORDS - TOUCH_WORDS;
+const COMMITTED: u32 = 0;
+const ERASED: u32 = 0xffff_ffff;
+/// Read at once when checking and reading payloads.
+const CHUNK_LEN: usize = 256;
+
+#[derive(Debug)]
+pub enum CacheError<E> {
+    Flash(E),
+    /// The URL is empty or longer than [`MAX_URL_LEN`].
+    BadUrl,
+    /// The page does not fit in the cache at all.
+    TooLarge,
+    /// More or fewer bytes were written than [`Cache::begin`] was told.
+    Length,
+    /// Nothing is being written.
+    NotWriting,
+}
+
+impl<E> From<E> for CacheError<E> {
+    fn from(err: E) -> Self {
+        CacheError::Flash(err)
+    }
+}
+
+/// Rounds `len` up to a whole number of words.
+fn words(len: usize) -> u32 {
+    (len as u32 + WORD - 1) / WORD * WORD
+}
+
+/// Offset of the payload from the start of the first flash page.
+fn payload_offset(url_len: usize) -> u32 {
+    HEADER_WORDS * WORD + words(url_len)
+}
+
+struct Entry {
+    url: String,
+    /// First flash page and how many there are.
+    page: u32,
+    pages: u32,
+    len: u32,
+    etag: u32,
+    /// When the page was last used, on the clock of the cache.
+    used: u32,
+    /// Touch words written so far.
+    touches: u32,
+}
+
+/// A page being written while it downloads.
+struct Writer {
+    entry: Entry,
+    written: u32,
+    /// Flash pages erased so far.
+    erased: u32,
+    crc: Crc32,
+    /// Bytes that do not make a whole word yet.
+    pending: Vec<u8>,
+}
+
+pub struct Cache<F: Flash> {
+    flash: F,
+    entries: Vec<Entry>,
+    writer: Option<Writer>,
+    /// Counts up every time a page is stored or used.
+    clock: u32,
+}
+
+impl<F: Flash> Cache<F> {
+    /// Opens the cache in `flash`, dropping anything in it that is not a
+    /// complete page.
+    pub fn open(mut flash: F) -> Result<Self, F::Error> {
+        let pages = flash.size() / F::PAGE_SIZE;
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut page = 0;
+        while page < pages {
+            match read_entry(&mut flash, page, pages)? {
+                Some(entry) => {
+                    page += entry.pages;
+                    match entries.iter_mut().find(|other| other.url == entry.url) {
+                        Some(other) if other.used < entry.used => *other = entry,
+                        Some(_) => {}
+                        None => entries.push(entry),
+                    }
+                }
+                None => page += 1,
+            }
+        }
+        let clock = entries.iter().map(|entry| entry.used.wrapping_add(1)).max().unwrap_or(0);
+        Ok(Self {
+            flash,
+            entries,
+            writer: None,
+            clock,
+        })
+    }
+
+    /// Gives the flash back.
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    fn tick(&mut self) -> u32 {
+        let now = self.clock;
+        self.clock = self.clock.wrapping_add(1);
+        now
+    }
+
+    fn find(&self, url: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.url == url)
+    }
+
+    /// The ETag of the cached copy of `url`, if there is one.
+    pub fn etag(&self, url: &str) -> Option<u32> {
+        self.find(url).map(|i| self.entries[i].etag)
+    }
+
+    /// URLs of the cached pages, in no particular order.
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.url.as_str())
+    }
+
+    /// Hands the payload of the cached copy of `url` to `sink` a piece at a
+    /// time, until `sink` returns false. Returns whether all of it was read.
+    pub fn read(&mut self, url: &str, sink: impl FnMut(&[u8]) -> bool) -> Result<bool, F::Error> {
+        let Some(i) = self.find(url) else {
+            return Ok(false);
+        };
+        self.read_range(url, 0..self.entries[i].len, sink)
+    }
+
+    /// Like [`Cache::read`], for the bytes of the payload in `range` only.
+    pub fn read_range(
+        &mut self,
+        url: &str,
+        range: Range<u32>,
+        mut sink: impl FnMut(&[u8]) -> bool,
+    ) -> Result<bool, F::Error> {
+        let Some(i) = self.find(url) else {
+            return Ok(false);
+        };
+        let entry = &self.entries[i];
+        if range.end > entry.len {
+            return Ok(false);
+        }
+        let start = entry.page * F::PAGE_SIZE + payload_offset(entry.url.len());
+        let mut buf = [0; CHUNK_LEN];
+        let mut offset = range.start;
+        while offset < range.end {
+            let n = (range.end - offset).min(CHUNK_LEN as u32) as usize;
+            self.flash.read(start + offset, &mut buf[..n])?;
+            if !sink(&buf[..n]) {
+                return Ok(false);
+            }
+            offset += n as u32;
+        }
+        Ok(true)
+    }
+
+    /// Reads the whole cached copy of `url`.
+    pub fn get(&mut self, url: &str) -> Result<Option<Vec<u8>>, F::Error> {
+        let mut payload = Vec::new();
+        let complete = self.read(url, |data| {
+            payload.extend_from_slice(data);
+            true
+        })?;
+        Ok(complete.then_some(payload))
+    }
+
+    /// Marks `url` as just used, so it is the last to be dropped.
+    pub async fn touch(&mut self, url: &str) -> Result<(), F::Error> {
+        let Some(i) = self.find(url) else {
+            return Ok(());
+        };
+        let now = self.tick();
+        let entry = &mut self.entries[i];
+        entry.used = now;
+        if entry.touches < TOUCHES {
+            let offset = entry.page * F::PAGE_SIZE + (TOUCH_WORDS + entry.touches) * WORD;
+            entry.touches += 1;
+            self.flash.write(offset, &now.to_le_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// Drops the cached copy of `url`, if there is one.
+    pub async fn remove(&mut self, url: &str) -> Result<(), F::Error> {
+        if let Some(i) = self.find(url) {
+            let entry = self.entries.swap_remove(i);
+            self.flash.erase(entry.page * F::PAGE_SIZE).await?;
+        }
+        Ok(())
+    }
+
+    /// First flash page of a free run of `pages`, if there is one.
+    fn free_run(&self, pages: u32) -> Option<u32> {
+        let total = self.flash.size() / F::PAGE_SIZE;
+        let mut used = vec![false; total as usize];
+        let writing = self.writer.as_ref().map(|writer| &writer.entry);
+        for entry in self.entries.iter().chain(writing) {
+            for page in entry.page..entry.page + entry.pages {
+                used[page as usize] = true;
+            }
+        }
+        let mut run = 0;
+        for (page, &used) in used.iter().enumerate() {
+            run = if used { 0 } else { run + 1 };
+            if run == pages {
+                return Some(page as u32 + 1 - pages);
+            }
+        }
+        None
+    }
+
+    /// Starts caching the `len` byte payload of `url`, dropping the pages
+    /// used least recently to make room. The copy of `url` there is stays
+    /// until [`Cache::commit`], unless nothing else can make room for the new
+    /// one. Whatever was being written before is abandoned.
+    pub async fn begin(&mut self, url: &str, len: usize) -> Result<(), CacheError<F::Error>> {
+        self.writer = None;
+        if url.is_empty() || url.len() > MAX_URL_LEN {
+            return Err(CacheError::BadUrl);
+        }
+        let total = self.flash.size() / F::PAGE_SIZE;
+        let size = payload_offset(url.len()) as usize + len;
+        let pages = ((size + F::PAGE_SIZE as usize - 1) / F::PAGE_SIZE as usize) as u32;
+        if pages > total {
+            return Err(CacheError::TooLarge);
+        }
+
+        let page = loop {
+            if let Some(page) = self.free_run(pages) {
+                break page;
+            }
+            let oldest = self
+                .entries
+                .iter()
+                .max_by_key(|entry| (entry.url != url, self.clock.wrapping_sub(entry.used)))
+                .map(|entry| entry.url.clone())
+                .ok_or(CacheError::TooLarge)?;
+            self.remove(&oldest).await?;
+        };
+
+        let start = page * F::PAGE_SIZE;
+        self.flash.erase(start).await?;
+        let mut header = Vec::with_capacity(3 * WORD as usize);
+        header.extend_from_slice(&MAGIC.to_le_bytes());
+        header.extend_from_slice(&(len as u32).to_le_bytes());
+        header.extend_from_slice(&(url.len() as u32).to_le_bytes());
+        self.flash.write(start, &header).await?;
+        let created = self.tick();
+        self.flash.write(start + CREATED_WORD * WORD, &created.to_le_bytes()).await?;
+        let mut url_words = Vec::from(url.as_bytes());
+        url_words.resize(words(url.len()) as usize, 0xff);
+        self.flash.write(start + HEADER_WORDS * WORD, &url_words).await?;
+
+        self.writer = Some(Writer {
+            entry: Entry {
+                url: String::from(url),
+                page,
+                pages,
+                len: len as u32,
+                etag: 0,
+                used: created,
+                touches: 0,
+            },
+            written: 0,
+            erased: 1,
+            crc: Crc32::new(),
+            pending: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Adds the next bytes of the payload being cached. Flash pages are
+    /// erased as the payload reaches them, so this never takes long.
+    pub async fn append(&mut self, data: &[u8]) -> Result<(), CacheError<F::Error>> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Err(CacheError::NotWriting);
+        };
+        let written = writer.written + (writer.pending.len() + data.len()) as u32;
+        if written > writer.entry.len {
+            self.writer = None;
+            return Err(CacheError::Length);
+        }
+        writer.crc.update(data);
+        writer.pending.extend_from_slice(data);
+        let whole = writer.pending.len() / WORD as usize * WORD as usize;
+        let bytes: Vec<u8> = writer.pending.drain(..whole).collect();
+        let result = self.write_payload(&bytes).await;
+        if result.is_err() {
+            self.writer = None;
+        }
+        result
+    }
+
+    /// Writes whole words at the end of the payload so far.
+    async fn write_payload(&mut self, bytes: &[u8]) -> Result<(), CacheError<F::Error>> {
+        let writer = self.writer.as_mut().ok_or(CacheError::NotWriting)?;
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        let entry = &writer.entry;
+        let start = entry.page * F::PAGE_SIZE;
+        let offset = payload_offset(entry.url.len()) + writer.written;
+        let end = offset + bytes.len() as u32;
+        while writer.erased * F::PAGE_SIZE < end {
+            self.flash.erase(start + writer.erased * F::PAGE_SIZE).await?;
+            writer.erased += 1;
+        }
+        self.flash.write(start + offset, bytes).await?;
+        writer.written += bytes.len() as u32;
+        Ok(())
+    }
+
+    /// Finishes the page being cached, replacing the copy there was, and
+    /// returns its ETag.
+    pub async fn commit(&mut self) -> Result<u32, CacheError<F::Error>> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Err(CacheError::NotWriting);
+        };
+        let pending_len = writer.pending.len() as u32;
+        if writer.written + pending_len != writer.entry.len {
+            self.writer = None;
+            return Err(CacheError::Length);
+        }
+        let mut last = core::mem::take(&mut writer.pending);
+        last.resize(words(last.len()) as usize, 0xff);
+        if let Err(err) = self.write_payload(&last).await {
+            self.writer = None;
+            return Err(err);
+        }
+
+        let mut writer = self.writer.take().ok_or(CacheError::NotWriting)?;
+        let etag = writer.crc.finish();
+        let mut commit = Vec::with_capacity(2 * WORD as usize);
+        commit.extend_from_slice(&etag.to_le_bytes());
+        commit.extend_from_slice(&COMMITTED.to_le_bytes());
+        let offset = writer.entry.page * F::PAGE_SIZE + ETAG_WORD * WORD;
+        self.flash.write(offset, &commit).await?;
+        writer.entry.etag = etag;
+        let old = self.find(&writer.entry.url);
+        self.entries.push(writer.entry);
+        if let Some(i) = old {
+            let old = self.entries.swap_remove(i);
+            self.flash.erase(old.page * F::PAGE_SIZE).await?;
+        }
+        Ok(etag)
+    }
+
+    /// Caches `payload` as the page at `url` in one go.
+    pub async fn insert(&mut self, url: &str, payload: &[u8]) -> Result<u32, CacheError<F::Error>> {
+        self.begin(url, payload.len()).await?;
+        self.append(payload).await?;
+        self.commit().await
+    }
+}
+
+/// Reads the cached page starting at flash page `page`, if it holds the
+/// start of a complete one.
+fn read_entry<F: Flash>(flash: &mut F, page: u32, total: u32) -> Result<Option<Entry>, F::Error> {
+    let start = page * F::PAGE_SIZE;
+    let mut header = [0; (HEADER_WORDS * WORD) as usize];
+    flash.read(start, &mut header)?;
+    let word = |i: u32| {
+        let i = (i * WORD) as usize;
+        u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]])
+    };
+    if word(0) != MAGIC || word(ETAG_WORD + 1) != COMMITTED {
+        return Ok(None);
+    }
+    let len = word(LEN_WORD);
+    let url_len = word(URL_LEN_WORD) as usize;
+    if url_len == 0 || url_len > MAX_URL_LEN {
+        return Ok(None);
+    }
+    let size = payload_offset(url_len) as u64 + len as u64;
+    let pages = ((size + F::PAGE_SIZE as u64 - 1) / F::PAGE_SIZE as u64) as u32;
+    if page as u64 + pages as u64 > total as u64 {
+        return Ok(None);
+    }
+
+    let mut url = vec![0; url_len];
+    flash.read(start + HEADER_WORDS * WORD, &mut url)?;
+    let Ok(url) = String::from_utf8(url) else {
+        return Ok(None);
+    };
+
+    let mut crc = Crc32::new();
+    let mut buf = [0; CHUNK_LEN];
+    let payload = start + payload_offset(url_len);
+    let mut offset = 0;
+    while offset < len {
+        let n = (len - offset).min(CHUNK_LEN as u32) as usize;
+        flash.read(payload + offset, &mut buf[..n])?;
+        crc.update(&buf[..n]);
+        offset += n as u32;
+    }
+    let etag = word(ETAG_WORD);
+    if crc.finish() != etag {
+        return Ok(None);
+    }
+
+    let mut used = word(CREATED_WORD);
+    let mut touches = 0;
+    while touches < TOUCHES && word(TOUCH_WORDS + touches) != ERASED {
+        used = word(TOUCH_WORDS + touches);
+        touches += 1;
+    }
+    Ok(Some(Entry {
+        url,
+        page,
+        pages,
+        len,
+        etag,
+        used,
+        touches,
+    }))
+}
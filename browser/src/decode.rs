@@ -0,0 +1,464 @@
+//! Decoding a page while it downloads.
+//!
+//! A page payload holds the title and the links, then the SWB program: the
+//! length of the text as a `u64`, the text, and finally the instructions of
+//! nine bytes each. [`PageDecoder`] is fed the payload piece by piece and has
+//! a [`Page`] to show as soon as the text is in; instructions are added to it
+//! as they arrive, so the first screen can be drawn long before the last
+//! instruction is received.
+//!
+//! Only a [`Window`] of the page is kept, as much as fits in the budget the
+//! decoder was created with: the start of the text, and the instructions
+//! from the first on for as long as the text they refer to was kept. The
+//! rest of the payload is only counted as it goes by. Other parts of the page
+//! are loaded later with [`PageDecoder::seek`], by asking for the ranges of
+//! the payload they are in.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ops::Range;
+
+use protocol::page::{decode_header, Link, PageHeader};
+use protocol::Crc32;
+use swb_shared::{Instruction, Program};
+
+use crate::page::{check, text_range, Page, PageError, Window};
+
+/// Size of an encoded instruction.
+const INSTRUCTION_LEN: usize = 9;
+/// Size of the length in front of the text.
+const TEXT_LEN_LEN: usize = 8;
+
+enum State {
+    /// Waiting for the links and program length.
+    Header,
+    /// Waiting for the length of the text.
+    TextLen,
+    /// Collecting the text.
+    Text,
+    /// Passing over the text that does not fit in the window.
+    SkipText { left: usize },
+    /// Decoding instructions.
+    Code,
+}
+
+/// A part of the page being loaded, see [`PageDecoder::seek`].
+enum Loading {
+    /// Waiting for `count` instructions from the first of the window on.
+    Code { count: u32 },
+    /// Waiting for `len` bytes of text from `base` on, which `code` refers
+    /// to.
+    Text {
+        code: Vec<Instruction>,
+        base: u32,
+        len: u32,
+    },
+}
+
+impl Loading {
+    /// Where the part being loaded is in the payload of the page `window`
+    /// belongs to, if that fits in a `u32`.
+    fn range(&self, window: &Window) -> Option<Range<u32>> {
+        let text_offset = window.program_offset.checked_add(TEXT_LEN_LEN as u32)?;
+        let (start, len) = match self {
+            Loading::Code { count } => {
+                let skipped = window.first.checked_mul(INSTRUCTION_LEN as u32)?;
+                let start = text_offset.checked_add(window.text_len)?.checked_add(skipped)?;
+                (start, count.checked_mul(INSTRUCTION_LEN as u32)?)
+            }
+            Loading::Text { base, len, .. } => (text_offset.checked_add(*base)?, *len),
+        };
+        Some(start..start.checked_add(len)?)
+    }
+}
+
+pub struct PageDecoder {
+    max_len: usize,
+    state: State,
+    /// Bytes received but not decoded yet.
+    pending: Vec<u8>,
+    /// Length of the whole program and the bytes of it still to come.
+    program_len: usize,
+    program_left: usize,
+    text_len: usize,
+    page: Option<Page>,
+    header: Option<PageHeader>,
+    /// Length of the header in front of the program.
+    header_len: usize,
+    /// Instructions of the page decoded so far.
+    decoded: u32,
+    /// Whether an instruction did not fit in the window, so none after it
+    /// is kept either.
+    window_full: bool,
+    /// Bytes of text and instructions a window may hold.
+    budget: usize,
+    crc: Crc32,
+    loading: Option<Loading>,
+}
+
+/// Heap taken by the title and links of a page.
+fn header_size(title: &str, links: &[Link]) -> usize {
+    title.len()
+        + links
+            .iter()
+            .map(|link| size_of::<Link>() + link.href.len())
+            .sum::<usize>()
+}
+
+impl PageDecoder {
+    /// Creates a decoder that keeps no more than about `max_len` bytes of
+    /// the page in memory.
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            state: State::Header,
+            pending: Vec::new(),
+            program_len: 0,
+            program_left: 0,
+            text_len: 0,
+            page: None,
+            header: None,
+            header_len: 0,
+            decoded: 0,
+            window_full: false,
+            budget: 0,
+            crc: Crc32::new(),
+            loading: None,
+        }
+    }
+
+    /// A decoder that is done already, for pages that were kept in memory.
+    /// Other parts of the page are loaded within `max_len`, like
+    /// [`PageDecoder::new`].
+    pub fn from_page(page: Page, max_len: usize) -> Self {
+        let mut decoder = Self::new(max_len);
+        decoder.set_budget(header_size(&page.title, &page.links));
+        decoder.state = State::Code;
+        decoder.text_len = page.window.text_len as usize;
+        decoder.decoded = page.window.instrs;
+        decoder.page = Some(page);
+        decoder
+    }
+
+    /// Leaves what is left of `max_len` next to a header of `header_size`
+    /// bytes to the windows.
+    fn set_budget(&mut self, header_size: usize) {
+        self.budget = self.max_len.saturating_sub(header_size);
+    }
+
+    /// Instructions a window holds at most, so there is room for text.
+    fn max_instrs(&self) -> usize {
+        self.budget / 2 / size_of::<Instruction>()
+    }
+
+    /// The part of the page decoded so far, once the text of its window is
+    /// in.
+    pub fn page(&self) -> Option<&Page> {
+        self.page.as_ref()
+    }
+
+    /// Feeds the next bytes of the page payload.
+    pub fn push(&mut self, data: &[u8]) -> Result<(), PageError> {
+        self.crc.update(data);
+        self.pending.extend_from_slice(data);
+        loop {
+            match self.state {
+                State::Header => {
+                    let (header, header_len) = match decode_header(&self.pending)
+                        .map_err(|err| PageError::Parse(err.to_string()))?
+                    {
+                        Some(header) => header,
+                        None => return Ok(()),
+                    };
+                    let header_size = header_size(&header.title, &header.links);
+                    // Every offset in the payload has to fit in a `u32`.
+                    let fits = header_len
+                        .checked_add(header.program_len)
+                        .is_some_and(|len| len <= u32::MAX as usize);
+                    if header_size >= self.max_len || !fits {
+                        return Err(PageError::Parse("page too large".to_string()));
+                    }
+                    self.set_budget(header_size);
+                    self.pending.drain(..header_len);
+                    self.header_len = header_len;
+                    self.program_len = header.program_len;
+                    self.program_left = header.program_len;
+                    self.header = Some(header);
+                    self.state = State::TextLen;
+                }
+                State::TextLen => {
+                    let Some(bytes) = self.take(TEXT_LEN_LEN)? else {
+                        return Ok(());
+                    };
+                    let mut len = [0; TEXT_LEN_LEN];
+                    len.copy_from_slice(&bytes);
+                    let len = u64::from_le_bytes(len);
+                    if len > self.program_left as u64 {
+                        return Err(PageError::Parse("text longer than the program".to_string()));
+                    }
+                    self.text_len = len as usize;
+                    // Half of the budget, the instructions are still to come.
+                    let kept = self.text_len.min(self.budget / 2);
+                    self.pending.reserve(kept.saturating_sub(self.pending.len()));
+                    self.state = State::Text;
+                }
+                State::Text => {
+                    let kept = self.text_len.min(self.budget / 2);
+                    let Some(mut text) = self.take(kept)? else {
+                        return Ok(());
+                    };
+                    // The window may end in the middle of a character.
+                    if let Err(err) = core::str::from_utf8(&text) {
+                        if err.error_len().is_some() || kept == self.text_len {
+                            return Err(PageError::Parse("text is not UTF-8".to_string()));
+                        }
+                        text.truncate(err.valid_up_to());
+                    }
+                    let text = String::from_utf8(text)
+                        .map_err(|_| PageError::Parse("text is not UTF-8".to_string()))?;
+                    let program = Program {
+                        code: Vec::new(),
+                        text,
+                    };
+                    let header = self.header.take().unwrap();
+                    let instrs = (self.program_left - (self.text_len - kept)) / INSTRUCTION_LEN;
+                    let window = Window {
+                        first: 0,
+                        text_base: 0,
+                        instrs: instrs as u32,
+                        text_len: self.text_len as u32,
+                        program_offset: self.header_len as u32,
+                        etag: 0,
+                    };
+                    self.page = Some(Page {
+                        program,
+                        links: header.links,
+                        title: header.title,
+                        window,
+                    });
+                    self.state = State::SkipText {
+                        left: self.text_len - kept,
+                    };
+                }
+                State::SkipText { left } => {
+                    let skipped = left.min(self.pending.len());
+                    self.pending.drain(..skipped);
+                    self.program_left -= skipped;
+                    if skipped < left {
+                        self.state = State::SkipText { left: left - skipped };
+                        return Ok(());
+                    }
+                    self.state = State::Code;
+                }
+                State::Code => {
+                    if self.pending.len() > self.program_left {
+                        return Err(PageError::Parse("data past the end of the program".to_string()));
+                    }
+                    let complete = self.pending.len() - self.pending.len() % INSTRUCTION_LEN;
+                    if complete == 0 {
+                        if self.program_left == 0 {
+                            self.page.as_mut().unwrap().window.etag = self.crc.finish();
+                        }
+                        return Ok(());
+                    }
+                    let Some(code) = self.take(complete)? else {
+                        return Ok(());
+                    };
+                    self.decode(&code)?;
+                }
+            }
+        }
+    }
+
+    /// How much of the page arrived, in percent.
+    pub fn progress(&self) -> u8 {
+        if matches!(self.state, State::Header) {
+            return 0;
+        }
+        if self.program_len == 0 {
+            return 100;
+        }
+        let received = (self.program_len - self.program_left + self.pending.len()).min(self.program_len);
+        (received * 100 / self.program_len) as u8
+    }
+
+    /// Whether the whole page has been decoded.
+    pub fn is_complete(&self) -> bool {
+        matches!(self.state, State::Code) && self.program_left == 0
+    }
+
+    /// Checks that the whole page arrived and returns it.
+    pub fn finish(self) -> Result<Page, PageError> {
+        let complete = self.is_complete() && self.loading.is_none();
+        match self.page {
+            Some(page) if complete => Ok(page),
+            _ => Err(PageError::Parse("truncated page".to_string())),
+        }
+    }
+
+    /// Removes `len` bytes of the program from the pending ones, if they are
+    /// there yet.
+    fn take(&mut self, len: usize) -> Result<Option<Vec<u8>>, PageError> {
+        if len > self.program_left {
+            return Err(PageError::Parse("truncated program".to_string()));
+        }
+        if self.pending.len() < len {
+            return Ok(None);
+        }
+        let rest = self.pending.split_off(len);
+        self.program_left -= len;
+        Ok(Some(core::mem::replace(&mut self.pending, rest)))
+    }
+
+    /// Parses encoded instructions.
+    fn parse_code(code: &[u8]) -> Result<Vec<Instruction>, PageError> {
+        // Let swb-shared do the decoding by handing it a program without any
+        // text, which is kept in the page already.
+        let mut swb = Vec::with_capacity(TEXT_LEN_LEN + code.len());
+        swb.extend_from_slice(&0u64.to_le_bytes());
+        swb.extend_from_slice(code);
+        let decoded = Program::try_from(swb.as_slice())
+            .map_err(|swb_shared::Error(err)| PageError::Parse(err.to_string()))?;
+        Ok(decoded.code)
+    }
+
+    /// Appends encoded instructions to the window, as long as they fit.
+    fn decode(&mut self, code: &[u8]) -> Result<(), PageError> {
+        let max_instrs = self.max_instrs();
+        let page = self.page.as_mut().unwrap();
+        for instr in Self::parse_code(code)? {
+            let index = self.decoded as usize;
+            self.decoded += 1;
+            let held = check(
+                &page.program.text,
+                page.window.text_base,
+                self.text_len,
+                index,
+                &instr,
+            )?;
+            if self.window_full {
+                continue;
+            }
+            if held && page.program.code.len() < max_instrs {
+                page.program.code.push(instr);
+            } else {
+                self.window_full = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the window of the page and starts loading the one from
+    /// instruction `first` on. Only done once the whole page went by.
+    ///
+    /// The new window arrives in two parts, the instructions and then the
+    /// text they refer to. [`PageDecoder::wanted`] tells what part of the
+    /// payload to [push](PageDecoder::push_range) next.
+    pub fn seek(&mut self, first: u32) {
+        if !self.is_complete() {
+            return;
+        }
+        let max_instrs = self.max_instrs().max(1) as u32;
+        let Some(page) = self.page.as_mut() else {
+            return;
+        };
+        let first = first.min(page.window.instrs);
+        let count = (page.window.instrs - first).min(max_instrs);
+        page.program.code = Vec::new();
+        page.program.text = String::new();
+        page.window.first = first;
+        page.window.text_base = 0;
+        self.pending = Vec::new();
+        self.loading = (count > 0).then_some(Loading::Code { count });
+    }
+
+    /// The range of the page payload to push next to load the window
+    /// asked for with [`PageDecoder::seek`]. Fails if the range does not
+    /// fit in a page payload.
+    pub fn wanted(&self) -> Result<Option<Range<u32>>, PageError> {
+        let (Some(page), Some(loading)) = (&self.page, &self.loading) else {
+            return Ok(None);
+        };
+        let range = loading
+            .range(&page.window)
+            .ok_or_else(|| PageError::Parse("range past the end of the payload".to_string()))?;
+        Ok(Some(range.start + self.pending.len() as u32..range.end))
+    }
+
+    /// Feeds the next bytes of the range [wanted](PageDecoder::wanted).
+    pub fn push_range(&mut self, data: &[u8]) -> Result<(), PageError> {
+        let Some(wanted) = self.wanted()? else {
+            return Err(PageError::Parse("no part of the page was asked for".to_string()));
+        };
+        if data.len() > wanted.len() {
+            return Err(PageError::Parse("data past the range asked for".to_string()));
+        }
+        self.pending.extend_from_slice(data);
+        if data.len() < wanted.len() {
+            return Ok(());
+        }
+        let data = core::mem::take(&mut self.pending);
+        match self.loading.take() {
+            Some(Loading::Code { .. }) => self.loaded_code(&data),
+            Some(Loading::Text { code, base, .. }) => {
+                let text = String::from_utf8(data)
+                    .map_err(|_| PageError::Parse("text is not UTF-8".to_string()))?;
+                self.loaded(code, base, text)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Takes the instructions of a new window, as many of them as there is
+    /// room left for the text of.
+    fn loaded_code(&mut self, data: &[u8]) -> Result<(), PageError> {
+        let first = self.page.as_ref().unwrap().window.first;
+        let mut code = Self::parse_code(data)?;
+        let mut span: Option<Range<usize>> = None;
+        let mut kept = code.len();
+        for (i, instr) in code.iter().enumerate() {
+            check("", 0, self.text_len, first as usize + i, instr)?;
+            let Instruction::Text(address) = instr else {
+                continue;
+            };
+            let range = text_range(address).unwrap();
+            let grown = match &span {
+                Some(span) => span.start.min(range.start)..span.end.max(range.end),
+                None => range,
+            };
+            // The window always holds at least one instruction.
+            if i > 0 && grown.len() + (i + 1) * size_of::<Instruction>() > self.budget {
+                kept = i;
+                break;
+            }
+            span = Some(grown);
+        }
+        code.truncate(kept);
+        match span {
+            Some(span) => {
+                self.loading = Some(Loading::Text {
+                    code,
+                    base: span.start as u32,
+                    len: span.len() as u32,
+                });
+                Ok(())
+            }
+            None => self.loaded(code, 0, String::new()),
+        }
+    }
+
+    /// Puts a new window in place, once its text arrived.
+    fn loaded(&mut self, code: Vec<Instruction>, base: u32, text: String) -> Result<(), PageError> {
+        let page = self.page.as_mut().unwrap();
+        let first = page.window.first as usize;
+        for (i, instr) in code.iter().enumerate() {
+            if !check(&text, base, self.text_len, first + i, instr)? {
+                return Err(PageError::Address { index: first + i });
+            }
+        }
+        page.program = Program { code, text };
+        page.window.text_base = base;
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+//! A 1-bit framebuffer the size of the display, for rendering off-device.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::Infallible;
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+
+use crate::view::{HEIGHT, WIDTH};
+
+/// Pixels are stored row by row, eight to a byte with the leftmost pixel in
+/// the most significant bit. A set bit is a black pixel.
+#[derive(Clone, Eq, PartialEq)]
+pub struct Framebuffer {
+    bits: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub const STRIDE: usize = (WIDTH as usize + 7) / 8;
+
+    pub fn new() -> Self {
+        Self {
+            bits: vec![0; Self::STRIDE * HEIGHT as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        let byte = self.bits[y as usize * Self::STRIDE + x as usize / 8];
+        byte & (0x80 >> (x % 8)) != 0
+    }
+
+    /// The packed rows, as stored in a binary PBM image.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        Size::new(WIDTH, HEIGHT)
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Infallible>
+    where
+        I: IntoIterator<Item = Pixel<BinaryColor>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x < 0 || point.y < 0 || point.x >= WIDTH as i32 || point.y >= HEIGHT as i32 {
+                continue;
+            }
+            let index = point.y as usize * Self::STRIDE + point.x as usize / 8;
+            let mask = 0x80 >> (point.x % 8);
+            match color {
+                BinaryColor::On => self.bits[index] |= mask,
+                BinaryColor::Off => self.bits[index] &= !mask,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+//! Back/forward navigation history.
+//!
+//! Every entry remembers its URL and how far the user had scrolled. Entries
+//! may also hold on to the parsed page so going back is instant, but with only
+//! a small heap those bodies are dropped again, furthest from the current
+//! entry first, and re-fetched when needed.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::page::Page;
+
+/// Entries beyond this are forgotten, oldest first.
+pub const MAX_ENTRIES: usize = 16;
+
+struct Entry {
+    url: String,
+    start_line: i32,
+    page: Option<Page>,
+}
+
+pub struct History {
+    entries: Vec<Entry>,
+    current: usize,
+    /// Heap budget for the bodies of pages other than the current one.
+    body_budget: usize,
+}
+
+impl History {
+    pub fn new(url: &str, body_budget: usize) -> Self {
+        Self {
+            entries: alloc::vec![Entry {
+                url: String::from(url),
+                start_line: 0,
+                page: None,
+            }],
+            current: 0,
+            body_budget,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.entries[self.current].url
+    }
+
+    pub fn start_line(&self) -> i32 {
+        self.entries[self.current].start_line
+    }
+
+    /// Sets how far the current page is scrolled, for a history restored
+    /// after a reset.
+    pub fn set_start_line(&mut self, start_line: i32) {
+        self.entries[self.current].start_line = start_line;
+    }
+
+    /// Takes the cached body of the current entry, if it was kept.
+    pub fn take_page(&mut self) -> Option<Page> {
+        self.entries[self.current].page.take()
+    }
+
+    /// Records the state of the current page as the user navigates away.
+    pub fn leave(&mut self, page: Page, start_line: i32) {
+        let entry = &mut self.entries[self.current];
+        entry.start_line = start_line;
+        entry.page = Some(page);
+    }
+
+    /// Navigates to a new page, discarding anything forward of the current
+    /// entry.
+    pub fn push(&mut self, url: String) {
+        self.entries.truncate(self.current + 1);
+        self.entries.push(Entry {
+            url,
+            start_line: 0,
+            page: None,
+        });
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.current = self.entries.len() - 1;
+        self.evict();
+    }
+
+    /// Returns false if there is nothing to go back to.
+    pub fn back(&mut self) -> bool {
+        if self.current == 0 {
+            return false;
+        }
+        self.current -= 1;
+        self.evict();
+        true
+    }
+
+    /// Returns false if there is nothing to go forward to.
+    pub fn forward(&mut self) -> bool {
+        if self.current + 1 >= self.entries.len() {
+            return false;
+        }
+        self.current += 1;
+        self.evict();
+        true
+    }
+
+    /// Drops page bodies until the ones kept fit in the body budget.
+    fn evict(&mut self) {
+        loop {
+            let current = self.current;
+            let kept = self
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != current)
+                .filter_map(|(_, entry)| entry.page.as_ref())
+                .map(Page::heap_size)
+                .sum::<usize>();
+            if kept <= self.body_budget {
+                return;
+            }
+            let furthest = self
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(i, entry)| *i != current && entry.page.is_some())
+                .max_by_key(|(i, _)| i.abs_diff(current))
+                .map(|(i, _)| i);
+            match furthest {
+                Some(i) => self.entries[i].page = None,
+                None => return,
+            }
+        }
+    }
+}
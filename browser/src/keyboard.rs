@@ -0,0 +1,161 @@
+//! Decoding of the BlackBerry Q10 keyboard as driven by the BBQ10
+//! keyboard firmware.
+//!
+//! The firmware reports every key as a single byte. Printable keys arrive as
+//! ASCII, everything else uses the control codes below. When its "use
+//! modifiers" option is enabled the firmware already applies shift and alt
+//! itself; when modifiers are reported as keys instead, [`Keymap`] keeps track
+//! of them and applies them here.
+
+/// Trackpad directions and click.
+pub const JOY_UP: u8 = 0x01;
+pub const JOY_DOWN: u8 = 0x02;
+pub const JOY_LEFT: u8 = 0x03;
+pub const JOY_RIGHT: u8 = 0x04;
+pub const JOY_CENTER: u8 = 0x05;
+/// The four buttons around the display.
+pub const BTN_LEFT1: u8 = 0x06;
+pub const BTN_RIGHT1: u8 = 0x07;
+pub const BACKSPACE: u8 = 0x08;
+pub const TAB: u8 = 0x09;
+pub const ENTER: u8 = 0x0a;
+pub const CARRIAGE_RETURN: u8 = 0x0d;
+pub const BTN_LEFT2: u8 = 0x11;
+pub const BTN_RIGHT2: u8 = 0x12;
+pub const MOD_ALT: u8 = 0x1a;
+pub const MOD_SHIFT_LEFT: u8 = 0x1b;
+pub const MOD_SHIFT_RIGHT: u8 = 0x1c;
+pub const MOD_SYM: u8 = 0x1d;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum KeyState {
+    Pressed,
+    /// Repeatedly reported while a key stays down.
+    Held,
+    Released,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Keycode {
+    /// A printable character, including space.
+    Char(char),
+    Enter,
+    Backspace,
+    Tab,
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Pressing the trackpad.
+    Select,
+    /// One of the buttons around the display, numbered left to right, top row
+    /// first.
+    Button(u8),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct KeyEvent {
+    pub state: KeyState,
+    pub code: Keycode,
+}
+
+/// Symbols printed on the keys, typed while alt is down.
+pub fn alt_symbol(c: char) -> Option<char> {
+    let symbol = match c {
+        'q' => '#',
+        'w' => '1',
+        'e' => '2',
+        'r' => '3',
+        't' => '(',
+        'y' => ')',
+        'u' => '_',
+        'i' => '-',
+        'o' => '+',
+        'p' => '@',
+        'a' => '*',
+        's' => '4',
+        'd' => '5',
+        'f' => '6',
+        'g' => '/',
+        'h' => ':',
+        'j' => ';',
+        'k' => '\'',
+        'l' => '"',
+        'z' => '7',
+        'x' => '8',
+        'c' => '9',
+        'v' => '?',
+        'b' => '!',
+        'n' => ',',
+        'm' => '.',
+        '$' => '`',
+        // The microphone key.
+        '~' => '0',
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+/// Decodes a key code without any modifiers applied. Modifier keys and
+/// unknown codes yield `None`.
+pub fn decode(value: u8) -> Option<Keycode> {
+    let code = match value {
+        JOY_UP => Keycode::Up,
+        JOY_DOWN => Keycode::Down,
+        JOY_LEFT => Keycode::Left,
+        JOY_RIGHT => Keycode::Right,
+        JOY_CENTER => Keycode::Select,
+        BTN_LEFT1 => Keycode::Button(0),
+        BTN_RIGHT1 => Keycode::Button(1),
+        BTN_LEFT2 => Keycode::Button(2),
+        BTN_RIGHT2 => Keycode::Button(3),
+        BACKSPACE => Keycode::Backspace,
+        TAB => Keycode::Tab,
+        ENTER | CARRIAGE_RETURN => Keycode::Enter,
+        b' '..=b'~' => Keycode::Char(value as char),
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Turns raw key reports into key events, applying shift and alt.
+#[derive(Debug, Default)]
+pub struct Keymap {
+    alt: bool,
+    shift_left: bool,
+    shift_right: bool,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, state: KeyState, value: u8) -> Option<KeyEvent> {
+        let down = state != KeyState::Released;
+        match value {
+            MOD_ALT => {
+                self.alt = down;
+                return None;
+            }
+            MOD_SHIFT_LEFT => {
+                self.shift_left = down;
+                return None;
+            }
+            MOD_SHIFT_RIGHT => {
+                self.shift_right = down;
+                return None;
+            }
+            _ => {}
+        }
+
+        let code = match decode(value)? {
+            Keycode::Char(c) if self.alt => Keycode::Char(alt_symbol(c).unwrap_or(c)),
+            Keycode::Char(c) if self.shift_left || self.shift_right => {
+                Keycode::Char(c.to_ascii_uppercase())
+            }
+            code => code,
+        };
+        Some(KeyEvent { state, code })
+    }
+}
@@ -0,0 +1,30 @@
+//! Hardware independent parts of the bambi browser client.
+//!
+//! The firmware in `client/` only runs on the nRF52840, everything in here
+//! builds for the host as well so it can be tested there and driven by the
+//! simulator. Drawing goes through `embedded-graphics`, so the same code
+//! renders to the Sharp display and to an in-memory [`framebuffer`].
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod address;
+pub mod cache;
+pub mod decode;
+pub mod framebuffer;
+pub mod history;
+pub mod keyboard;
+pub mod lines;
+pub mod page;
+pub mod pairing;
+pub mod servers;
+pub mod settings;
+pub mod status;
+pub mod style;
+pub mod view;
+pub mod wrap;
+
+/// Size of the heap on the device, everything the browser keeps in memory
+/// has to fit in here.
+pub const HEAP_SIZE: usize = 32000;
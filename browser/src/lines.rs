@@ -0,0 +1,214 @@
+//! Index of the visual lines of a page.
+//!
+//! Finding line `n` of a page means wrapping every text run before it, which
+//! gets slow on long pages when done on every key press. The [`LineIndex`]
+//! does that once and remembers where every sixteenth line starts, with the
+//! styles in effect there, so getting to any line only takes a few steps.
+//! Remembering every line would take more heap than the page itself.
+//!
+//! Only the instructions in the [window](crate::page::Window) of a page can be
+//! indexed. Since lines can only be laid out from a point where the styles
+//! are known, the checkpoints are also where other windows of a long page
+//! are loaded from, see [`LineIndex::start_of`].
+
+use alloc::vec::Vec;
+
+use swb_shared::Instruction;
+
+use crate::page::Page;
+use crate::style::{Styles, FONT};
+use crate::view::columns;
+use crate::wrap::wrap;
+
+/// Lines from one checkpoint to the next.
+const CHECKPOINT_EVERY: usize = 16;
+
+/// Where a visual line starts.
+#[derive(Copy, Clone)]
+pub struct LineStart {
+    /// Index of the `Text` or `Endl` instruction the line belongs to.
+    pub instr: u32,
+    /// Which of the wrapped lines of a `Text` instruction this is.
+    pub wrapped: u32,
+    /// Styles in effect at the instruction.
+    pub styles: Styles,
+}
+
+impl LineStart {
+    /// Height of the line in pixels, without the spacing below it.
+    pub fn height(&self, page: &Page) -> u32 {
+        match page.instr(self.instr) {
+            // Links are underlined, but set in the same font.
+            Some(Instruction::Text(_)) => self.styles.run(None).character_size().height,
+            _ => FONT.character_size.height,
+        }
+    }
+}
+
+/// Number of visual lines `instr` takes up with `styles` in effect.
+fn lines_in(page: &Page, instr: &Instruction, styles: &Styles) -> u32 {
+    match instr {
+        Instruction::Text(address) => {
+            let width = styles.run(None).character_size().width;
+            let count = wrap(page.text(address), columns(width)).count();
+            u32::try_from(count).unwrap_or(u32::MAX)
+        }
+        Instruction::Endl => 1,
+        _ => 0,
+    }
+}
+
+/// The lines of one page, built up as its instructions arrive.
+pub struct LineIndex {
+    /// Start of every `CHECKPOINT_EVERY`th line.
+    checkpoints: Vec<LineStart>,
+    /// Number of lines indexed.
+    len: usize,
+    /// First instruction not indexed yet.
+    next: usize,
+    styles: Styles,
+    /// Whether a `Stop` was reached, nothing after it is shown.
+    stopped: bool,
+}
+
+impl LineIndex {
+    pub fn new() -> Self {
+        Self {
+            checkpoints: Vec::new(),
+            len: 0,
+            next: 0,
+            styles: Styles::new(),
+            stopped: false,
+        }
+    }
+
+    /// Indexes the instructions added to `page` since the last update.
+    pub fn update(&mut self, page: &Page) {
+        if self.stopped {
+            return;
+        }
+        while let Some(instr) = page.instr(self.next as u32) {
+            let index = self.next as u32;
+            self.next += 1;
+            let count = lines_in(page, instr, &self.styles);
+            for wrapped in 0..count {
+                if self.checkpoints.len() * CHECKPOINT_EVERY == self.len {
+                    self.checkpoints.push(LineStart {
+                        instr: index,
+                        wrapped,
+                        styles: self.styles,
+                    });
+                }
+                self.len += 1;
+            }
+            match instr {
+                Instruction::Push(var) => self.styles.push(var),
+                Instruction::Pop(var) => self.styles.pop(var),
+                Instruction::Stop => {
+                    self.stopped = true;
+                    return;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Number of lines indexed so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether all lines of `page` are indexed.
+    pub fn is_complete(&self, page: &Page) -> bool {
+        self.stopped || self.next >= page.window.instrs as usize
+    }
+
+    /// The instruction to lay out `line` from: that of the checkpoint before
+    /// it, or of the last one if `line` was not indexed yet.
+    pub fn start_of(&self, line: usize) -> u32 {
+        self.checkpoints
+            .get(line.min(self.len) / CHECKPOINT_EVERY)
+            .or(self.checkpoints.last())
+            .map_or(0, |checkpoint| checkpoint.instr)
+    }
+
+    /// The indexed lines of `page` from `line` on.
+    pub fn lines<'a>(&self, page: &'a Page, line: usize) -> Lines<'a> {
+        let mut lines = Lines {
+            page,
+            instr: 0,
+            wrapped: 0,
+            count: None,
+            styles: Styles::new(),
+            remaining: 0,
+        };
+        if let Some(checkpoint) = self.checkpoints.get(line / CHECKPOINT_EVERY) {
+            let first = line / CHECKPOINT_EVERY * CHECKPOINT_EVERY;
+            lines.instr = checkpoint.instr as usize;
+            lines.wrapped = checkpoint.wrapped;
+            lines.styles = checkpoint.styles;
+            lines.remaining = self.len - first;
+            for _ in first..line.min(self.len) {
+                lines.next();
+            }
+        }
+        lines
+    }
+}
+
+impl Default for LineIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Iterator over the lines of a page, see [`LineIndex::lines`].
+pub struct Lines<'a> {
+    page: &'a Page,
+    instr: usize,
+    /// Next wrapped line of the current instruction.
+    wrapped: u32,
+    /// Number of lines of the current instruction, once known.
+    count: Option<u32>,
+    styles: Styles,
+    /// Lines left in the index.
+    remaining: usize,
+}
+
+impl Iterator for Lines<'_> {
+    type Item = LineStart;
+
+    fn next(&mut self) -> Option<LineStart> {
+        while self.remaining > 0 {
+            let instr = self.page.instr(self.instr as u32)?;
+            let count = match self.count {
+                Some(count) => count,
+                None => *self.count.insert(lines_in(self.page, instr, &self.styles)),
+            };
+            if self.wrapped < count {
+                let line = LineStart {
+                    instr: self.instr as u32,
+                    wrapped: self.wrapped,
+                    styles: self.styles,
+                };
+                self.wrapped += 1;
+                self.remaining -= 1;
+                return Some(line);
+            }
+            match instr {
+                Instruction::Push(var) => self.styles.push(var),
+                Instruction::Pop(var) => self.styles.pop(var),
+                Instruction::Stop => return None,
+                _ => {}
+            }
+            self.instr += 1;
+            self.wrapped = 0;
+            self.count = None;
+        }
+        None
+    }
+}
@@ -0,0 +1,178 @@
+//! A page as the browser keeps it in memory.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem::size_of;
+use core::ops::Range;
+
+use protocol::page::Link;
+use swb_shared::{Address, Instruction, Program};
+
+/// Why a page can't be shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PageError {
+    /// The SWB program could not be parsed.
+    Parse(String),
+    /// Instruction `index` refers to text past the end of the page, or splits
+    /// a character.
+    Address { index: usize },
+}
+
+impl fmt::Display for PageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageError::Parse(reason) => write!(f, "invalid page: {}", reason),
+            PageError::Address { index } => {
+                write!(f, "instruction {} refers to text outside the page", index)
+            }
+        }
+    }
+}
+
+/// Which part of a page is held in memory.
+///
+/// Long pages do not fit in the heap, so only a window of consecutive
+/// instructions is kept, along with the part of the text they refer to. The
+/// rest is fetched again when the user scrolls to it, see
+/// [`PageDecoder::seek`](crate::decode::PageDecoder::seek). Instructions keep
+/// their index in the whole page and text addresses stay relative to the
+/// whole text.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Window {
+    /// Index of the first instruction held.
+    pub first: u32,
+    /// Offset of the text held in the text of the whole page.
+    pub text_base: u32,
+    /// Number of instructions in the whole page.
+    pub instrs: u32,
+    /// Length of the text of the whole page.
+    pub text_len: u32,
+    /// Where the program starts in the page payload, to ask for parts of it.
+    pub program_offset: u32,
+    /// The [`etag`](protocol::etag) of the page payload.
+    pub etag: u32,
+}
+
+/// A parsed page together with its links and title.
+pub struct Page {
+    /// The instructions and text held, see [`Window`].
+    pub program: Program,
+    pub links: Vec<Link>,
+    /// Title from the HTML source, empty if the page has none.
+    pub title: String,
+    pub window: Window,
+}
+
+/// The byte range of the page text `address` refers to.
+pub(crate) fn text_range(address: &Address) -> Option<Range<usize>> {
+    let start = address.base.0 as usize;
+    let end = start.checked_add(address.range as usize)?;
+    Some(start..end)
+}
+
+/// The byte range of `text` that `address` refers to, if `text` starts at
+/// `base` in the text of the page.
+pub(crate) fn window_range(address: &Address, base: u32) -> Option<Range<usize>> {
+    let range = text_range(address)?;
+    let base = base as usize;
+    Some(range.start.checked_sub(base)?..range.end.checked_sub(base)?)
+}
+
+/// Checks that instruction `index` only refers to whole characters of the
+/// page text, which is `text_len` bytes long. Returns whether the text it
+/// refers to is in `text`, the part of the page text from `base` on.
+pub(crate) fn check(
+    text: &str,
+    base: u32,
+    text_len: usize,
+    index: usize,
+    instr: &Instruction,
+) -> Result<bool, PageError> {
+    let Instruction::Text(address) = instr else {
+        return Ok(true);
+    };
+    match text_range(address) {
+        Some(range) if range.end <= text_len => {}
+        _ => return Err(PageError::Address { index }),
+    }
+    match window_range(address, base) {
+        Some(range) if range.end <= text.len() => match text.get(range) {
+            Some(_) => Ok(true),
+            None => Err(PageError::Address { index }),
+        },
+        _ => Ok(false),
+    }
+}
+
+impl Page {
+    /// Parses an SWB program, see [`Page::new`].
+    pub fn parse(swb: &[u8], links: Vec<Link>) -> Result<Self, PageError> {
+        let program = Program::try_from(swb)
+            .map_err(|swb_shared::Error(err)| PageError::Parse(err.to_string()))?;
+        Self::new(program, links)
+    }
+
+    /// Checks that every text instruction of `program` refers to text that
+    /// is actually there. The page is held in memory as a whole.
+    pub fn new(program: Program, links: Vec<Link>) -> Result<Self, PageError> {
+        for (index, instr) in program.code.iter().enumerate() {
+            check(&program.text, 0, program.text.len(), index, instr)?;
+        }
+        let window = Window {
+            instrs: program.code.len() as u32,
+            text_len: program.text.len() as u32,
+            ..Window::default()
+        };
+        Ok(Self {
+            program,
+            links,
+            title: String::new(),
+            window,
+        })
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// The text an `Instruction::Text` refers to. Addresses are checked when
+    /// the page is created, anything else, and text outside the window, comes
+    /// up empty.
+    pub fn text(&self, address: &Address) -> &str {
+        window_range(address, self.window.text_base)
+            .and_then(|range| self.program.text.get(range))
+            .unwrap_or_default()
+    }
+
+    /// Instruction `index` of the page, if it is in the window.
+    pub fn instr(&self, index: u32) -> Option<&Instruction> {
+        let index = index.checked_sub(self.window.first)?;
+        self.program.code.get(index as usize)
+    }
+
+    /// Whether the window reaches the last instruction of the page.
+    pub fn holds_end(&self) -> bool {
+        self.window.first as usize + self.program.code.len() >= self.window.instrs as usize
+    }
+
+    /// Index of the link the text at `address` belongs to, if any.
+    pub fn link_at(&self, address: &Address) -> Option<usize> {
+        self.links
+            .iter()
+            .position(|link| link.covers(address.base.0 as u32, address.range as u32))
+    }
+
+    /// Approximate number of heap bytes held by this page.
+    pub fn heap_size(&self) -> usize {
+        self.program.text.len()
+            + self.title.len()
+            + self.program.code.len() * size_of::<Instruction>()
+            + self
+                .links
+                .iter()
+                .map(|link| size_of::<Link>() + link.href.len())
+                .sum::<usize>()
+    }
+}
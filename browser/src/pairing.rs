@@ -0,0 +1,159 @@
+//! Pairing with a server: the passkey prompt, and the keys kept afterwards.
+//!
+//! The link to the server is encrypted with LE Secure Connections. The first
+//! time the browser connects, the server shows a six digit passkey which the
+//! user types on the keyboard; should the server ask for one instead, the
+//! browser shows it. Both sides keep the key they agreed on, a [`Bond`], so
+//! later connections are encrypted without asking again.
+//!
+//! Secure Connections keys come without the EDIV and Rand that legacy keys
+//! are looked up by, they are always zero. Bonds are kept by the identity the
+//! server gave while bonding instead: its identity address, and the key that
+//! resolves the private addresses it may connect from.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+
+use crate::keyboard::Keycode;
+use crate::style::{Run, FONT, FONT_BOLD};
+use crate::view::{columns, MARGIN_LEFT, MARGIN_TOP, SPACING};
+
+/// Digits in a passkey.
+pub const PASSKEY_LEN: usize = 6;
+
+/// Servers the browser stays bonded with. Bonding with another one forgets
+/// the one bonded with longest ago.
+pub const MAX_BONDS: usize = 4;
+
+/// Bytes a bond takes in [`encode_bonds`].
+const BOND_LEN: usize = 1 + 6 + 16 + 16 + 1;
+
+/// The key agreed on with a server, and who the server is.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Bond {
+    /// Identity address of the server, as the SoftDevice reports it.
+    pub address_type: u8,
+    pub address: [u8; 6],
+    /// Identity resolution key of the server.
+    pub irk: [u8; 16],
+    pub ltk: [u8; 16],
+    /// Whether the key is authenticated and from LE Secure Connections, as
+    /// the SoftDevice reports it.
+    pub flags: u8,
+}
+
+/// Packs `bonds` to be kept in the settings.
+pub fn encode_bonds(bonds: &[Bond]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(bonds.len() * BOND_LEN);
+    for bond in bonds {
+        data.push(bond.address_type);
+        data.extend_from_slice(&bond.address);
+        data.extend_from_slice(&bond.irk);
+        data.extend_from_slice(&bond.ltk);
+        data.push(bond.flags);
+    }
+    data
+}
+
+/// Unpacks what [`encode_bonds`] packed. A bond cut short is dropped.
+pub fn decode_bonds(data: &[u8]) -> Vec<Bond> {
+    data.chunks_exact(BOND_LEN)
+        .map(|bond| Bond {
+            address_type: bond[0],
+            address: bond[1..7].try_into().unwrap(),
+            irk: bond[7..23].try_into().unwrap(),
+            ltk: bond[23..39].try_into().unwrap(),
+            flags: bond[39],
+        })
+        .take(MAX_BONDS)
+        .collect()
+}
+
+/// Puts `bond` first in `bonds`, replacing an older bond with the same
+/// server and forgetting the oldest one if there are too many.
+pub fn add_bond(bonds: &mut Vec<Bond>, bond: Bond) {
+    bonds.retain(|other| (other.address_type, other.address) != (bond.address_type, bond.address));
+    bonds.insert(0, bond);
+    bonds.truncate(MAX_BONDS);
+}
+
+/// Applies one key press to the passkey being typed. Returns true once the
+/// user submits a complete one.
+pub fn edit_passkey(passkey: &mut String, code: Keycode) -> bool {
+    match code {
+        Keycode::Enter | Keycode::Select => return passkey.len() == PASSKEY_LEN,
+        Keycode::Backspace => {
+            passkey.pop();
+        }
+        Keycode::Char(c) if c.is_ascii_digit() && passkey.len() < PASSKEY_LEN => passkey.push(c),
+        _ => {}
+    }
+    false
+}
+
+/// The passkey typed as six digits, to hand to the SoftDevice.
+pub fn passkey_digits(passkey: &str) -> Option<[u8; PASSKEY_LEN]> {
+    passkey.as_bytes().try_into().ok()
+}
+
+fn draw_lines<D>(lines: &[(bool, &str)], target: &mut D) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let plain = Run::plain(FONT);
+    let bold = Run::plain(FONT_BOLD);
+    let size = FONT.character_size;
+    let visible = columns(size.width);
+    let mut y = MARGIN_TOP;
+    for &(is_bold, text) in lines {
+        let text: String = text.chars().take(visible).collect();
+        let run = if is_bold { &bold } else { &plain };
+        run.draw(&text, Point::new(MARGIN_LEFT, y), target)?;
+        y += (size.height + SPACING) as i32;
+    }
+    Ok(())
+}
+
+/// Draws the prompt for the passkey the server at `server` shows, with the
+/// digits typed so far. The target is expected to be clear.
+pub fn draw_entry<D>(server: &str, passkey: &str, target: &mut D) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let pairing = format!("Pairing with {}", server);
+    let mut typed = String::from(passkey);
+    while typed.len() < PASSKEY_LEN {
+        typed.push('_');
+    }
+    let lines = [
+        (true, pairing.as_str()),
+        (false, ""),
+        (false, "Type the passkey the server shows:"),
+        (true, typed.as_str()),
+        (false, ""),
+        (false, "enter: pair"),
+    ];
+    draw_lines(&lines, target)
+}
+
+/// Draws `passkey` for the user to type on the server at `server`. The
+/// target is expected to be clear.
+pub fn draw_passkey<D>(server: &str, passkey: &str, target: &mut D) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let pairing = format!("Pairing with {}", server);
+    let lines = [
+        (true, pairing.as_str()),
+        (false, ""),
+        (false, "Type this passkey on the server:"),
+        (true, passkey),
+        (false, ""),
+        (false, "any key: done"),
+    ];
+    draw_lines(&lines, target)
+}
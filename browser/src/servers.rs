@@ -0,0 +1,201 @@
+//! Choosing the server to connect to.
+//!
+//! The firmware scans for devices advertising the page service and feeds
+//! every advertising report to a [`Picker`], which keeps the list of servers
+//! found so far and which one the user has selected. Reports are parsed here
+//! so the picker can be tested on the host.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+
+use crate::keyboard::Keycode;
+use crate::style::{Run, FONT, FONT_BOLD};
+use crate::view::{columns, MARGIN_LEFT, MARGIN_TOP, SPACING};
+
+/// 16-bit UUID of the page service.
+pub const PAGE_SERVICE: u16 = 0xfeed;
+
+/// Most servers listed, more than fit on the display are never found anyway.
+pub const MAX_SERVERS: usize = 10;
+
+/// Bluetooth base UUID, least significant byte first, with the 16-bit UUID
+/// going into bytes 12 and 13.
+const BASE_UUID: [u8; 16] = [
+    0xfb, 0x34, 0x9b, 0x5f, 0x80, 0x00, 0x00, 0x80, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Advertising data types, see the Bluetooth Core Specification Supplement.
+const AD_UUIDS_16_INCOMPLETE: u8 = 0x02;
+const AD_UUIDS_16_COMPLETE: u8 = 0x03;
+const AD_UUIDS_128_INCOMPLETE: u8 = 0x06;
+const AD_UUIDS_128_COMPLETE: u8 = 0x07;
+const AD_NAME_SHORT: u8 = 0x08;
+const AD_NAME_COMPLETE: u8 = 0x09;
+
+/// Iterates over the `(type, data)` structures in advertising data, stopping
+/// at the first malformed one.
+fn structures(mut data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    core::iter::from_fn(move || {
+        let (&len, rest) = data.split_first()?;
+        let len = len as usize;
+        if len == 0 || len > rest.len() {
+            return None;
+        }
+        let (structure, rest) = rest.split_at(len);
+        data = rest;
+        Some((structure[0], &structure[1..]))
+    })
+}
+
+/// Whether advertising data lists the service with 16-bit UUID `uuid`, in
+/// its short or its full form.
+pub fn advertises_service(data: &[u8], uuid: u16) -> bool {
+    let mut full = BASE_UUID;
+    full[12..14].copy_from_slice(&uuid.to_le_bytes());
+    structures(data).any(|(kind, uuids)| match kind {
+        AD_UUIDS_16_INCOMPLETE | AD_UUIDS_16_COMPLETE => {
+            uuids.chunks_exact(2).any(|short| short == uuid.to_le_bytes())
+        }
+        AD_UUIDS_128_INCOMPLETE | AD_UUIDS_128_COMPLETE => {
+            uuids.chunks_exact(16).any(|long| long == full)
+        }
+        _ => false,
+    })
+}
+
+/// The local name in advertising data, if it has a readable one.
+pub fn local_name(data: &[u8]) -> Option<&str> {
+    structures(data)
+        .filter(|&(kind, _)| kind == AD_NAME_SHORT || kind == AD_NAME_COMPLETE)
+        .find_map(|(_, name)| core::str::from_utf8(name).ok())
+}
+
+/// Writes a Bluetooth address the way people read it: most significant byte
+/// first. `address` is least significant byte first, as it goes over the air.
+pub fn format_address(address: &[u8; 6]) -> String {
+    let bytes: Vec<String> = address.iter().rev().map(|b| format!("{:02X}", b)).collect();
+    bytes.join(":")
+}
+
+/// A device found advertising the page service.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Server {
+    /// Least significant byte first.
+    pub address: [u8; 6],
+    /// Type of `address` as reported by the controller: public, random
+    /// static and so on.
+    pub address_type: u8,
+    pub name: Option<String>,
+    /// Signal strength of the last report, in dBm.
+    pub rssi: i8,
+}
+
+/// The list of servers found while scanning, with one of them selected.
+#[derive(Debug, Default)]
+pub struct Picker {
+    servers: Vec<Server>,
+    selected: usize,
+    /// Address of the server used before, selected as soon as it shows up.
+    current: Option<[u8; 6]>,
+}
+
+impl Picker {
+    pub fn new(current: Option<[u8; 6]>) -> Self {
+        Self {
+            current,
+            ..Self::default()
+        }
+    }
+
+    pub fn servers(&self) -> &[Server] {
+        &self.servers
+    }
+
+    pub fn selected(&self) -> Option<&Server> {
+        self.servers.get(self.selected)
+    }
+
+    /// Takes in an advertising report with data `data`. Devices that do not
+    /// advertise the page service are ignored, but the scan response of one
+    /// that does can still name it. Returns whether the list changed.
+    pub fn report(&mut self, address: [u8; 6], address_type: u8, rssi: i8, data: &[u8]) -> bool {
+        let name = local_name(data).map(String::from);
+        if let Some(server) = self.servers.iter_mut().find(|s| s.address == address) {
+            server.rssi = rssi;
+            if name.is_some() && server.name != name {
+                server.name = name;
+                return true;
+            }
+            return false;
+        }
+        if !advertises_service(data, PAGE_SERVICE) || self.servers.len() == MAX_SERVERS {
+            return false;
+        }
+        self.servers.push(Server {
+            address,
+            address_type,
+            name,
+            rssi,
+        });
+        if self.current == Some(address) {
+            self.selected = self.servers.len() - 1;
+        }
+        true
+    }
+
+    /// Applies one key press. Returns the server once the user picks it.
+    pub fn press(&mut self, code: Keycode) -> Option<Server> {
+        match code {
+            Keycode::Up | Keycode::Char('i') => self.selected = self.selected.saturating_sub(1),
+            Keycode::Down | Keycode::Char('k') => {
+                self.selected = (self.selected + 1).min(self.servers.len().saturating_sub(1))
+            }
+            Keycode::Enter | Keycode::Select | Keycode::Char('o') => {
+                return self.selected().cloned();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Draws the list of servers, the selected one in bold. The target is
+    /// expected to be clear.
+    pub fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let plain = Run::plain(FONT);
+        let bold = Run::plain(FONT_BOLD);
+        let size = FONT.character_size;
+        let visible = columns(size.width);
+        let mut y = MARGIN_TOP;
+        let mut line = |run: &Run, text: &str, target: &mut D| {
+            let text: String = text.chars().take(visible).collect();
+            let result = run.draw(&text, Point::new(MARGIN_LEFT, y), target);
+            y += (size.height + SPACING) as i32;
+            result
+        };
+
+        line(&bold, "Choose a server:", target)?;
+        for (i, server) in self.servers.iter().enumerate() {
+            let marker = if Some(server.address) == self.current { '*' } else { ' ' };
+            let text = format!(
+                "{} {}  {}  {} dBm",
+                marker,
+                format_address(&server.address),
+                server.name.as_deref().unwrap_or("?"),
+                server.rssi
+            );
+            line(if i == self.selected { &bold } else { &plain }, &text, target)?;
+        }
+        if self.servers.is_empty() {
+            line(&plain, "Scanning...", target)?;
+        }
+        line(&plain, "", target)?;
+        line(&plain, "i/k: select  o: connect", target)
+    }
+}
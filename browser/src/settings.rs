@@ -0,0 +1,323 @@
+//! A small key-value store for settings that have to survive a reset.
+//!
+//! The store takes a region of flash made of a few erasable pages, of which
+//! one is active at a time. Every change is appended to the active page as a
+//! record, so a key is only found by reading the page to its end: the last
+//! record for the key holds its value, or says it was removed. Once the page
+//! is full, the latest record for every key is copied to the next page,
+//! which then becomes the active one. The pages take turns, so they all wear
+//! at the same rate, and the old page stays valid until the copy is
+//! complete.
+//!
+//! Records are committed by writing a last word after them, so one that was
+//! cut short by a reset is skipped. A page is only taken into use once its
+//! header is written, after the copy.
+//!
+//! Flash is accessed through [`Flash`]. The firmware implements it with the
+//! SoftDevice flash API, the simulator over a file.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::future::Future;
+
+/// Flash can only be written in whole words.
+pub const WORD: u32 = 4;
+
+/// Longest key in bytes.
+pub const MAX_KEY_LEN: usize = 32;
+
+/// Marks the start of a page in use, followed by its sequence number.
+const MAGIC: u32 = 0x5445_5342;
+const PAGE_HEADER_LEN: u32 = 8;
+/// Value length of a record that removes its key.
+const REMOVED: u16 = 0xffff;
+/// Written after a record once it is complete.
+const COMMITTED: u32 = 0;
+
+/// A region of flash the store keeps its pages in. Offsets are relative to
+/// the start of the region.
+pub trait Flash {
+    type Error: core::fmt::Debug;
+    type WriteFuture<'a>: Future<Output = Result<(), Self::Error>>
+    where
+        Self: 'a;
+    type EraseFuture<'a>: Future<Output = Result<(), Self::Error>>
+    where
+        Self: 'a;
+
+    /// Size of the pages erased at once.
+    const PAGE_SIZE: u32;
+
+    /// Size of the region in bytes, a multiple of the page size.
+    fn size(&self) -> u32;
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Writes `data` at `offset`, both a multiple of [`WORD`] long. Writing
+    /// only clears bits, so the words written have to be erased first.
+    fn write<'a>(&'a mut self, offset: u32, data: &'a [u8]) -> Self::WriteFuture<'a>;
+
+    /// Erases the page at `offset` to all ones.
+    fn erase(&mut self, offset: u32) -> Self::EraseFuture<'_>;
+}
+
+#[derive(Debug)]
+pub enum StoreError<E> {
+    Flash(E),
+    /// The key is empty or longer than [`MAX_KEY_LEN`].
+    BadKey,
+    /// The settings would no longer fit in a page.
+    Full,
+}
+
+impl<E> From<E> for StoreError<E> {
+    fn from(err: E) -> Self {
+        StoreError::Flash(err)
+    }
+}
+
+/// A record read back from flash.
+struct Record {
+    key: Vec<u8>,
+    /// Offset and length of the value, `None` if the key was removed.
+    value: Option<(u32, usize)>,
+}
+
+/// Rounds `len` up to a whole number of words.
+fn words(len: usize) -> u32 {
+    (len as u32 + WORD - 1) / WORD * WORD
+}
+
+/// Space a record takes in a page: its header, the key and the value, and
+/// the commit word.
+fn record_len(key_len: usize, value_len: usize) -> u32 {
+    WORD + words(key_len + value_len) + WORD
+}
+
+pub struct Store<F: Flash> {
+    flash: F,
+    /// Active page.
+    page: u32,
+    sequence: u32,
+    /// Offset in the active page where the next record goes.
+    end: u32,
+}
+
+impl<F: Flash> Store<F> {
+    /// Opens the store in `flash`, which has to hold at least two pages.
+    /// Flash that does not hold a store yet is set up as an empty one.
+    pub async fn open(mut flash: F) -> Result<Self, StoreError<F::Error>> {
+        let pages = flash.size() / F::PAGE_SIZE;
+        assert!(pages >= 2, "the settings store needs at least two pages");
+
+        let mut active: Option<(u32, u32)> = None;
+        for page in 0..pages {
+            let mut header = [0; PAGE_HEADER_LEN as usize];
+            flash.read(page * F::PAGE_SIZE, &mut header)?;
+            if u32::from_le_bytes(header[..4].try_into().unwrap()) != MAGIC {
+                continue;
+            }
+            let sequence = u32::from_le_bytes(header[4..].try_into().unwrap());
+            // Sequence numbers may wrap around.
+            let newer = match active {
+                Some((_, latest)) => (sequence.wrapping_sub(latest) as i32) > 0,
+                None => true,
+            };
+            if newer {
+                active = Some((page, sequence));
+            }
+        }
+
+        let mut store = Self {
+            flash,
+            page: 0,
+            sequence: 0,
+            end: PAGE_HEADER_LEN,
+        };
+        match active {
+            Some((page, sequence)) => {
+                store.page = page;
+                store.sequence = sequence;
+                store.end = store.records()?.1;
+            }
+            None => {
+                store.flash.erase(0).await?;
+                store.write_header(0, 0).await?;
+            }
+        }
+        Ok(store)
+    }
+
+    /// Gives the flash back.
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    async fn write_header(&mut self, page: u32, sequence: u32) -> Result<(), F::Error> {
+        let mut header = [0; PAGE_HEADER_LEN as usize];
+        header[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..].copy_from_slice(&sequence.to_le_bytes());
+        self.flash.write(page * F::PAGE_SIZE, &header).await
+    }
+
+    /// Reads the committed records of the active page, oldest first, and
+    /// where the next one goes.
+    fn records(&mut self) -> Result<(Vec<Record>, u32), F::Error> {
+        let start = self.page * F::PAGE_SIZE;
+        let mut records = Vec::new();
+        let mut offset = PAGE_HEADER_LEN;
+        while offset + 2 * WORD <= F::PAGE_SIZE {
+            let mut header = [0; WORD as usize];
+            self.flash.read(start + offset, &mut header)?;
+            if header == [0xff; WORD as usize] {
+                break;
+            }
+            let key_len = header[0] as usize;
+            let value_len = u16::from_le_bytes([header[2], header[3]]);
+            let stored_len = if value_len == REMOVED { 0 } else { value_len as usize };
+            let len = record_len(key_len, stored_len);
+            if key_len == 0 || key_len > MAX_KEY_LEN || offset + len > F::PAGE_SIZE {
+                // Not written by us, nothing after it can be trusted.
+                offset = F::PAGE_SIZE;
+                break;
+            }
+
+            let mut commit = [0; WORD as usize];
+            self.flash.read(start + offset + len - WORD, &mut commit)?;
+            if u32::from_le_bytes(commit) == COMMITTED {
+                let mut key = vec![0; key_len];
+                self.flash.read(start + offset + WORD, &mut key)?;
+                let value_offset = start + offset + WORD + key_len as u32;
+                records.push(Record {
+                    key,
+                    value: (value_len != REMOVED).then_some((value_offset, stored_len)),
+                });
+            }
+            offset += len;
+        }
+        Ok((records, offset))
+    }
+
+    /// The latest record for every key still set, in no particular order.
+    fn live_records(&mut self) -> Result<Vec<Record>, F::Error> {
+        let (records, _) = self.records()?;
+        let mut live: Vec<Record> = Vec::new();
+        for record in records {
+            live.retain(|other| other.key != record.key);
+            if record.value.is_some() {
+                live.push(record);
+            }
+        }
+        Ok(live)
+    }
+
+    fn read_value(&mut self, record: &Record) -> Result<Option<Vec<u8>>, F::Error> {
+        let Some((offset, len)) = record.value else {
+            return Ok(None);
+        };
+        let mut value = vec![0; len];
+        self.flash.read(offset, &mut value)?;
+        Ok(Some(value))
+    }
+
+    /// The value of `key`, if it is set.
+    pub fn get(&mut self, key: &str) -> Result<Option<Vec<u8>>, StoreError<F::Error>> {
+        let (records, _) = self.records()?;
+        match records.iter().rev().find(|record| record.key == key.as_bytes()) {
+            Some(record) => Ok(self.read_value(record)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets `key` to `value`. Setting a key to the value it already has
+    /// leaves the flash alone.
+    pub async fn set(&mut self, key: &str, value: &[u8]) -> Result<(), StoreError<F::Error>> {
+        if self.get(key)?.as_deref() == Some(value) {
+            return Ok(());
+        }
+        self.append(key, Some(value)).await
+    }
+
+    /// Removes `key` if it is set.
+    pub async fn remove(&mut self, key: &str) -> Result<(), StoreError<F::Error>> {
+        if self.get(key)?.is_none() {
+            return Ok(());
+        }
+        self.append(key, None).await
+    }
+
+    async fn append(&mut self, key: &str, value: Option<&[u8]>) -> Result<(), StoreError<F::Error>> {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return Err(StoreError::BadKey);
+        }
+        let value_len = value.map_or(0, <[u8]>::len);
+        if value_len >= REMOVED as usize {
+            return Err(StoreError::Full);
+        }
+        let len = record_len(key.len(), value_len);
+        if self.end + len > F::PAGE_SIZE {
+            self.compact(key, len).await?;
+        }
+        let offset = self.page * F::PAGE_SIZE + self.end;
+        write_record(&mut self.flash, offset, key.as_bytes(), value).await?;
+        self.end += len;
+        Ok(())
+    }
+
+    /// Copies the settings other than `key` to the next page and makes it
+    /// the active one, leaving `reserve` bytes free for the new record of
+    /// `key`.
+    async fn compact(&mut self, key: &str, reserve: u32) -> Result<(), StoreError<F::Error>> {
+        let mut live = self.live_records()?;
+        live.retain(|record| record.key != key.as_bytes());
+        let needed: u32 = live
+            .iter()
+            .map(|record| record_len(record.key.len(), record.value.map_or(0, |(_, len)| len)))
+            .sum();
+        if PAGE_HEADER_LEN + needed + reserve > F::PAGE_SIZE {
+            return Err(StoreError::Full);
+        }
+
+        let pages = self.flash.size() / F::PAGE_SIZE;
+        let next = (self.page + 1) % pages;
+        self.flash.erase(next * F::PAGE_SIZE).await?;
+        let mut end = PAGE_HEADER_LEN;
+        for record in &live {
+            let value = self.read_value(record)?;
+            let offset = next * F::PAGE_SIZE + end;
+            write_record(&mut self.flash, offset, &record.key, value.as_deref()).await?;
+            end += record_len(record.key.len(), value.map_or(0, |value| value.len()));
+        }
+        let sequence = self.sequence.wrapping_add(1);
+        self.write_header(next, sequence).await?;
+
+        self.page = next;
+        self.sequence = sequence;
+        self.end = end;
+        Ok(())
+    }
+}
+
+/// Writes a record at `offset`, then commits it.
+async fn write_record<F: Flash>(
+    flash: &mut F,
+    offset: u32,
+    key: &[u8],
+    value: Option<&[u8]>,
+) -> Result<(), F::Error> {
+    let value_len = match value {
+        Some(value) => value.len() as u16,
+        None => REMOVED,
+    };
+    let value = value.unwrap_or(&[]);
+    let body_len = words(key.len() + value.len()) as usize;
+    let mut record = Vec::with_capacity(WORD as usize + body_len);
+    record.extend_from_slice(&[key.len() as u8, 0xff]);
+    record.extend_from_slice(&value_len.to_le_bytes());
+    record.extend_from_slice(key);
+    record.extend_from_slice(value);
+    record.resize(WORD as usize + body_len, 0xff);
+    flash.write(offset, &record).await?;
+    let commit_offset = offset + record.len() as u32;
+    flash.write(commit_offset, &COMMITTED.to_le_bytes()).await
+}
@@ -0,0 +1,140 @@
+//! The status bar below the page.
+//!
+//! It shows the title of the page on the left and, on the right, whether
+//! loading it failed, how much of it arrived, where on the page the view is
+//! and whether the server is connected. The bar is laid out as a list of
+//! [`Field`]s, which the firmware draws as toekomst labels and
+//! [`Status::draw`] draws on any other target.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{Line, PrimitiveStyle, Rectangle};
+
+use crate::style::{Run, FONT, FONT_BOLD};
+use crate::view::{MARGIN_LEFT, PAGE_BOTTOM, STATUS_HEIGHT, WIDTH};
+
+/// State of the link to the server.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Connection {
+    Connecting,
+    Connected,
+    Disconnected,
+}
+
+impl Connection {
+    fn label(self) -> &'static str {
+        match self {
+            Connection::Connecting => "BLE...",
+            Connection::Connected => "BLE",
+            Connection::Disconnected => "no BLE",
+        }
+    }
+}
+
+/// Everything the status bar shows.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Status {
+    pub title: String,
+    pub connection: Connection,
+    /// Percentage of the page received while it is loading.
+    pub progress: Option<u8>,
+    /// Loading the page failed.
+    pub error: bool,
+    /// First line on screen and number of lines, while a page is shown.
+    pub position: Option<(i32, i32)>,
+}
+
+/// A piece of text in the status bar.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Field {
+    pub text: String,
+    pub bounds: Rectangle,
+    pub bold: bool,
+}
+
+impl Status {
+    pub fn new(connection: Connection) -> Self {
+        Self {
+            title: String::new(),
+            connection,
+            progress: None,
+            error: false,
+            position: None,
+        }
+    }
+
+    /// The text of the bar: the state on the right, the title in bold in the
+    /// space left of it, cut short if needed.
+    pub fn fields(&self) -> Vec<Field> {
+        let mut state = Vec::new();
+        if self.error {
+            state.push(String::from("ERROR"));
+        }
+        if let Some(progress) = self.progress {
+            state.push(format!("{}%", progress));
+        }
+        if let Some((line, lines)) = self.position {
+            state.push(format!("{}/{}", line, lines));
+        }
+        state.push(String::from(self.connection.label()));
+        let state = state.join("  ");
+
+        let size = FONT.character_size;
+        let top = PAGE_BOTTOM + 2;
+        let width = size.width * state.chars().count() as u32;
+        let state_left = WIDTH as i32 - MARGIN_LEFT - width as i32;
+        let mut fields = Vec::new();
+
+        let columns = ((state_left - MARGIN_LEFT) as u32 / size.width).saturating_sub(1);
+        let mut title: String = self.title.chars().take(columns as usize).collect();
+        if title.len() < self.title.len() && title.pop().is_some() {
+            title.push('~');
+        }
+        if !title.is_empty() {
+            let width = size.width * title.chars().count() as u32;
+            fields.push(Field {
+                text: title,
+                bounds: Rectangle::new(Point::new(MARGIN_LEFT, top), Size::new(width, size.height)),
+                bold: true,
+            });
+        }
+        fields.push(Field {
+            text: state,
+            bounds: Rectangle::new(Point::new(state_left, top), Size::new(width, size.height)),
+            bold: false,
+        });
+        fields
+    }
+
+    /// Clears the status bar and draws the rule above it, leaving the fields
+    /// to be drawn.
+    pub fn draw_background<D>(target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        Rectangle::new(Point::new(0, PAGE_BOTTOM), Size::new(WIDTH, STATUS_HEIGHT))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+            .draw(target)?;
+        Line::new(Point::new(0, PAGE_BOTTOM), Point::new(WIDTH as i32 - 1, PAGE_BOTTOM))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(target)?;
+        Ok(())
+    }
+
+    /// Draws the whole status bar.
+    pub fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        Self::draw_background(target)?;
+        for field in self.fields() {
+            let run = Run::plain(if field.bold { FONT_BOLD } else { FONT });
+            run.draw(&field.text, field.bounds.top_left, target)?;
+        }
+        Ok(())
+    }
+}
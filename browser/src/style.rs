@@ -0,0 +1,178 @@
+//! Text styles and how they look on the monochrome display.
+//!
+//! Every `StyleVar` gets its own stack so nested and overlapping styles
+//! combine:
+//!
+//! - bold and italic text use the matching font,
+//! - underlined text and links get a rule under the text,
+//! - code is drawn inverted,
+//! - headings use a larger bold font,
+//! - the focused link is boxed in.
+
+use embedded_graphics::mono_font::ascii::{
+    FONT_6X13, FONT_6X13_BOLD, FONT_6X13_ITALIC, FONT_9X18_BOLD,
+};
+use embedded_graphics::mono_font::{MonoFont, MonoTextStyleBuilder};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{Line, PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Baseline, Text};
+use swb_shared::StyleVar;
+
+/// Font for text without any style.
+pub static FONT: &MonoFont<'static> = &FONT_6X13;
+pub static FONT_BOLD: &MonoFont<'static> = &FONT_6X13_BOLD;
+pub static FONT_ITALIC: &MonoFont<'static> = &FONT_6X13_ITALIC;
+pub static FONT_HEADING: &MonoFont<'static> = &FONT_9X18_BOLD;
+
+#[derive(Copy, Clone)]
+pub struct StyleVarStack {
+    state: u8,
+}
+
+impl StyleVarStack {
+    pub fn new() -> Self {
+        Self { state: 0 }
+    }
+
+    pub fn push(&mut self) {
+        self.state = self.state.saturating_add(1);
+    }
+
+    pub fn pop(&mut self) {
+        self.state = self.state.saturating_sub(1);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.state > 0
+    }
+}
+
+impl Default for StyleVarStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The styles in effect at some point in a page.
+#[derive(Copy, Clone, Default)]
+pub struct Styles {
+    bold: StyleVarStack,
+    italic: StyleVarStack,
+    underline: StyleVarStack,
+    code: StyleVarStack,
+    heading: StyleVarStack,
+}
+
+impl Styles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn stack(&mut self, var: &StyleVar) -> Option<&mut StyleVarStack> {
+        match var {
+            StyleVar::Bold => Some(&mut self.bold),
+            StyleVar::Italic => Some(&mut self.italic),
+            StyleVar::Underline => Some(&mut self.underline),
+            StyleVar::Code => Some(&mut self.code),
+            StyleVar::Heading => Some(&mut self.heading),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    pub fn push(&mut self, var: &StyleVar) {
+        if let Some(stack) = self.stack(var) {
+            stack.push();
+        }
+    }
+
+    pub fn pop(&mut self, var: &StyleVar) {
+        if let Some(stack) = self.stack(var) {
+            stack.pop();
+        }
+    }
+
+    /// How text drawn with the current styles looks. `link` is `Some` for
+    /// link text, telling whether that link has focus.
+    pub fn run(&self, link: Option<bool>) -> Run {
+        let font = if self.heading.is_enabled() {
+            FONT_HEADING
+        } else if self.italic.is_enabled() {
+            FONT_ITALIC
+        } else if self.bold.is_enabled() {
+            FONT_BOLD
+        } else {
+            FONT
+        };
+        Run {
+            font,
+            underline: self.underline.is_enabled() || link.is_some(),
+            inverted: self.code.is_enabled(),
+            framed: link == Some(true),
+        }
+    }
+}
+
+/// Appearance of a run of text.
+pub struct Run {
+    pub font: &'static MonoFont<'static>,
+    pub underline: bool,
+    pub inverted: bool,
+    pub framed: bool,
+}
+
+impl Run {
+    /// Text in `font` without any decoration.
+    pub fn plain(font: &'static MonoFont<'static>) -> Self {
+        Self {
+            font,
+            underline: false,
+            inverted: false,
+            framed: false,
+        }
+    }
+
+    pub fn character_size(&self) -> Size {
+        self.font.character_size
+    }
+
+    /// Draws `text` with its top left corner at `top_left`.
+    pub fn draw<D>(&self, text: &str, top_left: Point, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let (fg, bg) = if self.inverted {
+            (BinaryColor::Off, BinaryColor::On)
+        } else {
+            (BinaryColor::On, BinaryColor::Off)
+        };
+        let style = MonoTextStyleBuilder::new()
+            .font(self.font)
+            .text_color(fg)
+            .background_color(bg)
+            .build();
+        let size = self.font.character_size;
+        let width = size.width * text.chars().count() as u32;
+
+        Text::with_baseline(text, top_left, style, Baseline::Top).draw(target)?;
+        if self.underline && width > 0 {
+            let y = top_left.y + size.height as i32 - 1;
+            Line::new(
+                Point::new(top_left.x, y),
+                Point::new(top_left.x + width as i32 - 1, y),
+            )
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(target)?;
+        }
+        if self.framed {
+            Rectangle::new(
+                top_left - Point::new(2, 1),
+                Size::new(width + 4, size.height + 2),
+            )
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(target)?;
+        }
+        Ok(())
+    }
+}
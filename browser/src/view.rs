@@ -0,0 +1,451 @@
+//! Laying out a page on the display and reacting to the user.
+//!
+//! Every text run starts on a new line and is wrapped to the width of the
+//! display; an `Endl` adds an empty line. Lines are counted from zero and the
+//! [`View`] draws from its `start_line` on until the display is full, leaving
+//! room for the [status bar](crate::status) at the bottom. Where
+//! each line starts is kept in a [`LineIndex`], so drawing does not have to
+//! lay out the page from the top every time.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use swb_shared::Instruction;
+
+use crate::keyboard::Keycode;
+use crate::lines::{LineIndex, LineStart};
+use crate::page::Page;
+use crate::style::{Run, FONT, FONT_BOLD};
+use crate::wrap::wrap;
+
+/// Size of the Sharp memory display.
+pub const WIDTH: u32 = 400;
+pub const HEIGHT: u32 = 240;
+
+/// Space left of the text and above the first line.
+pub(crate) const MARGIN_LEFT: i32 = 5;
+pub(crate) const MARGIN_TOP: i32 = 2;
+/// Space between two lines.
+pub(crate) const SPACING: u32 = 2;
+/// Height of the status bar below the page.
+pub const STATUS_HEIGHT: u32 = 16;
+/// Bottom of the area pages are drawn in.
+pub(crate) const PAGE_BOTTOM: i32 = (HEIGHT - STATUS_HEIGHT) as i32;
+/// More lines than fit on the display: none is shorter than 8 pixels.
+const MAX_LINES_ON_SCREEN: usize = HEIGHT as usize / 8;
+
+pub static LINES_PER_SCROLL: i32 = 5;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Action {
+    ScrollUp,
+    ScrollDown,
+    NextLink,
+    PrevLink,
+    FollowLink,
+    /// Scroll by a whole screen.
+    PageUp,
+    PageDown,
+    /// Jump to the start or the end of the page.
+    Top,
+    Bottom,
+    Back,
+    Forward,
+    EditAddress,
+    /// Choose another server to connect to.
+    PickServer,
+}
+
+/// Where to go after leaving a page.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Navigate {
+    To(String),
+    Back,
+    Forward,
+    EditAddress,
+    PickServer,
+    /// Load the current URL again.
+    Reload,
+}
+
+/// Key bindings while reading a page.
+pub fn action_for(code: Keycode) -> Option<Action> {
+    let action = match code {
+        Keycode::Char('i') | Keycode::Up => Action::ScrollUp,
+        Keycode::Char('k') | Keycode::Down => Action::ScrollDown,
+        Keycode::Char('I') | Keycode::Button(2) => Action::PageUp,
+        Keycode::Char('K') | Keycode::Char(' ') | Keycode::Button(3) => Action::PageDown,
+        Keycode::Char('t') => Action::Top,
+        Keycode::Char('e') => Action::Bottom,
+        Keycode::Char('j') | Keycode::Left => Action::PrevLink,
+        Keycode::Char('l') | Keycode::Right | Keycode::Tab => Action::NextLink,
+        Keycode::Char('o') | Keycode::Select | Keycode::Enter => Action::FollowLink,
+        Keycode::Char('b') | Keycode::Backspace | Keycode::Button(0) => Action::Back,
+        Keycode::Char('f') | Keycode::Button(1) => Action::Forward,
+        Keycode::Char('g') => Action::EditAddress,
+        Keycode::Char('s') => Action::PickServer,
+        _ => return None,
+    };
+    Some(action)
+}
+
+/// Text columns on the display in a font `width` pixels wide.
+pub(crate) fn columns(width: u32) -> usize {
+    ((WIDTH as i32 - 2 * MARGIN_LEFT) as u32 / width) as usize
+}
+
+/// What the display showed after the last draw.
+struct Drawn {
+    start_line: i32,
+    focus: Option<usize>,
+    /// First instruction of the window of the page.
+    first: u32,
+    /// Top of the first line not drawn yet.
+    bottom: i32,
+}
+
+/// Scroll position and link focus on one page.
+pub struct View {
+    start_line: i32,
+    focus: Option<usize>,
+    /// Links on screen after the last draw, in reading order.
+    visible: Vec<usize>,
+    /// Number of lines shown by the last draw.
+    shown: i32,
+    /// Whether the last draw filled the display.
+    full: bool,
+    index: LineIndex,
+    /// `None` if the display has to be redrawn from scratch.
+    drawn: Option<Drawn>,
+}
+
+impl View {
+    pub fn new(start_line: i32) -> Self {
+        Self {
+            start_line,
+            focus: None,
+            visible: Vec::new(),
+            shown: 0,
+            full: false,
+            index: LineIndex::new(),
+            drawn: None,
+        }
+    }
+
+    pub fn start_line(&self) -> i32 {
+        self.start_line
+    }
+
+    pub fn focus(&self) -> Option<usize> {
+        self.focus
+    }
+
+    pub fn visible_links(&self) -> &[usize] {
+        &self.visible
+    }
+
+    /// Whether the last draw filled the display, so more of the page arriving
+    /// would not change what is shown.
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    /// Where the window of `page` should start, if the last draw could not
+    /// fill the display because the lines it needs are not in the window, or
+    /// if the lines of the next screen are not known yet. See
+    /// [`PageDecoder::seek`](crate::decode::PageDecoder::seek).
+    pub fn wanted(&self, page: &Page) -> Option<u32> {
+        let start = self.start_line.max(0) as usize;
+        let end = start + self.shown as usize;
+        let missing = if self.index.is_complete(page) {
+            // Unless the page ends on screen.
+            !self.full && end < self.index.len()
+        } else {
+            !self.full || end + MAX_LINES_ON_SCREEN > self.index.len()
+        };
+        let first = self.index.start_of(start);
+        (missing && first != page.window.first).then_some(first)
+    }
+
+    /// The first line on screen, counting from one, and the number of lines
+    /// of the page as far as it was drawn.
+    pub fn position(&self) -> (i32, i32) {
+        let lines = self.index.len() as i32;
+        ((self.start_line + 1).min(lines), lines)
+    }
+
+    /// Makes the next draw start over, for when something else was drawn on
+    /// the display in between.
+    pub fn invalidate(&mut self) {
+        self.drawn = None;
+    }
+
+    /// Makes the next draw start over with another version of the page, whose
+    /// lines may start elsewhere than those of the page drawn so far.
+    pub fn replace(&mut self) {
+        self.index = LineIndex::new();
+        self.invalidate();
+    }
+
+    /// Draws the visible part of `page` above the status bar.
+    ///
+    /// Only what changed since the last draw is drawn again: the lines of
+    /// links that gained or lost focus and lines that arrived since. After
+    /// scrolling, once another window of the page was loaded, or after
+    /// [`View::invalidate`], the page area is cleared and drawn from scratch.
+    /// A view is meant for one page, which may grow between draws, unless
+    /// [`View::replace`] is called in between.
+    pub fn draw<D>(&mut self, page: &Page, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        self.index.update(page);
+        let bottom = match self.drawn.take() {
+            Some(drawn) if drawn.start_line == self.start_line && drawn.first == page.window.first => {
+                if drawn.focus != self.focus {
+                    self.redraw_links(page, &[drawn.focus, self.focus], target)?;
+                }
+                self.draw_lines(page, drawn.bottom, target)?
+            }
+            _ => {
+                Rectangle::new(Point::zero(), Size::new(WIDTH, PAGE_BOTTOM as u32))
+                    .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+                    .draw(target)?;
+                self.visible.clear();
+                self.shown = 0;
+                self.full = false;
+                self.draw_lines(page, MARGIN_TOP, target)?
+            }
+        };
+        self.drawn = Some(Drawn {
+            start_line: self.start_line,
+            focus: self.focus,
+            first: page.window.first,
+            bottom,
+        });
+        Ok(())
+    }
+
+    /// Draws the lines after the ones already shown, starting at `y`, until
+    /// the display is full. Returns where the next line would go.
+    fn draw_lines<D>(&mut self, page: &Page, mut y: i32, target: &mut D) -> Result<i32, D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let first = self.start_line.max(0) as usize + self.shown as usize;
+        for line in self.index.lines(page, first) {
+            if y >= PAGE_BOTTOM {
+                self.full = true;
+                break;
+            }
+            let height = line.height(page) as i32;
+            if y + height > PAGE_BOTTOM {
+                self.full = true;
+                break;
+            }
+            if let Some(link) = self.draw_line(page, &line, y, target)? {
+                if self.visible.last() != Some(&link) {
+                    self.visible.push(link);
+                }
+            }
+            y += height + SPACING as i32;
+            self.shown += 1;
+        }
+        if y >= PAGE_BOTTOM {
+            self.full = true;
+        }
+        Ok(y)
+    }
+
+    /// Draws the lines on screen that belong to any of `links` again.
+    fn redraw_links<D>(
+        &mut self,
+        page: &Page,
+        links: &[Option<usize>],
+        target: &mut D,
+    ) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let first = self.start_line.max(0) as usize;
+        let mut y = MARGIN_TOP;
+        for line in self.index.lines(page, first).take(self.shown as usize) {
+            let height = line.height(page);
+            let link = match page.instr(line.instr) {
+                Some(Instruction::Text(address)) => page.link_at(address),
+                _ => None,
+            };
+            if link.is_some() && links.contains(&link) {
+                // The focus frame reaches one pixel above and below the line.
+                Rectangle::new(Point::new(0, y - 1), Size::new(WIDTH, height + 2))
+                    .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+                    .draw(target)?;
+                self.draw_line(page, &line, y, target)?;
+            }
+            y += (height + SPACING) as i32;
+        }
+        Ok(())
+    }
+
+    /// Draws `line` with its top at `y`. Returns the link it belongs to.
+    fn draw_line<D>(
+        &self,
+        page: &Page,
+        line: &LineStart,
+        y: i32,
+        target: &mut D,
+    ) -> Result<Option<usize>, D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let Some(Instruction::Text(address)) = page.instr(line.instr) else {
+            return Ok(None);
+        };
+        let link = page.link_at(address);
+        let run = line.styles.run(link.map(|link| self.focus == Some(link)));
+        let columns = columns(run.character_size().width);
+        let Some(text) = wrap(page.text(address), columns).nth(line.wrapped as usize) else {
+            return Ok(link);
+        };
+        let top_left = Point::new(MARGIN_LEFT, y);
+        if text.hyphen {
+            run.draw(&format!("{}-", text.text), top_left, target)?;
+        } else {
+            run.draw(text.text, top_left, target)?;
+        }
+        Ok(link)
+    }
+
+    /// The first line of the screen that ends just before line `end`.
+    fn screen_start(&self, page: &Page, end: usize) -> i32 {
+        let end = end.min(self.index.len());
+        let first = end.saturating_sub(MAX_LINES_ON_SCREEN);
+        // Lines outside the window of the page are taken to be plain.
+        let mut heights = [FONT.character_size.height; MAX_LINES_ON_SCREEN];
+        for (height, line) in heights.iter_mut().zip(self.index.lines(page, first)) {
+            *height = line.height(page);
+        }
+        let mut used = 0;
+        let mut start = end;
+        while start > first {
+            used += heights[start - 1 - first] + SPACING;
+            if MARGIN_TOP + (used - SPACING) as i32 > PAGE_BOTTOM {
+                break;
+            }
+            start -= 1;
+        }
+        start as i32
+    }
+
+    /// Where the last screen of `page` starts.
+    fn last_start(&mut self, page: &Page) -> i32 {
+        self.index.update(page);
+        self.screen_start(page, self.index.len())
+    }
+
+    /// Applies `action`. Returns where to go if it leaves the page.
+    pub fn apply(&mut self, page: &Page, action: Action) -> Option<Navigate> {
+        match action {
+            Action::ScrollUp => {
+                self.start_line -= LINES_PER_SCROLL;
+                self.start_line = self.start_line.max(0);
+            }
+            Action::ScrollDown => {
+                let last_start = self.last_start(page);
+                self.start_line = (self.start_line + LINES_PER_SCROLL).min(last_start);
+            }
+            Action::PageUp => {
+                self.index.update(page);
+                self.start_line = self.screen_start(page, self.start_line.max(0) as usize);
+            }
+            Action::PageDown => {
+                let last_start = self.last_start(page);
+                self.start_line = (self.start_line + self.shown.max(1)).min(last_start);
+            }
+            Action::Top => self.start_line = 0,
+            Action::Bottom => self.start_line = self.last_start(page),
+            Action::NextLink => self.focus = cycle_focus(self.focus, &self.visible, true),
+            Action::PrevLink => self.focus = cycle_focus(self.focus, &self.visible, false),
+            Action::FollowLink => {
+                let link = self.focus.filter(|focus| self.visible.contains(focus))?;
+                return Some(Navigate::To(page.links[link].href.clone()));
+            }
+            Action::Back => return Some(Navigate::Back),
+            Action::Forward => return Some(Navigate::Forward),
+            Action::EditAddress => return Some(Navigate::EditAddress),
+            Action::PickServer => return Some(Navigate::PickServer),
+        }
+        None
+    }
+}
+
+/// Moves focus to the next (or previous) link on screen, wrapping around.
+fn cycle_focus(focus: Option<usize>, visible: &[usize], forward: bool) -> Option<usize> {
+    if visible.is_empty() {
+        return None;
+    }
+    let current = focus.and_then(|focus| visible.iter().position(|&link| link == focus));
+    let next = match (current, forward) {
+        (Some(i), true) => (i + 1) % visible.len(),
+        (Some(i), false) => (i + visible.len() - 1) % visible.len(),
+        (None, true) => 0,
+        (None, false) => visible.len() - 1,
+    };
+    Some(visible[next])
+}
+
+/// What `action` does on the error page shown instead of a page that failed
+/// to load: following a link retries, going back, forward, to the address
+/// bar and to the server picker work as usual.
+pub fn apply_on_error(action: Action) -> Option<Navigate> {
+    match action {
+        Action::FollowLink => Some(Navigate::Reload),
+        Action::Back => Some(Navigate::Back),
+        Action::Forward => Some(Navigate::Forward),
+        Action::EditAddress => Some(Navigate::EditAddress),
+        Action::PickServer => Some(Navigate::PickServer),
+        _ => None,
+    }
+}
+
+/// What `action` does while none of a page arrived yet: like
+/// [`apply_on_error`], except that there is nothing to retry.
+pub fn apply_while_loading(action: Action) -> Option<Navigate> {
+    match action {
+        Action::FollowLink => None,
+        action => apply_on_error(action),
+    }
+}
+
+/// Draws the error page for `url`. The target is expected to be clear.
+pub fn draw_error<D>(url: &str, reason: &str, target: &mut D) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let plain = Run::plain(FONT);
+    let bold = Run::plain(FONT_BOLD);
+    let size = FONT.character_size;
+    let mut y = MARGIN_TOP;
+    let mut lines = |run: &Run, text: &str, target: &mut D| {
+        for line in wrap(text, columns(size.width)) {
+            if line.hyphen {
+                run.draw(&format!("{}-", line.text), Point::new(MARGIN_LEFT, y), target)?;
+            } else {
+                run.draw(line.text, Point::new(MARGIN_LEFT, y), target)?;
+            }
+            y += (size.height + SPACING) as i32;
+        }
+        Ok(())
+    };
+
+    lines(&bold, "Could not load page", target)?;
+    lines(&plain, url, target)?;
+    lines(&plain, "", target)?;
+    lines(&plain, reason, target)?;
+    lines(&plain, "", target)?;
+    lines(&plain, "o: retry  b: back  g: go to address  s: servers", target)
+}
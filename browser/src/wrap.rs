@@ -0,0 +1,89 @@
+//! Breaking text runs into lines that fit the display.
+//!
+//! The display uses a monospace font, so widths are counted in characters.
+//! Lines break at spaces; words that do not fit on a line of their own are
+//! hyphenated.
+
+/// One visual line produced by [`wrap`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Line<'a> {
+    pub text: &'a str,
+    /// The line ends in the middle of a word and should be drawn with a
+    /// trailing hyphen.
+    pub hyphen: bool,
+}
+
+impl<'a> Line<'a> {
+    /// Width of the line in characters, including the hyphen.
+    pub fn width(&self) -> usize {
+        self.text.chars().count() + self.hyphen as usize
+    }
+}
+
+/// Wraps `text` into lines of at most `columns` characters.
+///
+/// Spaces at the point where a line is broken are dropped. Text that is empty
+/// still produces one, empty line so that it takes up vertical space like it
+/// did before wrapping.
+pub fn wrap(text: &str, columns: usize) -> Wrap<'_> {
+    Wrap {
+        rest: text,
+        columns: columns.max(1),
+        first: true,
+    }
+}
+
+pub struct Wrap<'a> {
+    rest: &'a str,
+    columns: usize,
+    first: bool,
+}
+
+/// Byte offset of the character at index `chars`, or the end of `s`.
+fn byte_offset(s: &str, chars: usize) -> usize {
+    s.char_indices().nth(chars).map_or(s.len(), |(i, _)| i)
+}
+
+impl<'a> Iterator for Wrap<'a> {
+    type Item = Line<'a>;
+
+    fn next(&mut self) -> Option<Line<'a>> {
+        if self.rest.is_empty() {
+            if self.first {
+                self.first = false;
+                return Some(Line { text: "", hyphen: false });
+            }
+            return None;
+        }
+        self.first = false;
+
+        let rest = self.rest;
+        let fits = byte_offset(rest, self.columns);
+        if fits == rest.len() {
+            self.rest = "";
+            return Some(Line { text: rest, hyphen: false });
+        }
+
+        // Break at the last space that still fits, the space itself may be
+        // the character just past the end of the line.
+        let candidate = &rest[..byte_offset(rest, self.columns + 1)];
+        let line = match candidate.rfind(' ') {
+            Some(space) if !rest[..space].trim_end().is_empty() => Line {
+                text: rest[..space].trim_end(),
+                hyphen: false,
+            },
+            _ => {
+                // A single word longer than the line.
+                let keep = if self.columns >= 2 { self.columns - 1 } else { self.columns };
+                Line {
+                    text: &rest[..byte_offset(rest, keep)],
+                    hyphen: self.columns >= 2,
+                }
+            }
+        };
+
+        let consumed = line.text.len();
+        self.rest = rest[consumed..].trim_start_matches(' ');
+        Some(line)
+    }
+}
@@ -0,0 +1,51 @@
+use browser::address::{draw, edit, Edit};
+use browser::framebuffer::Framebuffer;
+use browser::keyboard::Keycode;
+
+fn drawn(current: &str, url: &str) -> Framebuffer {
+    let mut fb = Framebuffer::new();
+    draw(current, url, &mut fb).unwrap();
+    fb
+}
+
+#[test]
+fn only_url_characters_are_typed() {
+    let mut url = String::new();
+    for c in "ab c/ü".chars() {
+        assert_eq!(edit(&mut url, Keycode::Char(c)), Edit::Typing);
+    }
+    assert_eq!(url, "abc/");
+    edit(&mut url, Keycode::Backspace);
+    assert_eq!(url, "abc");
+    assert_eq!(edit(&mut url, Keycode::Enter), Edit::Submit);
+}
+
+#[test]
+fn the_line_can_be_cleared_or_left() {
+    let mut url = String::from("https://example.org/");
+    assert_eq!(edit(&mut url, Keycode::Button(1)), Edit::Typing);
+    assert_eq!(url, "");
+    url.push_str("ab.html");
+    assert_eq!(edit(&mut url, Keycode::Button(0)), Edit::Cancel);
+}
+
+#[test]
+fn long_urls_show_their_end() {
+    let end = "/".repeat(200);
+    let first = drawn(&format!("https://a{}", end), &format!("b{}", end));
+    let second = drawn(&format!("https://z{}", end), &format!("y{}", end));
+    assert_eq!(first.as_bytes(), second.as_bytes());
+    assert!(first.as_bytes() != drawn("", "").as_bytes());
+}
+
+#[test]
+fn non_ascii_urls_are_cut_between_characters() {
+    let end = "/".repeat(200);
+    let cut = drawn(&format!("https://bücher.example/ä{}", end), "ö");
+    let ascii = drawn(&format!("https://a{}", end), "ö");
+    assert_eq!(cut.as_bytes(), ascii.as_bytes());
+    // Whatever the number of columns, one of these ends mid-character in bytes.
+    let wide = "ü".repeat(200);
+    drawn(&wide, &wide);
+    drawn(&format!("{}a", wide), &format!("{}a", wide));
+}
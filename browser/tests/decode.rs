@@ -0,0 +1,191 @@
+use browser::decode::PageDecoder;
+use browser::framebuffer::Framebuffer;
+use browser::page::{Page, PageError};
+use browser::view::View;
+use browser::HEAP_SIZE;
+use protocol::etag;
+use protocol::page::{encode_page, Link};
+use swb_shared::Instruction;
+
+fn swb(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/../client/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+fn links() -> Vec<Link> {
+    vec![Link { start: 0, len: 4, href: "ns.html".to_string() }]
+}
+
+fn screen(page: &Page) -> Framebuffer {
+    let mut screen = Framebuffer::new();
+    View::new(0).draw(page, &mut screen).unwrap();
+    screen
+}
+
+#[test]
+fn decodes_pages_in_any_chunk_size() {
+    let program = swb("ns.swb");
+    let expected = Page::parse(&program, links()).unwrap();
+    let payload = encode_page("NS", &program, &links()).unwrap();
+    for chunk in [1, 7, 9, 239, payload.len()] {
+        let mut decoder = PageDecoder::new(64 * 1024);
+        for data in payload.chunks(chunk) {
+            assert!(!decoder.is_complete());
+            decoder.push(data).unwrap();
+        }
+        assert!(decoder.is_complete());
+        let page = decoder.finish().unwrap();
+        assert_eq!(page.program.text, expected.program.text);
+        assert_eq!(page.program.code.len(), expected.program.code.len());
+        assert_eq!(page.links, expected.links);
+        assert_eq!(page.title, "NS");
+    }
+}
+
+#[test]
+fn progress_counts_the_program() {
+    let program = swb("ab.swb");
+    let payload = encode_page("AB", &program, &links()).unwrap();
+    let header_len = payload.len() - program.len();
+    let mut decoder = PageDecoder::new(64 * 1024);
+    decoder.push(&payload[..header_len - 1]).unwrap();
+    assert_eq!(decoder.progress(), 0);
+    decoder.push(&payload[header_len - 1..header_len + program.len() / 2]).unwrap();
+    assert!(matches!(decoder.progress(), 49 | 50));
+    decoder.push(&payload[header_len + program.len() / 2..]).unwrap();
+    assert_eq!(decoder.progress(), 100);
+}
+
+#[test]
+fn first_screen_is_ready_before_the_page_is() {
+    let program = swb("ns.swb");
+    let expected = screen(&Page::parse(&program, links()).unwrap());
+    let payload = encode_page("NS", &program, &links()).unwrap();
+
+    let mut decoder = PageDecoder::new(64 * 1024);
+    let mut received = 0;
+    for data in payload.chunks(239) {
+        decoder.push(data).unwrap();
+        received += data.len();
+        let Some(page) = decoder.page() else {
+            continue;
+        };
+        let mut view = View::new(0);
+        let mut partial = Framebuffer::new();
+        view.draw(page, &mut partial).unwrap();
+        if view.is_full() {
+            assert!(partial == expected);
+            break;
+        }
+    }
+    assert!(received < payload.len(), "first screen only complete with the whole page");
+}
+
+#[test]
+fn truncated_pages_are_rejected() {
+    let payload = encode_page("AB", &swb("ab.swb"), &links()).unwrap();
+    let mut decoder = PageDecoder::new(64 * 1024);
+    decoder.push(&payload[..payload.len() - 1]).unwrap();
+    assert!(!decoder.is_complete());
+    assert!(matches!(decoder.finish(), Err(PageError::Parse(_))));
+}
+
+#[test]
+fn data_past_the_program_is_rejected() {
+    let mut payload = encode_page("AB", &swb("ab.swb"), &links()).unwrap();
+    payload.push(0);
+    assert!(PageDecoder::new(64 * 1024).push(&payload).is_err());
+}
+
+#[test]
+fn kept_pages_are_complete() {
+    let page = Page::parse(&swb("ab.swb"), links()).unwrap();
+    let decoder = PageDecoder::from_page(page, 64 * 1024);
+    assert!(decoder.is_complete());
+    assert!(decoder.wanted().unwrap().is_none());
+    assert!(decoder.finish().is_ok());
+}
+
+#[test]
+fn oversized_pages_are_rejected() {
+    // Only the title and the links have to fit as a whole.
+    let payload = encode_page("NS", &swb("ab.swb"), &links()).unwrap();
+    let mut decoder = PageDecoder::new(16);
+    assert!(decoder.push(&payload[..64]).is_err());
+
+    // Offsets into the payload have to fit in a `u32`.
+    let mut payload = encode_page("NS", &[], &[]).unwrap();
+    let len = payload.len();
+    payload[len - 4..].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(PageDecoder::new(64 * 1024).push(&payload).is_err());
+}
+
+/// Feeds `decoder` the parts of `payload` it asks for, in frame sized
+/// pieces.
+fn load(decoder: &mut PageDecoder, payload: &[u8]) {
+    while let Some(range) = decoder.wanted().unwrap() {
+        let range = range.start as usize..range.end as usize;
+        for data in payload[range].chunks(239) {
+            decoder.push_range(data).unwrap();
+        }
+    }
+}
+
+#[test]
+fn long_pages_are_held_in_windows() {
+    let program = swb("ns.swb");
+    let expected = Page::parse(&program, links()).unwrap();
+    let payload = encode_page("NS", &program, &links()).unwrap();
+    let budget = HEAP_SIZE / 2;
+    assert!(payload.len() > budget);
+
+    let mut decoder = PageDecoder::new(budget);
+    for data in payload.chunks(239) {
+        decoder.push(data).unwrap();
+        if let Some(page) = decoder.page() {
+            assert!(page.heap_size() <= budget);
+        }
+    }
+    assert!(decoder.is_complete());
+    assert!(!decoder.page().unwrap().holds_end());
+    assert_eq!(decoder.page().unwrap().window.etag, etag(&payload));
+
+    // Every instruction of the page turns up in one of the windows.
+    let mut first = 0;
+    let mut windows = 0;
+    while first < expected.program.code.len() as u32 {
+        decoder.seek(first);
+        load(&mut decoder, &payload);
+        let page = decoder.page().unwrap();
+        assert!(page.heap_size() <= budget);
+        assert_eq!(page.window.first, first);
+        assert!(!page.program.code.is_empty());
+        for (i, instr) in page.program.code.iter().enumerate() {
+            let index = first as usize + i;
+            let want = &expected.program.code[index];
+            assert_eq!(format!("{:?}", instr), format!("{:?}", want));
+            if let (Instruction::Text(got), Instruction::Text(want)) = (instr, want) {
+                assert_eq!(page.text(got), expected.text(want));
+            }
+        }
+        first += page.program.code.len() as u32;
+        windows += 1;
+    }
+    assert!(windows > 1);
+    assert!(decoder.page().unwrap().holds_end());
+    assert!(decoder.finish().is_ok());
+}
+
+#[test]
+fn ranges_are_only_taken_when_asked_for() {
+    let payload = encode_page("AB", &swb("ab.swb"), &links()).unwrap();
+    let mut decoder = PageDecoder::new(HEAP_SIZE / 2);
+    assert!(decoder.push_range(&payload[..9]).is_err());
+    decoder.push(&payload).unwrap();
+    decoder.seek(10);
+    let wanted = decoder.wanted().unwrap().unwrap();
+    let mut too_long = payload[wanted.start as usize..wanted.end as usize].to_vec();
+    too_long.push(0);
+    assert!(decoder.push_range(&too_long).is_err());
+    // A window that is still loading is not worth keeping.
+    assert!(decoder.finish().is_err());
+}
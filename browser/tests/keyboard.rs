@@ -0,0 +1,140 @@
+use browser::keyboard::*;
+
+/// Every code the firmware can report, and what it decodes to without
+/// modifiers.
+fn table() -> Vec<(u8, Option<Keycode>)> {
+    let mut table = vec![
+        (0x00, None),
+        (JOY_UP, Some(Keycode::Up)),
+        (JOY_DOWN, Some(Keycode::Down)),
+        (JOY_LEFT, Some(Keycode::Left)),
+        (JOY_RIGHT, Some(Keycode::Right)),
+        (JOY_CENTER, Some(Keycode::Select)),
+        (BTN_LEFT1, Some(Keycode::Button(0))),
+        (BTN_RIGHT1, Some(Keycode::Button(1))),
+        (BACKSPACE, Some(Keycode::Backspace)),
+        (TAB, Some(Keycode::Tab)),
+        (ENTER, Some(Keycode::Enter)),
+        (0x0b, None),
+        (0x0c, None),
+        (CARRIAGE_RETURN, Some(Keycode::Enter)),
+        (0x0e, None),
+        (0x0f, None),
+        (0x10, None),
+        (BTN_LEFT2, Some(Keycode::Button(2))),
+        (BTN_RIGHT2, Some(Keycode::Button(3))),
+    ];
+    table.extend((0x13..=0x19).map(|code| (code, None)));
+    table.extend([
+        (MOD_ALT, None),
+        (MOD_SHIFT_LEFT, None),
+        (MOD_SHIFT_RIGHT, None),
+        (MOD_SYM, None),
+    ]);
+    table.extend((0x1e..=0x1f).map(|code| (code, None)));
+    table.extend((b' '..=b'~').map(|code| (code, Some(Keycode::Char(code as char)))));
+    table.extend((0x7f..=0xff).map(|code| (code, None)));
+    table
+}
+
+#[test]
+fn table_covers_every_code() {
+    let table = table();
+    assert_eq!(table.len(), 256);
+    for (i, (code, _)) in table.iter().enumerate() {
+        assert_eq!(*code as usize, i);
+    }
+}
+
+#[test]
+fn every_code_decodes_as_in_the_table() {
+    for (code, expected) in table() {
+        assert_eq!(decode(code), expected, "code {:#04x}", code);
+    }
+}
+
+#[test]
+fn alt_types_the_symbols_printed_on_the_keys() {
+    let expected = [
+        ("qwertyuiop", "#123()_-+@"),
+        ("asdfghjkl", "*456/:;'\""),
+        ("zxcvbnm$~", "789?!,.`0"),
+    ];
+    let mut keymap = Keymap::new();
+    assert_eq!(keymap.feed(KeyState::Pressed, MOD_ALT), None);
+    for (keys, symbols) in expected {
+        for (key, symbol) in keys.bytes().zip(symbols.chars()) {
+            assert_eq!(
+                keymap.feed(KeyState::Pressed, key),
+                Some(KeyEvent { state: KeyState::Pressed, code: Keycode::Char(symbol) }),
+                "alt+{}",
+                key as char
+            );
+        }
+    }
+    // Keys without a symbol are not affected.
+    assert_eq!(
+        keymap.feed(KeyState::Pressed, b' ').unwrap().code,
+        Keycode::Char(' ')
+    );
+    assert_eq!(keymap.feed(KeyState::Released, MOD_ALT), None);
+    assert_eq!(keymap.feed(KeyState::Pressed, b'w').unwrap().code, Keycode::Char('w'));
+}
+
+#[test]
+fn shift_types_capitals() {
+    let mut keymap = Keymap::new();
+    for shift in [MOD_SHIFT_LEFT, MOD_SHIFT_RIGHT] {
+        keymap.feed(KeyState::Pressed, shift);
+        // Held modifiers stay active.
+        keymap.feed(KeyState::Held, shift);
+        for key in b'a'..=b'z' {
+            assert_eq!(
+                keymap.feed(KeyState::Pressed, key).unwrap().code,
+                Keycode::Char(key.to_ascii_uppercase() as char)
+            );
+        }
+        assert_eq!(keymap.feed(KeyState::Pressed, b'$').unwrap().code, Keycode::Char('$'));
+        keymap.feed(KeyState::Released, shift);
+        assert_eq!(keymap.feed(KeyState::Pressed, b'a').unwrap().code, Keycode::Char('a'));
+    }
+}
+
+#[test]
+fn either_shift_key_keeps_shift_down() {
+    let mut keymap = Keymap::new();
+    keymap.feed(KeyState::Pressed, MOD_SHIFT_LEFT);
+    keymap.feed(KeyState::Pressed, MOD_SHIFT_RIGHT);
+    keymap.feed(KeyState::Released, MOD_SHIFT_LEFT);
+    assert_eq!(keymap.feed(KeyState::Pressed, b'a').unwrap().code, Keycode::Char('A'));
+    keymap.feed(KeyState::Released, MOD_SHIFT_RIGHT);
+    assert_eq!(keymap.feed(KeyState::Pressed, b'a').unwrap().code, Keycode::Char('a'));
+}
+
+#[test]
+fn already_translated_keys_pass_through() {
+    // With the firmware applying modifiers itself, alt+w arrives as '1'
+    // alongside the alt report.
+    let mut keymap = Keymap::new();
+    keymap.feed(KeyState::Pressed, MOD_ALT);
+    assert_eq!(keymap.feed(KeyState::Pressed, b'1').unwrap().code, Keycode::Char('1'));
+    keymap.feed(KeyState::Released, MOD_ALT);
+    keymap.feed(KeyState::Pressed, MOD_SHIFT_LEFT);
+    assert_eq!(keymap.feed(KeyState::Pressed, b'Q').unwrap().code, Keycode::Char('Q'));
+}
+
+#[test]
+fn held_and_released_states_are_kept() {
+    let mut keymap = Keymap::new();
+    for state in [KeyState::Pressed, KeyState::Held, KeyState::Released] {
+        assert_eq!(
+            keymap.feed(state, JOY_DOWN),
+            Some(KeyEvent { state, code: Keycode::Down })
+        );
+        assert_eq!(
+            keymap.feed(state, BACKSPACE),
+            Some(KeyEvent { state, code: Keycode::Backspace })
+        );
+    }
+    assert_eq!(keymap.feed(KeyState::Held, MOD_SYM), None);
+}
@@ -0,0 +1,45 @@
+use browser::page::{Page, PageError};
+
+/// Assembles an SWB program: the text followed by `Text` instructions for
+/// the given (base, range) pairs and a `Stop`.
+fn swb(text: &str, runs: &[(u32, u32)]) -> Vec<u8> {
+    let mut swb = (text.len() as u64).to_le_bytes().to_vec();
+    swb.extend_from_slice(text.as_bytes());
+    for &(base, range) in runs {
+        swb.push(1);
+        swb.extend_from_slice(&base.to_le_bytes());
+        swb.extend_from_slice(&range.to_le_bytes());
+    }
+    swb.extend_from_slice(&[0; 9]);
+    swb
+}
+
+#[test]
+fn accepts_valid_pages() {
+    let page = Page::parse(&swb("hello world", &[(0, 5), (6, 5)]), Vec::new()).unwrap();
+    assert_eq!(page.program.code.len(), 3);
+}
+
+#[test]
+fn rejects_text_past_the_end() {
+    let err = Page::parse(&swb("hello", &[(0, 5), (3, 5)]), Vec::new()).err();
+    assert_eq!(err, Some(PageError::Address { index: 1 }));
+}
+
+#[test]
+fn rejects_text_splitting_a_character() {
+    let err = Page::parse(&swb("héllo", &[(0, 2)]), Vec::new()).err();
+    assert_eq!(err, Some(PageError::Address { index: 0 }));
+}
+
+#[test]
+fn rejects_addresses_that_overflow() {
+    let err = Page::parse(&swb("hello", &[(u32::MAX, u32::MAX)]), Vec::new()).err();
+    assert_eq!(err, Some(PageError::Address { index: 0 }));
+}
+
+#[test]
+fn rejects_truncated_programs() {
+    let err = Page::parse(&[5, 0, 0, 0, 0, 0, 0, 0, b'a'], Vec::new()).err();
+    assert!(matches!(err, Some(PageError::Parse(_))));
+}
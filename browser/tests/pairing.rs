@@ -0,0 +1,80 @@
+use browser::framebuffer::Framebuffer;
+use browser::keyboard::Keycode;
+use browser::pairing::{
+    add_bond, decode_bonds, draw_entry, draw_passkey, edit_passkey, encode_bonds, passkey_digits,
+    Bond, MAX_BONDS,
+};
+
+fn bond(server: u8) -> Bond {
+    Bond {
+        address_type: 1,
+        address: [server, 0x28, 0xec, 0xcf, 0x7f, 0xe8],
+        irk: [server; 16],
+        ltk: [0xa5; 16],
+        flags: 0b101,
+    }
+}
+
+#[test]
+fn bonds_survive_encoding() {
+    let bonds = vec![bond(1), bond(2)];
+    assert_eq!(decode_bonds(&encode_bonds(&bonds)), bonds);
+    // A bond cut short by a damaged setting is dropped.
+    let encoded = encode_bonds(&bonds);
+    assert_eq!(decode_bonds(&encoded[..encoded.len() - 1]), [bond(1)]);
+    assert!(decode_bonds(&[]).is_empty());
+}
+
+#[test]
+fn the_oldest_bond_is_forgotten() {
+    let mut bonds = Vec::new();
+    for server in 0..MAX_BONDS as u8 + 1 {
+        add_bond(&mut bonds, bond(server));
+    }
+    assert_eq!(bonds.len(), MAX_BONDS);
+    assert_eq!(bonds[0], bond(MAX_BONDS as u8));
+    assert!(!bonds.contains(&bond(0)));
+
+    // Bonding again with a server moves it to the front.
+    let renewed = Bond { ltk: [0x5a; 16], ..bond(2) };
+    add_bond(&mut bonds, renewed);
+    assert_eq!(bonds.len(), MAX_BONDS);
+    assert_eq!(bonds[0], renewed);
+    let same = bonds.iter().filter(|other| other.address == bond(2).address);
+    assert_eq!(same.count(), 1);
+
+    // A public and a random address with the same bytes are different servers.
+    let other_type = Bond { address_type: 0, ..bond(3) };
+    add_bond(&mut bonds, other_type);
+    assert_eq!(bonds[0], other_type);
+    assert!(bonds.contains(&bond(3)));
+}
+
+#[test]
+fn passkeys_are_six_digits() {
+    let mut passkey = String::new();
+    for c in "12a3456789".chars() {
+        assert!(!edit_passkey(&mut passkey, Keycode::Char(c)));
+    }
+    assert_eq!(passkey, "123456");
+    assert!(!edit_passkey(&mut passkey, Keycode::Backspace));
+    // Incomplete passkeys can't be submitted.
+    assert!(!edit_passkey(&mut passkey, Keycode::Enter));
+    assert!(!edit_passkey(&mut passkey, Keycode::Char('0')));
+    assert!(edit_passkey(&mut passkey, Keycode::Select));
+    assert_eq!(passkey_digits(&passkey), Some(*b"123450"));
+    assert_eq!(passkey_digits("123"), None);
+}
+
+#[test]
+fn prompts_show_the_passkey() {
+    let mut empty = Framebuffer::new();
+    draw_entry("28:7F:CF:EC:28:26", "", &mut empty).unwrap();
+    let mut typed = Framebuffer::new();
+    draw_entry("28:7F:CF:EC:28:26", "12", &mut typed).unwrap();
+    assert!(empty.as_bytes() != typed.as_bytes());
+
+    let mut shown = Framebuffer::new();
+    draw_passkey("28:7F:CF:EC:28:26", "123456", &mut shown).unwrap();
+    assert!(shown.as_bytes() != Framebuffer::new().as_bytes());
+}
@@ -0,0 +1,102 @@
+//! Renders the example pages into a framebuffer and compares the result with
+//! the golden images in `tests/golden`, stored as binary PBM files.
+//!
+//! After an intended change to the rendering, regenerate them with
+//!
+//! ```sh
+//! UPDATE_GOLDENS=1 cargo test -p browser --test render
+//! ```
+//!
+//! and look at the new images before committing them. When a comparison
+//! fails the rendered image is written next to the golden with an `.actual`
+//! suffix.
+
+use std::fs;
+use std::path::PathBuf;
+
+use browser::framebuffer::Framebuffer;
+use browser::page::Page;
+use browser::status::{Connection, Status};
+use browser::view::{View, HEIGHT, STATUS_HEIGHT, WIDTH};
+
+fn pbm(screen: &Framebuffer) -> Vec<u8> {
+    let mut pbm = format!("P4\n{} {}\n", WIDTH, HEIGHT).into_bytes();
+    pbm.extend_from_slice(screen.as_bytes());
+    pbm
+}
+
+fn load(name: &str) -> Page {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../client").join(name);
+    let swb = fs::read(path).unwrap();
+    Page::parse(&swb, Vec::new()).unwrap()
+}
+
+fn check(name: &str, start_lines: &[i32]) {
+    let page = load(&format!("{}.swb", name)).with_title(name);
+    let golden_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let update = std::env::var_os("UPDATE_GOLDENS").is_some();
+    let mut failed = Vec::new();
+
+    for &start_line in start_lines {
+        let mut screen = Framebuffer::new();
+        let mut view = View::new(start_line);
+        view.draw(&page, &mut screen).unwrap();
+        let status = Status {
+            title: page.title.clone(),
+            position: Some(view.position()),
+            ..Status::new(Connection::Connected)
+        };
+        status.draw(&mut screen).unwrap();
+        let actual = pbm(&screen);
+
+        let golden = golden_dir.join(format!("{}-{}.pbm", name, start_line));
+        let mismatch = golden.with_extension("pbm.actual");
+        if update {
+            fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+        match fs::read(&golden) {
+            Ok(expected) if expected == actual => {
+                let _ = fs::remove_file(&mismatch);
+            }
+            _ => {
+                fs::write(&mismatch, &actual).unwrap();
+                failed.push(golden.display().to_string());
+            }
+        }
+    }
+    assert!(failed.is_empty(), "rendering differs from {:?}", failed);
+}
+
+#[test]
+fn renders_ab() {
+    check("ab", &[0, 1, 5]);
+}
+
+#[test]
+fn renders_ns() {
+    check("ns", &[0, 1, 5, 10]);
+}
+
+#[test]
+fn renders_rust_datatypes() {
+    check("rust_datatypes", &[0, 1, 5, 50, 200]);
+}
+
+#[test]
+fn scrolling_by_one_line_drops_the_first_line() {
+    let page = load("rust_datatypes.swb");
+    let mut top = Framebuffer::new();
+    View::new(0).draw(&page, &mut top).unwrap();
+    let mut scrolled = Framebuffer::new();
+    View::new(1).draw(&page, &mut scrolled).unwrap();
+
+    // Every line of the example is set in the same font, so scrolling by one
+    // line moves everything up by one line height.
+    let line_height = 13 + 2;
+    for y in 0..HEIGHT - STATUS_HEIGHT - 2 * line_height {
+        for x in 0..WIDTH {
+            assert_eq!(scrolled.get(x, y), top.get(x, y + line_height), "pixel {},{}", x, y);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use browser::framebuffer::Framebuffer;
+use browser::keyboard::Keycode;
+use browser::servers::{advertises_service, format_address, local_name, Picker, PAGE_SERVICE};
+
+const SERVER: [u8; 6] = [0x26, 0x28, 0xec, 0xcf, 0x7f, 0x28];
+const OTHER: [u8; 6] = [1, 2, 3, 4, 5, 6];
+
+/// Advertising data of the server: flags and the page service.
+const ADVERTISEMENT: &[u8] = &[2, 0x01, 0x06, 3, 0x03, 0xed, 0xfe];
+/// Its scan response, with the name.
+const SCAN_RESPONSE: &[u8] = &[6, 0x09, b'b', b'a', b'm', b'b', b'i'];
+
+#[test]
+fn advertising_data_is_parsed() {
+    assert!(advertises_service(ADVERTISEMENT, PAGE_SERVICE));
+    assert!(!advertises_service(SCAN_RESPONSE, PAGE_SERVICE));
+    assert!(!advertises_service(&[3, 0x03, 0x0d, 0xf0], PAGE_SERVICE));
+    let full = [
+        17, 0x07, 0xfb, 0x34, 0x9b, 0x5f, 0x80, 0x00, 0x00, 0x80, 0x00, 0x10, 0x00, 0x00, 0xed,
+        0xfe, 0x00, 0x00,
+    ];
+    assert!(advertises_service(&full, PAGE_SERVICE));
+    assert_eq!(local_name(SCAN_RESPONSE), Some("bambi"));
+    assert_eq!(local_name(ADVERTISEMENT), None);
+    // Structures running past the end are not read.
+    assert!(!advertises_service(&[4, 0x03, 0xed, 0xfe], PAGE_SERVICE));
+}
+
+#[test]
+fn addresses_are_written_most_significant_byte_first() {
+    assert_eq!(format_address(&SERVER), "28:7F:CF:EC:28:26");
+}
+
+#[test]
+fn servers_are_listed_once_and_named_by_scan_responses() {
+    let mut picker = Picker::new(None);
+    assert!(!picker.report(OTHER, 0, -70, SCAN_RESPONSE));
+    assert!(picker.report(SERVER, 0, -60, ADVERTISEMENT));
+    assert!(!picker.report(SERVER, 0, -50, ADVERTISEMENT));
+    assert!(picker.report(SERVER, 0, -50, SCAN_RESPONSE));
+    assert_eq!(picker.servers().len(), 1);
+    let server = &picker.servers()[0];
+    assert_eq!(server.name.as_deref(), Some("bambi"));
+    assert_eq!(server.rssi, -50);
+}
+
+#[test]
+fn keys_choose_a_server() {
+    let mut picker = Picker::new(None);
+    assert_eq!(picker.press(Keycode::Enter), None);
+    picker.report(OTHER, 1, -70, ADVERTISEMENT);
+    picker.report(SERVER, 0, -60, ADVERTISEMENT);
+    picker.press(Keycode::Down);
+    picker.press(Keycode::Down);
+    assert_eq!(picker.selected().unwrap().address, SERVER);
+    picker.press(Keycode::Char('i'));
+    let chosen = picker.press(Keycode::Select).unwrap();
+    assert_eq!(chosen.address, OTHER);
+    assert_eq!(chosen.address_type, 1);
+}
+
+#[test]
+fn the_server_used_before_is_selected() {
+    let mut picker = Picker::new(Some(SERVER));
+    picker.report(OTHER, 0, -70, ADVERTISEMENT);
+    picker.report(SERVER, 0, -60, ADVERTISEMENT);
+    assert_eq!(picker.press(Keycode::Enter).unwrap().address, SERVER);
+}
+
+#[test]
+fn the_list_is_drawn() {
+    let mut picker = Picker::new(None);
+    let mut empty = Framebuffer::new();
+    picker.draw(&mut empty).unwrap();
+    picker.report(SERVER, 0, -60, ADVERTISEMENT);
+    let mut listed = Framebuffer::new();
+    picker.draw(&mut listed).unwrap();
+    assert!(empty.as_bytes() != listed.as_bytes());
+}
@@ -0,0 +1,39 @@
+use browser::status::{Connection, Status};
+
+fn texts(status: &Status) -> Vec<String> {
+    status.fields().into_iter().map(|field| field.text).collect()
+}
+
+#[test]
+fn shows_title_and_state() {
+    let status = Status {
+        title: "Algebraic data types".to_string(),
+        progress: Some(42),
+        position: Some((3, 120)),
+        ..Status::new(Connection::Connected)
+    };
+    assert_eq!(texts(&status), ["Algebraic data types", "42%  3/120  BLE"]);
+    assert!(status.fields()[0].bold);
+}
+
+#[test]
+fn errors_and_lost_connections_show() {
+    let status = Status {
+        error: true,
+        ..Status::new(Connection::Disconnected)
+    };
+    assert_eq!(texts(&status), ["ERROR  no BLE"]);
+}
+
+#[test]
+fn long_titles_are_cut_short() {
+    let status = Status {
+        title: "word ".repeat(30),
+        position: Some((1, 10)),
+        ..Status::new(Connection::Connecting)
+    };
+    let fields = status.fields();
+    assert!(fields[0].text.ends_with('~'));
+    assert!(fields[0].bounds.top_left.x + (fields[0].bounds.size.width as i32) < fields[1].bounds.top_left.x);
+    assert_eq!(fields[1].text, "1/10  BLE...");
+}
@@ -0,0 +1,230 @@
+use browser::decode::PageDecoder;
+use browser::framebuffer::Framebuffer;
+use browser::keyboard::Keycode;
+use browser::page::Page;
+use browser::view::{action_for, apply_on_error, apply_while_loading, Action, Navigate, View};
+use browser::HEAP_SIZE;
+use protocol::page::{encode_page, Link};
+use swb_shared::Instruction;
+
+fn swb(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/../client/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+fn load(name: &str) -> Page {
+    Page::parse(&swb(name), Vec::new()).unwrap()
+}
+
+/// Every third text run of the page as a link.
+fn links(name: &str) -> Vec<Link> {
+    let page = load(name);
+    let texts = page.program.code.iter().filter_map(|instr| match instr {
+        Instruction::Text(address) => Some(address),
+        _ => None,
+    });
+    texts
+        .step_by(3)
+        .map(|address| Link {
+            start: address.base.0 as u32,
+            len: address.range as u32,
+            href: "ab.html".to_string(),
+        })
+        .collect()
+}
+
+/// Draws the view from scratch.
+fn draw(view: &mut View, page: &Page) -> Framebuffer {
+    let mut screen = Framebuffer::new();
+    view.invalidate();
+    view.draw(page, &mut screen).unwrap();
+    screen
+}
+
+#[test]
+fn paging_moves_by_a_screen() {
+    let page = load("rust_datatypes.swb");
+    let mut view = View::new(0);
+    draw(&mut view, &page);
+    view.apply(&page, Action::PageDown);
+    let first = view.start_line();
+    assert!(first > 5);
+
+    draw(&mut view, &page);
+    view.apply(&page, Action::PageDown);
+    assert_eq!(view.start_line(), 2 * first);
+
+    view.apply(&page, Action::PageUp);
+    assert_eq!(view.start_line(), first);
+    view.apply(&page, Action::PageUp);
+    assert_eq!(view.start_line(), 0);
+    view.apply(&page, Action::PageUp);
+    assert_eq!(view.start_line(), 0);
+}
+
+#[test]
+fn scrolling_stops_at_the_end() {
+    let page = load("ab.swb");
+    let mut view = View::new(0);
+    view.apply(&page, Action::Bottom);
+    let last = view.start_line();
+    assert!(last > 0);
+    let bottom = draw(&mut view, &page);
+
+    view.apply(&page, Action::ScrollDown);
+    assert_eq!(view.start_line(), last);
+    view.apply(&page, Action::PageDown);
+    assert_eq!(view.start_line(), last);
+    assert!(draw(&mut view, &page) == bottom);
+
+    view.apply(&page, Action::Top);
+    assert_eq!(view.start_line(), 0);
+}
+
+#[test]
+fn the_last_screen_is_full() {
+    let page = load("rust_datatypes.swb");
+    let mut view = View::new(0);
+    view.apply(&page, Action::Bottom);
+    draw(&mut view, &page);
+    assert!(!view.is_full());
+
+    // One line further up and the last line no longer fits.
+    let mut view = View::new(view.start_line() - 1);
+    draw(&mut view, &page);
+    assert!(view.is_full());
+}
+
+#[test]
+fn paging_keys() {
+    assert_eq!(action_for(Keycode::Char(' ')), Some(Action::PageDown));
+    assert_eq!(action_for(Keycode::Char('K')), Some(Action::PageDown));
+    assert_eq!(action_for(Keycode::Char('I')), Some(Action::PageUp));
+    assert_eq!(action_for(Keycode::Button(3)), Some(Action::PageDown));
+    assert_eq!(action_for(Keycode::Char('t')), Some(Action::Top));
+    assert_eq!(action_for(Keycode::Char('e')), Some(Action::Bottom));
+}
+
+#[test]
+fn servers_can_be_picked_from_pages() {
+    assert_eq!(action_for(Keycode::Char('s')), Some(Action::PickServer));
+    assert_eq!(apply_on_error(Action::PickServer), Some(Navigate::PickServer));
+}
+
+#[test]
+fn only_failed_loads_are_retried() {
+    assert_eq!(apply_on_error(Action::FollowLink), Some(Navigate::Reload));
+    assert_eq!(apply_while_loading(Action::FollowLink), None);
+    assert_eq!(apply_while_loading(Action::Back), Some(Navigate::Back));
+    assert_eq!(apply_while_loading(Action::EditAddress), Some(Navigate::EditAddress));
+}
+
+#[test]
+fn redraws_match_drawing_from_scratch() {
+    let page = Page::parse(&swb("ns.swb"), links("ns.swb")).unwrap();
+    let mut view = View::new(0);
+    let mut screen = Framebuffer::new();
+    view.draw(&page, &mut screen).unwrap();
+    let actions = [
+        Action::NextLink,
+        Action::NextLink,
+        Action::NextLink,
+        Action::PrevLink,
+        Action::ScrollDown,
+        Action::NextLink,
+        Action::PageDown,
+        Action::NextLink,
+        Action::PrevLink,
+        Action::Top,
+        Action::PrevLink,
+    ];
+    for action in actions {
+        view.apply(&page, action);
+        view.draw(&page, &mut screen).unwrap();
+        assert!(screen == draw(&mut view, &page), "after {:?}", action);
+    }
+}
+
+#[test]
+fn growing_pages_are_drawn_as_they_grow() {
+    let program = swb("ns.swb");
+    let links = links("ns.swb");
+    let payload = encode_page("NS", &program, &links).unwrap();
+    let mut decoder = PageDecoder::new(HEAP_SIZE / 2);
+    let mut view = View::new(0);
+    let mut screen = Framebuffer::new();
+    for data in payload.chunks(50) {
+        decoder.push(data).unwrap();
+        if let Some(page) = decoder.page() {
+            view.draw(page, &mut screen).unwrap();
+        }
+    }
+    let page = decoder.finish().unwrap();
+    view.draw(&page, &mut screen).unwrap();
+    let whole = Page::parse(&program, links).unwrap();
+    assert!(screen == draw(&mut View::new(0), &whole));
+}
+
+#[test]
+fn replaced_pages_are_laid_out_again() {
+    let page = load("rust_datatypes.swb");
+    let mut view = View::new(0);
+    draw(&mut view, &page);
+    view.apply(&page, Action::Bottom);
+    let other = load("ab.swb");
+    view.apply(&other, Action::Top);
+    view.replace();
+    let mut screen = Framebuffer::new();
+    view.draw(&other, &mut screen).unwrap();
+
+    let mut fresh = View::new(0);
+    assert!(screen == draw(&mut fresh, &other));
+    assert_eq!(view.position(), fresh.position());
+}
+
+/// Loads the window of the page in `decoder` that `view` needs, the way the
+/// client does. Returns whether there was one to load.
+fn load_wanted(view: &View, decoder: &mut PageDecoder, payload: &[u8]) -> bool {
+    let Some(first) = decoder.page().and_then(|page| view.wanted(page)) else {
+        return false;
+    };
+    decoder.seek(first);
+    while let Some(range) = decoder.wanted().unwrap() {
+        let range = range.start as usize..range.end as usize;
+        decoder.push_range(&payload[range]).unwrap();
+    }
+    true
+}
+
+#[test]
+fn long_pages_are_read_a_window_at_a_time() {
+    let program = swb("ns.swb");
+    let links = links("ns.swb");
+    let payload = encode_page("NS", &program, &links).unwrap();
+    let whole = Page::parse(&program, links).unwrap();
+    let mut decoder = PageDecoder::new(HEAP_SIZE / 2);
+    decoder.push(&payload).unwrap();
+
+    let mut view = View::new(0);
+    let mut screen = Framebuffer::new();
+    let mut loads = 0;
+    let mut step = |view: &mut View, action: Action| {
+        loop {
+            view.draw(decoder.page().unwrap(), &mut screen).unwrap();
+            if !load_wanted(view, &mut decoder, &payload) {
+                break;
+            }
+            loads += 1;
+        }
+        let start_line = view.start_line();
+        assert!(screen == draw(&mut View::new(start_line), &whole), "at line {}", start_line);
+        view.apply(decoder.page().unwrap(), action);
+        start_line
+    };
+
+    while step(&mut view, Action::PageDown) != view.start_line() {}
+    let mut bottom = View::new(0);
+    bottom.apply(&whole, Action::Bottom);
+    assert_eq!(view.start_line(), bottom.start_line());
+    while step(&mut view, Action::PageUp) != 0 {}
+    assert!(loads > 2);
+}
@@ -0,0 +1,64 @@
+use browser::wrap::{wrap, Line};
+
+fn lines(text: &str, columns: usize) -> Vec<String> {
+    wrap(text, columns)
+        .map(|line| {
+            assert!(line.width() <= columns, "{:?} is wider than {}", line, columns);
+            let mut s = line.text.to_string();
+            if line.hyphen {
+                s.push('-');
+            }
+            s
+        })
+        .collect()
+}
+
+#[test]
+fn short_text_is_one_line() {
+    assert_eq!(lines("Errata:", 20), ["Errata:"]);
+    assert_eq!(lines("exactly ten", 11), ["exactly ten"]);
+}
+
+#[test]
+fn empty_text_is_one_empty_line() {
+    assert_eq!(
+        wrap("", 10).collect::<Vec<_>>(),
+        [Line { text: "", hyphen: false }]
+    );
+}
+
+#[test]
+fn breaks_at_word_boundaries() {
+    assert_eq!(
+        lines("Het bewijs van het pompend lemma voor reguliere talen bevat een fout!", 20),
+        ["Het bewijs van het", "pompend lemma voor", "reguliere talen", "bevat een fout!"]
+    );
+    // A space right after a full line is where it breaks.
+    assert_eq!(lines("aaaa bbbb", 4), ["aaaa", "bbbb"]);
+    assert_eq!(lines("aaaa  bbbb  ", 5), ["aaaa", "bbbb"]);
+}
+
+#[test]
+fn long_words_are_hyphenated() {
+    assert_eq!(
+        lines("see https://example.com/a/very/long/path ok", 12),
+        ["see", "https://exa-", "mple.com/a/-", "very/long/p-", "ath ok"]
+    );
+    assert_eq!(lines("abcdef", 1), ["a", "b", "c", "d", "e", "f"]);
+}
+
+#[test]
+fn counts_characters_not_bytes() {
+    assert_eq!(lines("één twee drie", 8), ["één twee", "drie"]);
+    assert_eq!(lines("ééééé", 3), ["éé-", "ééé"]);
+}
+
+#[test]
+fn every_character_is_kept() {
+    let text = std::fs::read_to_string(format!("{}/../client/ns.html", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    for columns in [1, 2, 7, 65] {
+        let joined: String = wrap(&text, columns).map(|line| line.text).collect();
+        let expected: String = text.chars().filter(|c| *c != ' ').collect();
+        assert_eq!(joined.replace(' ', ""), expected, "{} columns", columns);
+    }
+}
@@ -2,6 +2,15 @@ MEMORY
 {
   /* NOTE 1 K = 1 KiBi = 1024 bytes */
   /* These values correspond to the NRF52840 with Softdevices S140 7.0.1 */
-  FLASH : ORIGIN = 0x00027000, LENGTH = 868K
+  FLASH : ORIGIN = 0x00027000, LENGTH = 724K
+  /* Page cache and settings store, see src/flash.rs. Not part of the
+     program, so flashing new firmware leaves them alone. */
+  CACHE : ORIGIN = 0x000dc000, LENGTH = 128K
+  SETTINGS : ORIGIN = 0x000fc000, LENGTH = 16K
   RAM : ORIGIN = 0x2000d488, LENGTH = 128K
 }
+
+__cache_start = ORIGIN(CACHE);
+__cache_end = ORIGIN(CACHE) + LENGTH(CACHE);
+__settings_start = ORIGIN(SETTINGS);
+__settings_end = ORIGIN(SETTINGS) + LENGTH(SETTINGS);
@@ -0,0 +1,70 @@
+//! Address bar for typing a URL to navigate to.
+//!
+//! While the address bar is open the keyboard driver hands every key to
+//! [`type_key`] instead of pressing toekomst keys, which also keeps the
+//! scroll and link bindings quiet while typing.
+
+use alloc::string::String;
+use browser::address::{self, edit, Edit};
+use browser::keyboard::Keycode;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::channel::Channel;
+use toekomst::display::{disp, request_redraw};
+
+use crate::pairing;
+
+static EDITING: AtomicBool = AtomicBool::new(false);
+static TYPED: Channel<ThreadModeRawMutex, Keycode, 16> = Channel::new();
+
+/// Whether key presses should go to the address bar.
+pub fn is_editing() -> bool {
+    EDITING.load(Ordering::Relaxed)
+}
+
+/// Called by the keyboard driver for every key pressed while editing.
+pub fn type_key(code: Keycode) {
+    let _ = TYPED.try_send(code);
+}
+
+async fn draw(current: &str, url: &str) {
+    {
+        let mut dp = disp().await;
+        dp.clear();
+        let _ = address::draw(current, url, &mut *dp);
+    }
+    request_redraw();
+}
+
+/// Lets the user edit `current` and returns the URL to navigate to, or
+/// `None` if they cancelled or submitted an empty address.
+pub async fn edit_address(current: &str) -> Option<String> {
+    // Drop anything typed before the address bar opened.
+    while TYPED.try_recv().is_ok() {}
+    EDITING.store(true, Ordering::Relaxed);
+
+    let mut url = String::from(current);
+    loop {
+        draw(current, &url).await;
+        match select(TYPED.recv(), pairing::requested()).await {
+            Either::First(code) => match edit(&mut url, code) {
+                Edit::Typing => {}
+                Edit::Submit => break,
+                Edit::Cancel => {
+                    url.clear();
+                    break;
+                }
+            },
+            Either::Second(prompt) => pairing::prompt(prompt).await,
+        }
+    }
+
+    EDITING.store(false, Ordering::Relaxed);
+    if url.is_empty() {
+        None
+    } else {
+        Some(url)
+    }
+}
@@ -0,0 +1,177 @@
+//! Pages kept in flash, so they show straight away and also while the server
+//! is out of reach.
+//!
+//! They live in the `CACHE` [region](crate::flash), in a
+//! [`browser::cache::Cache`]. Pages are written while they download, see
+//! [`fetch`](crate::fetch), and checked against the server with their ETag
+//! whenever they are shown again. The writes are queued for a task of their
+//! own, [`write_pages`], so flash never holds up the download.
+
+use alloc::format;
+use alloc::string::String;
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use browser::cache::{Cache, CacheError};
+use browser::decode::PageDecoder;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::mutex::Mutex;
+
+use crate::fetch::{Frame, MAX_PAGE_LEN};
+use crate::flash::Region;
+
+static CACHE: Mutex<ThreadModeRawMutex, Option<Cache<Region>>> = Mutex::new(None);
+
+/// Opens the cache. Until it is called, or if opening failed, nothing is
+/// cached.
+pub async fn init() {
+    match Cache::open(Region::cache()) {
+        Ok(cache) => {
+            info!("{} pages cached", cache.urls().count());
+            *CACHE.lock().await = Some(cache);
+        }
+        Err(err) => error!("Opening the cache failed: {}", format!("{:?}", err).as_str()),
+    }
+}
+
+/// Decodes the cached copy of `url`, if there is one, and returns it with
+/// its ETag.
+pub async fn load(url: &str) -> Option<(PageDecoder, u32)> {
+    let mut cache = CACHE.lock().await;
+    let cache = cache.as_mut()?;
+    let etag = cache.etag(url)?;
+    let mut decoder = PageDecoder::new(MAX_PAGE_LEN);
+    let mut failed = None;
+    let read = cache.read(url, |data| match decoder.push(data) {
+        Ok(()) => true,
+        Err(err) => {
+            failed = Some(err);
+            false
+        }
+    });
+    match (read, failed) {
+        (Ok(true), None) if decoder.is_complete() => {
+            if let Err(err) = cache.touch(url).await {
+                warn!("Touching {} failed: {}", url, format!("{:?}", err).as_str());
+            }
+            info!("Loaded {} from the cache", url);
+            Some((decoder, etag))
+        }
+        (Err(err), _) => {
+            warn!("Reading {} failed: {}", url, format!("{:?}", err).as_str());
+            None
+        }
+        // A copy that can't be shown is no use keeping.
+        (_, failed) => {
+            if let Some(err) = failed {
+                warn!("Cached {} is broken: {}", url, format!("{}", err).as_str());
+            }
+            let _ = cache.remove(url).await;
+            None
+        }
+    }
+}
+
+/// Loads the part of `url` that `decoder` [wants](PageDecoder::wanted) from
+/// the cache, if it holds the copy with `etag`. Returns whether it did.
+pub async fn load_range(url: &str, etag: u32, decoder: &RefCell<PageDecoder>) -> bool {
+    let mut cache = CACHE.lock().await;
+    let Some(cache) = cache.as_mut() else {
+        return false;
+    };
+    if cache.etag(url) != Some(etag) {
+        return false;
+    }
+    loop {
+        let range = match decoder.borrow().wanted() {
+            Ok(Some(range)) => range,
+            Ok(None) => return true,
+            Err(_) => return false,
+        };
+        let read = cache.read_range(url, range, |data| {
+            decoder.borrow_mut().push_range(data).is_ok()
+        });
+        match read {
+            Ok(true) => {}
+            Ok(false) => return false,
+            Err(err) => {
+                warn!("Reading {} failed: {}", url, format!("{:?}", err).as_str());
+                return false;
+            }
+        }
+    }
+}
+
+fn log_error(what: &str, err: CacheError<nrf_softdevice::FlashError>) {
+    match err {
+        // The page is not being cached, because starting failed.
+        CacheError::NotWriting => {}
+        err => warn!("{} failed: {}", what, format!("{:?}", err).as_str()),
+    }
+}
+
+/// A write to the page being cached, queued for [`write_pages`].
+enum Write {
+    Begin(String, usize),
+    Append(Frame),
+    Commit,
+}
+
+/// Writes waiting for flash. Flash is slower than the radio at times, so
+/// the fetcher only queues them and keeps receiving.
+static WRITES: Channel<ThreadModeRawMutex, Write, 8> = Channel::new();
+
+/// Set when a write of the page being cached did not fit in [`WRITES`]. The
+/// page is not cached then, the copy there was stays.
+static DROPPED: AtomicBool = AtomicBool::new(false);
+
+fn queue(write: Write) {
+    if DROPPED.load(Ordering::Relaxed) {
+        return;
+    }
+    if WRITES.try_send(write).is_err() {
+        warn!("Cache writes queue full, not caching the page");
+        DROPPED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Starts caching the `len` byte page at `url`.
+pub fn begin(url: &str, len: usize) {
+    DROPPED.store(false, Ordering::Relaxed);
+    queue(Write::Begin(String::from(url), len));
+}
+
+/// Adds the next part of the page being cached.
+pub fn append(data: &[u8]) {
+    match Frame::from_slice(data) {
+        Ok(data) => queue(Write::Append(data)),
+        Err(()) => DROPPED.store(true, Ordering::Relaxed),
+    }
+}
+
+/// Finishes caching the page, once it all arrived.
+pub fn commit() {
+    queue(Write::Commit);
+}
+
+/// Writes the pages queued by [`begin`], [`append`] and [`commit`] to flash.
+/// A page that is not committed is abandoned by the next [`begin`].
+#[embassy_executor::task]
+pub async fn write_pages() {
+    loop {
+        let write = WRITES.recv().await;
+        let mut cache = CACHE.lock().await;
+        let Some(cache) = cache.as_mut() else {
+            continue;
+        };
+        let result = match write {
+            Write::Begin(url, len) => cache.begin(&url, len).await,
+            Write::Append(data) => cache.append(&data).await,
+            Write::Commit => cache.commit().await.map(|_| ()),
+        };
+        if let Err(err) = result {
+            log_error("Caching", err);
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! Fetches pages from the server over the BLE connection.
+//!
+//! The server exposes a single characteristic: requests are written to it
+//! without response and the reply comes back as notifications, both as
+//! frames of the shared [`protocol`]. Pages are decoded fragment by fragment
+//! so they can be shown before they are complete. When the connection drops
+//! halfway through a page, the rest of it is asked for again once the
+//! [`supervisor`](crate::supervisor) has reconnected. Frames lost because
+//! [`FRAMES`] overflowed are asked for again the same way.
+//!
+//! Pages are [cached](crate::cache) as they arrive. A page shown from the
+//! cache is revalidated: the server only sends it again if its ETag changed.
+//!
+//! Only a window of a long page is kept in memory. When the user scrolls
+//! out of it, [`fetch_window`] loads the part they went to, from the cache
+//! if it has the page and with [`MessageType::Range`] requests otherwise.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use browser::decode::PageDecoder;
+use browser::page::PageError;
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::channel::Channel;
+use nrf_softdevice::ble::gatt_client;
+use nrf_softdevice::ble::Connection;
+use protocol::{
+    decode_error, encode_range, encode_resume, encode_revalidate, fragments, Chunk, Crc32,
+    MessageType, Reassembler, Status,
+};
+use toekomst::notify::Notify;
+
+use crate::{cache, supervisor};
+
+/// ATT MTU the softdevice is configured with, the most a connection can
+/// agree on.
+pub const ATT_MTU: u16 = 256;
+
+/// Largest frame we exchange, the ATT MTU minus the ATT header. Frames we
+/// write are cut to the MTU of the connection instead, see
+/// [`supervisor::frame_len`].
+pub const FRAME_MTU: usize = ATT_MTU as usize - 3;
+
+/// Heap budget for the part of a page kept in memory, see [`PageDecoder`].
+pub const MAX_PAGE_LEN: usize = crate::HEAP_SIZE / 2;
+
+/// Largest page accepted. Pages are decoded as they arrive rather than
+/// buffered, so this only keeps out nonsense lengths.
+const MAX_PAYLOAD_LEN: usize = 1024 * 1024;
+
+pub type Frame = heapless::Vec<u8, FRAME_MTU>;
+
+/// Notifications are received in a callback, they are queued here until the
+/// fetcher gets to them.
+pub static FRAMES: Channel<ThreadModeRawMutex, Frame, 16> = Channel::new();
+
+/// Set when the page in the decoder was replaced by a newer one.
+static REPLACED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the page being fetched started over since the last call, so what
+/// is on screen of it has to be drawn again from scratch.
+pub fn take_replaced() -> bool {
+    REPLACED.swap(false, Ordering::Relaxed)
+}
+
+#[nrf_softdevice::gatt_client(uuid = "feed")]
+pub struct PageServiceClient {
+    #[characteristic(uuid = "f00d", write, write_without_response, notify)]
+    page: Frame,
+}
+
+pub enum FetchError {
+    Protocol(protocol::Error),
+    /// The server answered with an error message.
+    Server(Option<Status>, String),
+    /// The server answered with something other than a page.
+    Unexpected(MessageType),
+    /// The page arrived but can't be shown.
+    Page(PageError),
+}
+
+/// What the server answered.
+pub enum Fetched {
+    /// A page, now in the decoder.
+    Page,
+    /// The copy the decoder holds is still current.
+    NotModified,
+}
+
+impl core::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FetchError::Protocol(err) => write!(f, "{}", err),
+            FetchError::Server(status, reason) => write!(f, "{:?}: {}", status, reason),
+            FetchError::Unexpected(kind) => write!(f, "unexpected {:?} message", kind),
+            FetchError::Page(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Forwards notifications from the server to [`FRAMES`] until the connection
+/// drops.
+pub async fn receive_frames(conn: &Connection, client: &PageServiceClient) {
+    gatt_client::run(conn, client, |event| match event {
+        PageServiceClientEvent::PageNotification(frame) => {
+            if FRAMES.try_send(frame).is_err() {
+                warn!("Frame queue full, dropping notification");
+            }
+        }
+    })
+    .await;
+}
+
+/// Sends a message to the server over the current connection.
+async fn send(kind: MessageType, payload: &[u8]) {
+    for frame in fragments(kind, payload, supervisor::frame_len()) {
+        supervisor::send(unwrap!(Frame::from_slice(&frame))).await;
+    }
+}
+
+/// Requests `url` and feeds the page into `decoder` as it arrives, notifying
+/// `progress` after every fragment.
+///
+/// With an `etag`, `decoder` holds a copy of the page already, which is only
+/// replaced if the server has a different one.
+///
+/// Waits for a connection if there is none. If the connection drops before
+/// the page is complete, the page is resumed from the last fragment that
+/// arrived intact once the server is back, and right away if fragments went
+/// missing.
+pub async fn fetch(
+    url: &str,
+    etag: Option<u32>,
+    decoder: &RefCell<PageDecoder>,
+    progress: &Notify<()>,
+) -> Result<Fetched, FetchError> {
+    info!("Fetching {}", url);
+    // Bytes of the page received intact, over all connections, and their
+    // checksum for the server to find the page by when resuming.
+    let mut received = 0;
+    let mut checksum = Crc32::new();
+    loop {
+        supervisor::clear_link_changed();
+        let Some(session) = supervisor::session() else {
+            supervisor::link_changed().await;
+            continue;
+        };
+
+        // Anything still queued belongs to an earlier, abandoned request.
+        while FRAMES.try_recv().is_ok() {}
+        if received == 0 {
+            match etag {
+                Some(etag) => send(MessageType::Revalidate, &encode_revalidate(etag, url)).await,
+                None => send(MessageType::Request, url.as_bytes()).await,
+            }
+        } else {
+            info!("Resuming {} at byte {}", url, received);
+            let resume = encode_resume(received as u32, checksum.finish(), url);
+            send(MessageType::Resume, &resume).await;
+        }
+
+        match select(
+            receive(url, decoder, progress, &mut received, &mut checksum),
+            link_lost(session),
+        )
+        .await
+        {
+            Either::First(Ok(Some(fetched))) => return Ok(fetched),
+            Either::First(Ok(None)) => warn!("Frames of {} went missing", url),
+            Either::First(Err(err)) => return Err(err),
+            Either::Second(()) => warn!("Connection lost while fetching {}", url),
+        }
+    }
+}
+
+/// Waits until connection `session` is gone.
+async fn link_lost(session: u32) {
+    while supervisor::session() == Some(session) {
+        supervisor::link_changed().await;
+    }
+}
+
+/// What a frame turned out to be.
+enum Received<'f> {
+    Chunk(Chunk<'f>),
+    /// Part of a response we stopped waiting for.
+    Stale,
+    /// Frames before it went missing, what is left of the response has to
+    /// be asked for again.
+    Gap,
+}
+
+fn next_chunk<'f>(
+    reassembler: &mut Reassembler,
+    frame: &'f Frame,
+) -> Result<Received<'f>, FetchError> {
+    match reassembler.push_chunk(frame) {
+        Ok(chunk) => Ok(Received::Chunk(chunk)),
+        Err(protocol::Error::Sequence { expected: 0, .. }) => Ok(Received::Stale),
+        Err(protocol::Error::Sequence { .. }) => Ok(Received::Gap),
+        Err(err) => Err(FetchError::Protocol(err)),
+    }
+}
+
+/// The error in a complete error message.
+fn server_error(payload: &[u8]) -> FetchError {
+    let (status, reason) = decode_error(payload);
+    FetchError::Server(status, reason.to_string())
+}
+
+/// Receives the response to the message just sent, counting the bytes of
+/// the page in `received` and adding them to `checksum`. Returns `None` if
+/// part of it went missing.
+async fn receive(
+    url: &str,
+    decoder: &RefCell<PageDecoder>,
+    progress: &Notify<()>,
+    received: &mut usize,
+    checksum: &mut Crc32,
+) -> Result<Option<Fetched>, FetchError> {
+    let mut reassembler = Reassembler::new(MAX_PAYLOAD_LEN);
+    // Error messages are small, they are collected and decoded at the end.
+    let mut error = Vec::new();
+    loop {
+        let frame = FRAMES.recv().await;
+        let chunk = match next_chunk(&mut reassembler, &frame)? {
+            Received::Chunk(chunk) => chunk,
+            Received::Stale => continue,
+            Received::Gap => return Ok(None),
+        };
+        match chunk.kind {
+            MessageType::Page => {
+                if *received == 0 && chunk.first {
+                    // Whatever copy the decoder held is out of date.
+                    *decoder.borrow_mut() = PageDecoder::new(MAX_PAGE_LEN);
+                    REPLACED.store(true, Ordering::Relaxed);
+                    cache::begin(url, chunk.total_len);
+                }
+                decoder
+                    .borrow_mut()
+                    .push(chunk.data)
+                    .map_err(FetchError::Page)?;
+                cache::append(chunk.data);
+                *received += chunk.data.len();
+                checksum.update(chunk.data);
+                progress.notify(());
+            }
+            MessageType::Error => error.extend_from_slice(chunk.data),
+            MessageType::NotModified => {
+                info!("{} is up to date", url);
+                return Ok(Some(Fetched::NotModified));
+            }
+            kind => return Err(FetchError::Unexpected(kind)),
+        }
+        if !chunk.last {
+            continue;
+        }
+
+        if chunk.kind == MessageType::Error {
+            return Err(server_error(&error));
+        }
+        if !decoder.borrow().is_complete() {
+            return Err(FetchError::Page(PageError::Parse(
+                "truncated page".to_string(),
+            )));
+        }
+        cache::commit();
+        info!("Received {} byte page", *received);
+        return Ok(Some(Fetched::Page));
+    }
+}
+
+/// Loads the window of the page at `url` that `decoder` was
+/// [moved](PageDecoder::seek) to, from the cache if it holds the same copy
+/// of the page and from the server otherwise.
+///
+/// Waits for a connection if there is none, and asks for what is still
+/// missing again after the connection dropped.
+pub async fn fetch_window(url: &str, decoder: &RefCell<PageDecoder>) -> Result<(), FetchError> {
+    let Some(etag) = decoder.borrow().page().map(|page| page.window.etag) else {
+        return Ok(());
+    };
+    if cache::load_range(url, etag, decoder).await {
+        return Ok(());
+    }
+    loop {
+        let Some(range) = decoder.borrow().wanted().map_err(FetchError::Page)? else {
+            return Ok(());
+        };
+        supervisor::clear_link_changed();
+        let Some(session) = supervisor::session() else {
+            supervisor::link_changed().await;
+            continue;
+        };
+
+        while FRAMES.try_recv().is_ok() {}
+        debug!("Fetching bytes {}..{} of {}", range.start, range.end, url);
+        send(MessageType::Range, &encode_range(etag, range, url)).await;
+        match select(receive_range(decoder), link_lost(session)).await {
+            Either::First(result) => result?,
+            Either::Second(()) => warn!("Connection lost while fetching {}", url),
+        }
+    }
+}
+
+/// Receives the part of a page just asked for into `decoder`, or as much of
+/// it as arrives before frames go missing.
+async fn receive_range(decoder: &RefCell<PageDecoder>) -> Result<(), FetchError> {
+    let mut reassembler = Reassembler::new(MAX_PAYLOAD_LEN);
+    let mut error = Vec::new();
+    loop {
+        let frame = FRAMES.recv().await;
+        let chunk = match next_chunk(&mut reassembler, &frame)? {
+            Received::Chunk(chunk) => chunk,
+            Received::Stale => continue,
+            Received::Gap => return Ok(()),
+        };
+        match chunk.kind {
+            MessageType::Page => decoder
+                .borrow_mut()
+                .push_range(chunk.data)
+                .map_err(FetchError::Page)?,
+            MessageType::Error => error.extend_from_slice(chunk.data),
+            kind => return Err(FetchError::Unexpected(kind)),
+        }
+        if chunk.last {
+            return match chunk.kind {
+                MessageType::Error => Err(server_error(&error)),
+                _ => Ok(()),
+            };
+        }
+    }
+}
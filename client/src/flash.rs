@@ -0,0 +1,100 @@
+//! The regions of flash reserved in `memory.x` for data that survives a
+//! reset and new firmware: the settings and the page cache.
+//!
+//! Flash is written through the SoftDevice so it does not get in the way of
+//! the radio. The SoftDevice hands out its flash only once, so the regions
+//! take turns with it. Reads go straight to memory.
+
+use core::future::Future;
+
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::mutex::Mutex;
+use embedded_storage_async::nor_flash::AsyncNorFlash;
+use nrf_softdevice::{Flash as SoftdeviceFlash, FlashError, Softdevice};
+
+extern "C" {
+    static __settings_start: u8;
+    static __settings_end: u8;
+    static __cache_start: u8;
+    static __cache_end: u8;
+}
+
+static FLASH: Mutex<ThreadModeRawMutex, Option<SoftdeviceFlash>> = Mutex::new(None);
+
+/// Takes the flash from the SoftDevice. Has to be called before any region
+/// is written.
+pub async fn init(sd: &Softdevice) {
+    *FLASH.lock().await = Some(SoftdeviceFlash::take(sd));
+}
+
+/// A region of flash, see [`browser::settings::Flash`].
+pub struct Region {
+    start: u32,
+    size: u32,
+}
+
+impl Region {
+    /// Between two linker symbols.
+    fn between(start: &'static u8, end: &'static u8) -> Self {
+        let start = start as *const u8 as u32;
+        let end = end as *const u8 as u32;
+        Self {
+            start,
+            size: end - start,
+        }
+    }
+
+    /// The `SETTINGS` region.
+    pub fn settings() -> Self {
+        // SAFETY: only the addresses of the symbols are used.
+        unsafe { Self::between(&__settings_start, &__settings_end) }
+    }
+
+    /// The `CACHE` region.
+    pub fn cache() -> Self {
+        // SAFETY: only the addresses of the symbols are used.
+        unsafe { Self::between(&__cache_start, &__cache_end) }
+    }
+}
+
+impl browser::settings::Flash for Region {
+    type Error = FlashError;
+    type WriteFuture<'a> = impl Future<Output = Result<(), FlashError>> + 'a where Self: 'a;
+    type EraseFuture<'a> = impl Future<Output = Result<(), FlashError>> + 'a where Self: 'a;
+
+    const PAGE_SIZE: u32 = 4096;
+
+    fn size(&self) -> u32 {
+        self.size
+    }
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), FlashError> {
+        if offset + buf.len() as u32 > self.size {
+            return Err(FlashError::Failed);
+        }
+        // SAFETY: flash is mapped into memory, and the range is inside the
+        // region.
+        unsafe {
+            let from = (self.start + offset) as *const u8;
+            core::ptr::copy_nonoverlapping(from, buf.as_mut_ptr(), buf.len());
+        }
+        Ok(())
+    }
+
+    fn write<'a>(&'a mut self, offset: u32, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        async move {
+            let mut flash = FLASH.lock().await;
+            let flash = flash.as_mut().ok_or(FlashError::Failed)?;
+            flash.write(self.start + offset, data).await
+        }
+    }
+
+    fn erase(&mut self, offset: u32) -> Self::EraseFuture<'_> {
+        async move {
+            let mut flash = FLASH.lock().await;
+            let flash = flash.as_mut().ok_or(FlashError::Failed)?;
+            let from = self.start + offset;
+            flash.erase(from, from + Self::PAGE_SIZE).await
+        }
+    }
+}
@@ -6,212 +6,303 @@
 extern crate alloc;
 
 use alloc::format;
+use alloc::string::String;
 use core::alloc::Layout;
+use core::cell::RefCell;
 use core::mem::MaybeUninit;
-use embassy_embedded_hal::adapter::BlockingAsync;
 use embassy_executor::_export::StaticCell;
-use embassy_futures::select::select3;
+use embassy_futures::select::{select4, Either4};
 use embassy_nrf::interrupt;
 use embassy_nrf::interrupt::Priority;
-use embassy_nrf::pac::TWIS0;
 use embassy_nrf::peripherals::P0_11;
 use embassy_nrf::peripherals::P0_12;
 use embassy_nrf::peripherals::TWISPI0;
 use embassy_nrf::twim;
 use embassy_nrf::twim::Twim;
-use embassy_nrf::Peripheral;
-use embassy_nrf::Peripherals;
 use nrf_softdevice::ble;
-use nrf_softdevice::ble::peripheral;
-use nrf_softdevice::ble::Connection;
-use nrf_softdevice::ble::TxPower;
-use nrf_softdevice::ble::{central, gatt_client, Address, AddressType};
 use nrf_softdevice::{raw, Softdevice};
 
 use alloc_cortex_m::CortexMHeap;
 use embassy_executor::Spawner;
 
-use swb_shared::Program;
-use swb_shared::{Instruction, StyleVar};
-
 use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
-use embassy_futures::join::{join, join3, join4};
-use embassy_futures::select::select;
+use embassy_futures::join::{join, join3};
+use embassy_futures::select::{select, Either};
 use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::channel::Channel;
 use embassy_sync::mutex::Mutex;
-use embedded_graphics::geometry::{Point, Size};
+use embedded_graphics::geometry::Size;
 use toekomst::display::disp;
 
-use bbq10kbd::{Bbq10Kbd, KeyRaw, KeyStatus};
-use toekomst::button::Button;
+use bbq10kbd::{Bbq10Kbd, KeyRaw};
+use browser::keyboard::{KeyState, Keycode, Keymap};
 use toekomst::display::request_redraw;
-use toekomst::input::Input;
-use toekomst::key::Accel;
 use toekomst::key::Key;
-use toekomst::label;
-use toekomst::label::{label_once, label_once_bold, label_with};
-use toekomst::layout::Vertical;
 use toekomst::notify::Notify;
-use toekomst::widget::Widget;
 
 #[allow(unused_imports)]
 #[cfg(feature = "defmt")]
 use {defmt_rtt as _, panic_probe as _};
 
 pub(crate) mod fmt;
+mod address;
+mod cache;
+mod fetch;
+mod flash;
 #[cfg(feature = "log")]
 mod logger;
+mod pairing;
+mod picker;
+mod settings;
+mod status;
+mod supervisor;
+
+use fetch::{FetchError, Fetched, MAX_PAGE_LEN};
+use browser::decode::PageDecoder;
+use browser::history::History;
+use browser::status::Status;
+use browser::view::{
+    action_for, apply_on_error, apply_while_loading, draw_error, Action, Navigate, View,
+};
 
 #[global_allocator]
 static ALLOCATOR: CortexMHeap = CortexMHeap::empty();
-const HEAP_SIZE: usize = 32000;
+const HEAP_SIZE: usize = browser::HEAP_SIZE;
+
+/// Page loaded when the browser starts and no page was open before.
+const HOME_URL: &str = "ab.html";
 
-static BINARY: &'static [u8] = include_bytes!("../ab.swb");
+/// Every key pressed while reading a page.
+static KEYS: Channel<ThreadModeRawMutex, Keycode, 8> = Channel::new();
 
-fn parse_swb(bytes: &[u8]) -> Program {
-    let result = Program::try_from(bytes);
-    match result {
-        Ok(program) => program,
-        Err(swb_shared::Error(e)) => {
-            error!("error parsing: {}", e);
-            panic!("fatal error");
+async fn controls(notify: &Notify<Action>) {
+    loop {
+        if let Some(action) = action_for(KEYS.recv().await) {
+            notify.notify(action);
         }
     }
 }
 
-struct StyleVarStack {
-    state: u32,
-}
-
-impl StyleVarStack {
-    pub fn new() -> Self {
-        Self { state: 0 }
+/// What the status bar shows for the page in `decoder`.
+fn page_status(decoder: &PageDecoder, view: &View) -> Status {
+    let mut status = Status::new(status::connection());
+    if let Some(page) = decoder.page() {
+        status.title = page.title.clone();
+        status.position = Some(view.position());
     }
-
-    pub fn push(&mut self) {
-        self.state += 1;
+    if !decoder.is_complete() {
+        status.progress = Some(decoder.progress());
     }
+    status
+}
 
-    pub fn pop(&mut self) {
-        self.state -= 1;
-    }
+/// Renders the page in `decoder` from `start_line` on and handles scrolling
+/// and link focus on it. The page may still be arriving: it is redrawn on
+/// `progress`. Parts of long pages not in memory are asked for on `wanted`.
+/// Returns where the user wants to go next and how far they had scrolled.
+async fn render_page(
+    decoder: &RefCell<PageDecoder>,
+    start_line: i32,
+    actions: &Notify<Action>,
+    progress: &Notify<()>,
+    wanted: &Notify<u32>,
+) -> (Navigate, i32) {
+    let mut view = View::new(start_line);
+    let mut shown_status = None;
+    loop {
+        if fetch::take_replaced() {
+            view.replace();
+        }
+        {
+            let mut dp = disp().await;
+            // The view only redraws what changed since its last draw.
+            let decoder = decoder.borrow();
+            if let Some(page) = decoder.page() {
+                let _ = view.draw(page, &mut *dp);
+                if decoder.is_complete() {
+                    if let Some(first) = view.wanted(page) {
+                        wanted.notify(first);
+                    }
+                }
+            } else {
+                dp.clear();
+                shown_status = None;
+            }
+        }
+        let status = page_status(&decoder.borrow(), &view);
+        if shown_status.as_ref() != Some(&status) {
+            status::draw(&status).await;
+            shown_status = Some(status);
+        }
+        request_redraw();
 
-    pub fn is_enabled(&self) -> bool {
-        self.state > 0
+        // We rendered our current version of the page, now wait for a command,
+        // for more of the page, for the connection to change or for pairing.
+        let waits = select4(
+            actions.wait(),
+            progress.wait(),
+            status::changed(),
+            pairing::requested(),
+        );
+        let action = match waits.await {
+            Either4::First(action) => action,
+            Either4::Second(()) | Either4::Third(()) => continue,
+            Either4::Fourth(prompt) => {
+                pairing::prompt(prompt).await;
+                view.invalidate();
+                shown_status = None;
+                continue;
+            }
+        };
+        let navigate = match decoder.borrow().page() {
+            Some(page) => view.apply(page, action),
+            None => apply_while_loading(action),
+        };
+        match navigate {
+            Some(navigate) => return (navigate, view.start_line()),
+            None => match action {
+                Action::ScrollUp | Action::ScrollDown => {
+                    info!("Scrolled to {}", view.start_line())
+                }
+                Action::FollowLink => info!("No link focused"),
+                _ => {}
+            },
+        }
     }
 }
 
-enum Scroll {
-    Up,
-    Down,
-}
-
-async fn scroll(notify: &Notify<Scroll>) {
-    let up_fut = async {
-        loop {
-            toekomst::key::wait(Key::i).await;
-            notify.notify(Scroll::Up);
+/// Shows why `url` could not be loaded until the user decides where to go.
+async fn render_error(url: &str, err: &FetchError, actions: &Notify<Action>) -> Navigate {
+    let reason = format!("{}", err);
+    loop {
+        {
+            let mut dp = disp().await;
+            dp.clear();
+            let _ = draw_error(url, &reason, &mut *dp);
         }
-    };
-
-    let down_fut = async {
+        let status = Status {
+            error: true,
+            ..Status::new(status::connection())
+        };
+        status::draw(&status).await;
+        request_redraw();
         loop {
-            toekomst::key::wait(Key::k).await;
-            notify.notify(Scroll::Down);
+            match select(actions.wait(), pairing::requested()).await {
+                Either::First(action) => {
+                    if let Some(navigate) = apply_on_error(action) {
+                        return navigate;
+                    }
+                }
+                Either::Second(prompt) => {
+                    pairing::prompt(prompt).await;
+                    break;
+                }
+            }
         }
-    };
-
-    join(up_fut, down_fut).await;
+    }
 }
 
-static LINES_PER_SCROLL: i32 = 5;
+/// Shows the server picker and connects to the server chosen.
+async fn pick_server(sd: &'static Softdevice) {
+    let server = picker::pick_server(sd, supervisor::server()).await;
+    supervisor::set_server(&server);
+    settings::set_server(&server).await;
+}
 
-async fn render_page(page: &Program, scroll_notify: &Notify<Scroll>) {
-    let mut start_line = 0;
-    let spacing = 2;
-    let line_height = label::FONT.character_size.height + spacing;
-    loop {
-        let mut v = Vertical::new(Point::new(5, 2), spacing);
-
-        let mut bold = StyleVarStack::new();
-        let mut cur_line = 0;
-        let mut line_space_left = 240 / line_height;
-        for instr in &page.code {
-            if line_space_left == 0 {
-                break;
-            }
-            match instr {
-                Instruction::Text(address) => {
-                    cur_line += 1;
-                    if cur_line < start_line + 1 {
-                        continue;
+async fn ui(sd: &'static Softdevice) {
+    let actions = Notify::new();
+    let progress = Notify::new();
+    let wanted = Notify::new();
+    let browse_fut = async {
+        // The server and page from before a reset are used again.
+        match settings::server().await {
+            Some(server) => supervisor::set_server(&server),
+            None => pick_server(sd).await,
+        }
+        let (url, start_line) = settings::page()
+            .await
+            .unwrap_or_else(|| (String::from(HOME_URL), 0));
+        let mut history = History::new(&url, HEAP_SIZE / 4);
+        history.set_start_line(start_line);
+        loop {
+            settings::set_page(history.url(), history.start_line());
+            // Pages not visited this time may have been cached before. They
+            // show straight away, and are checked with the server meanwhile.
+            let mut etag = None;
+            let decoder = RefCell::new(match history.take_page() {
+                Some(page) => PageDecoder::from_page(page, MAX_PAGE_LEN),
+                None => match cache::load(history.url()).await {
+                    Some((decoder, cached)) => {
+                        etag = Some(cached);
+                        decoder
                     }
-                    //info!("text: {}", format!("{}", address).as_str());
-                    let str = page
-                        .text
-                        .as_bytes()
-                        .get(
-                            address.base.0 as usize
-                                ..(address.base.offset(address.range as i32).0 as usize),
-                        )
-                        .unwrap();
-                    let str = core::str::from_utf8(str).unwrap();
-                    if bold.is_enabled() {
-                        label_once_bold(str, v.push(label::FONT.character_size)).await;
-                    } else {
-                        label_once(str, v.push(label::FONT.character_size)).await;
+                    None => PageDecoder::new(MAX_PAGE_LEN),
+                },
+            });
+            // Keeps receiving while the user reads what arrived so far.
+            let download = async {
+                if !decoder.borrow().is_complete() || etag.is_some() {
+                    match fetch::fetch(history.url(), etag, &decoder, &progress).await {
+                        Ok(Fetched::Page) => progress.notify(()),
+                        Ok(Fetched::NotModified) => {}
+                        Err(err) => return err,
                     }
-                    line_space_left -= 1;
                 }
-                Instruction::Push(StyleVar::Bold) => {
-                    bold.push();
+                // Long pages are loaded a window at a time as the user scrolls.
+                loop {
+                    let first = wanted.wait().await;
+                    decoder.borrow_mut().seek(first);
+                    if let Err(err) = fetch::fetch_window(history.url(), &decoder).await {
+                        return err;
+                    }
+                    progress.notify(());
                 }
-                Instruction::Push(_) => {}
-                Instruction::Pop(StyleVar::Bold) => {
-                    bold.pop();
+            };
+            let render = render_page(&decoder, history.start_line(), &actions, &progress, &wanted);
+            let result = select(download, render).await;
+            let navigate = match result {
+                Either::First(err) => {
+                    error!("Loading {} failed: {}", history.url(), format!("{}", err).as_str());
+                    render_error(history.url(), &err, &actions).await
                 }
-                Instruction::Pop(_) => {}
-                Instruction::Endl => {
-                    cur_line += 1;
-                    if cur_line < start_line {
-                        continue;
+                Either::Second((navigate, start_line)) => {
+                    settings::set_page(history.url(), start_line);
+                    // Pages left before they were complete are fetched again.
+                    if let Ok(page) = decoder.into_inner().finish() {
+                        history.leave(page, start_line);
                     }
-                    line_space_left -= 1;
-                    v.push(label::FONT.character_size);
+                    navigate
                 }
-                Instruction::Stop => {
-                    break;
+            };
+            match navigate {
+                Navigate::To(url) => {
+                    info!("Following link to {}", url.as_str());
+                    history.push(url);
                 }
+                Navigate::Back => {
+                    if !history.back() {
+                        info!("Already at the oldest page");
+                    }
+                }
+                Navigate::Forward => {
+                    if !history.forward() {
+                        info!("Already at the newest page");
+                    }
+                }
+                Navigate::EditAddress => {
+                    if let Some(url) = address::edit_address(history.url()).await {
+                        history.push(url);
+                    }
+                }
+                Navigate::PickServer => pick_server(sd).await,
+                Navigate::Reload => info!("Reloading {}", history.url()),
             }
         }
-
-        request_redraw();
-
-        // We rendered our current version of the page, now wait for a scroll command
-        let scroll = scroll_notify.wait().await;
-        match scroll {
-            Scroll::Up => {
-                start_line -= LINES_PER_SCROLL;
-                start_line = start_line.max(0);
-                info!("Scrolling up to {}", start_line);
-            }
-            Scroll::Down => {
-                start_line += LINES_PER_SCROLL;
-                info!("Scrolling down to {}", start_line);
-            }
-        }
-
-        let mut dp = disp().await;
-        dp.clear();
-    }
-}
-
-async fn ui(page: &Program) {
-    let scroll_notify = Notify::new();
-    let scroll_fut = scroll(&scroll_notify);
-    join(scroll_fut, render_page(page, &scroll_notify)).await;
+    };
+    join(controls(&actions), browse_fut).await;
 }
 
+/// Maps a letter onto the toekomst key of the same name.
 fn parse_key_state(value: u8) -> Option<Key> {
     if value >= 'a' as u8 && value <= 'z' as u8 {
         // SAFETY: We just verified this key is a valid character
@@ -233,23 +324,54 @@ async fn keyboard_driver(twim: TWISPI0, p0_12: P0_12, p0_11: P0_11) {
     info!("Created I2C bus");
     let mut kb = Bbq10Kbd::new(I2cDevice::new(i2c_bus));
     info!("Initialized keyboard driver");
+    let mut keymap = Keymap::new();
     loop {
         let key = kb.get_fifo_key_raw().await.unwrap();
-        match key {
-            KeyRaw::Pressed(value) => {
-                match parse_key_state(value) {
-                    Some(key) => toekomst::key::press_key(key),
-                    None => {}
-                };
+        let (state, value) = match key {
+            KeyRaw::Pressed(value) => (KeyState::Pressed, value),
+            KeyRaw::Held(value) => (KeyState::Held, value),
+            KeyRaw::Released(value) => (KeyState::Released, value),
+            _ => continue,
+        };
+        let event = match keymap.feed(state, value) {
+            Some(event) => event,
+            None => continue,
+        };
+        // Holding a key repeats it, nothing acts on releases.
+        if event.state == KeyState::Released {
+            continue;
+        }
+
+        if pairing::is_entering() {
+            pairing::type_key(event.code);
+            continue;
+        }
+        if address::is_editing() {
+            address::type_key(event.code);
+            continue;
+        }
+        if picker::is_picking() {
+            picker::type_key(event.code);
+            continue;
+        }
+        if let Keycode::Char(c) = event.code {
+            if let Some(key) = parse_key_state(c.to_ascii_lowercase() as u8) {
+                toekomst::key::press_key(key);
             }
-            _ => {}
+        }
+        if KEYS.try_send(event.code).is_err() {
+            warn!("Key queue full");
         }
     }
 }
 
 #[embassy_executor::task]
 async fn softdevice_driver(sd: &'static Softdevice) -> ! {
-    sd.run().await
+    sd.run_with_callback(|evt| {
+        pairing::on_event(evt);
+        supervisor::on_event(evt);
+    })
+    .await
 }
 
 #[nrf_softdevice::gatt_service(uuid = "feed")]
@@ -275,7 +397,9 @@ fn initialize_softdevice(spawner: &Spawner) -> (&'static Softdevice, Server) {
             conn_count: 6,
             event_length: 24,
         }),
-        conn_gatt: Some(raw::ble_gatt_conn_cfg_t { att_mtu: 256 }),
+        conn_gatt: Some(raw::ble_gatt_conn_cfg_t {
+            att_mtu: fetch::ATT_MTU,
+        }),
         gatts_attr_tab_size: Some(raw::ble_gatts_cfg_attr_tab_size_t {
             attr_tab_size: 32768,
         }),
@@ -283,6 +407,8 @@ fn initialize_softdevice(spawner: &Spawner) -> (&'static Softdevice, Server) {
             adv_set_count: 1,
             periph_role_count: 3,
             central_role_count: 3,
+            // The browser is the peripheral on the link to the server, which
+            // pairs without these. Central links are only used to scan.
             central_sec_count: 0,
             _bitfield_1: raw::ble_gap_cfg_role_count_t::new_bitfield_1(0),
         }),
@@ -304,20 +430,6 @@ fn initialize_softdevice(spawner: &Spawner) -> (&'static Softdevice, Server) {
     (sd, server)
 }
 
-// Note: reversed!
-const SERVER_ADDR: [u8; 6] = [0x26, 0x28, 0xec, 0xcf, 0x7f, 0x28];
-
-async fn advertise(sd: &'static Softdevice) -> Connection {
-    let config = peripheral::Config::default();
-    let adv = peripheral::ConnectableAdvertisement::NonscannableDirected {
-        peer: Address::new(AddressType::Public, SERVER_ADDR),
-    };
-    let conn = peripheral::advertise_connectable(sd, adv, &config)
-        .await
-        .unwrap();
-    conn
-}
-
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     {
@@ -333,15 +445,21 @@ async fn main(spawner: Spawner) {
     let (sd, server) = initialize_softdevice(&spawner);
     info!("Initialized softdevice");
     info!("My address: {:?}", ble::get_address(sd));
+    flash::init(sd).await;
+    settings::init().await;
+    spawner.spawn(settings::save_page()).unwrap();
+    cache::init().await;
+    spawner.spawn(cache::write_pages()).unwrap();
+    pairing::init(sd).await;
 
-    let connection = advertise(sd).await;
-    info!("Connected to {}", format!("{:?}", connection.peer_address()).as_str());
+    // Pages are fetched once a server is picked and the supervisor has
+    // connected to it.
+    spawner.spawn(supervisor::supervise(sd)).unwrap();
 
     spawner
         .spawn(keyboard_driver(p.TWISPI0, p.P0_12, p.P0_11))
         .unwrap();
 
-    let program = parse_swb(BINARY);
     toekomst::display::init_disp(
         p.SPI2,
         p.P0_14,
@@ -352,7 +470,7 @@ async fn main(spawner: Spawner) {
     );
     info!("Display initialized");
 
-    select(toekomst::display::run_disp(), ui(&program)).await;
+    join3(toekomst::display::run_disp(), ui(sd), pairing::save_bonds()).await;
 }
 
 #[cfg(feature = "defmt")]
@@ -0,0 +1,323 @@
+//! Pairing and bonding with the server, see [`browser::pairing`].
+//!
+//! The SoftDevice asks for passkeys and keys from its event handler, which
+//! can't wait. Keys are looked up in the bonds kept in memory, by the
+//! identity of the server on the other end, and new bonds are saved to the
+//! settings afterwards by [`save_bonds`]. Passkeys are handed
+//! to the UI, which waits for them with [`requested`] and shows them with
+//! [`prompt`]. Like the address bar, the prompt takes over the keyboard
+//! while it is open: the keyboard driver hands every key to [`type_key`].
+//!
+//! LE Secure Connections agree on a key with elliptic curve Diffie-Hellman,
+//! which the SoftDevice leaves to the application: [`on_event`] answers its
+//! requests for the shared key, using the P-256 key pair made by [`init`].
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use browser::keyboard::Keycode;
+use browser::pairing::{
+    add_bond, decode_bonds, draw_entry, draw_passkey, edit_passkey, encode_bonds, passkey_digits,
+    Bond,
+};
+use browser::servers::format_address;
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use nrf_softdevice::ble::security::{IoCapabilities, PasskeyReply, SecurityHandler};
+use nrf_softdevice::ble::{
+    Address, AddressType, Connection, EncryptionInfo, IdentityKey, IdentityResolutionKey, MasterId,
+    SecurityMode,
+};
+use nrf_softdevice::{raw, Softdevice};
+use p256::ecdh::diffie_hellman;
+use p256::{PublicKey, SecretKey};
+use toekomst::display::{disp, request_redraw};
+
+use crate::{settings, supervisor};
+
+/// How long pairing may take, including typing the passkey.
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(60);
+/// Wait before asking the SoftDevice for random bytes again, when it has
+/// too few left.
+const RANDOM_RETRY: Duration = Duration::from_millis(10);
+
+/// What the SoftDevice wants the user to see.
+pub enum Prompt {
+    /// Type the passkey the server shows.
+    Enter(PasskeyReply),
+    /// Show this passkey, for typing it on the server.
+    Show([u8; 6]),
+}
+
+static BONDS: Mutex<ThreadModeRawMutex, RefCell<Vec<Bond>>> = Mutex::new(RefCell::new(Vec::new()));
+static BONDED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+static SECURITY: Signal<ThreadModeRawMutex, SecurityMode> = Signal::new();
+static PROMPT: Signal<ThreadModeRawMutex, Prompt> = Signal::new();
+static ENTERING: AtomicBool = AtomicBool::new(false);
+static TYPED: Channel<ThreadModeRawMutex, Keycode, 16> = Channel::new();
+/// Our key pair for LE Secure Connections.
+static LESC_KEY: Mutex<ThreadModeRawMutex, RefCell<Option<SecretKey>>> =
+    Mutex::new(RefCell::new(None));
+
+/// The identity of the server `bond` is with.
+fn identity(bond: &Bond) -> IdentityKey {
+    let address_type = AddressType::try_from(bond.address_type).unwrap_or(AddressType::Public);
+    IdentityKey {
+        irk: IdentityResolutionKey::from_raw(raw::ble_gap_irk_t { irk: bond.irk }),
+        addr: Address::new(address_type, bond.address),
+    }
+}
+
+/// The key of the bond with the server at `address`, which may be one of
+/// its private addresses.
+fn find_key(address: Address) -> Option<EncryptionInfo> {
+    BONDS.lock(|bonds| {
+        let bonds = bonds.borrow();
+        let bond = bonds.iter().find(|bond| identity(bond).is_match(address))?;
+        Some(EncryptionInfo {
+            ltk: bond.ltk,
+            flags: bond.flags,
+        })
+    })
+}
+
+/// Answers the SoftDevice while pairing and encrypting.
+pub struct Bonder;
+
+/// The one security handler, handed to the SoftDevice with every connection.
+pub static BONDER: Bonder = Bonder;
+
+impl SecurityHandler for Bonder {
+    fn io_capabilities(&self) -> IoCapabilities {
+        IoCapabilities::KeyboardDisplay
+    }
+
+    fn can_bond(&self, _conn: &Connection) -> bool {
+        true
+    }
+
+    fn display_passkey(&self, passkey: &[u8; 6]) {
+        PROMPT.signal(Prompt::Show(*passkey));
+    }
+
+    fn enter_passkey(&self, reply: PasskeyReply) {
+        PROMPT.signal(Prompt::Enter(reply));
+    }
+
+    fn on_security_update(&self, _conn: &Connection, security_mode: SecurityMode) {
+        SECURITY.signal(security_mode);
+    }
+
+    fn on_bonded(
+        &self,
+        conn: &Connection,
+        _master_id: MasterId,
+        key: EncryptionInfo,
+        peer_id: IdentityKey,
+    ) {
+        let bond = Bond {
+            address_type: peer_id.addr.address_type() as u8,
+            address: peer_id.addr.bytes(),
+            irk: peer_id.irk.as_raw().irk,
+            ltk: key.ltk,
+            flags: key.flags,
+        };
+        // A bond made before under another identity is with the same server
+        // all the same, and of no use anymore.
+        let address = conn.peer_address();
+        BONDS.lock(|bonds| {
+            let mut bonds = bonds.borrow_mut();
+            bonds.retain(|old| !identity(old).is_match(address));
+            add_bond(&mut bonds, bond);
+        });
+        BONDED.signal(());
+    }
+
+    // The EDIV and Rand in `_master_id` are zero with Secure Connections.
+    fn get_key(&self, conn: &Connection, _master_id: MasterId) -> Option<EncryptionInfo> {
+        find_key(conn.peer_address())
+    }
+}
+
+/// Loads the bonds kept in the settings and makes a new LE Secure
+/// Connections key pair.
+pub async fn init(sd: &Softdevice) {
+    let bonds = decode_bonds(&settings::bonds().await.unwrap_or_default());
+    info!("Bonded with {} servers", bonds.len());
+    BONDS.lock(|cell| *cell.borrow_mut() = bonds);
+
+    let mut bytes = [0; 32];
+    let key = loop {
+        if nrf_softdevice::random_bytes(sd, &mut bytes).is_err() {
+            Timer::after(RANDOM_RETRY).await;
+            continue;
+        }
+        // Almost every 32 bytes are a valid secret key.
+        if let Ok(key) = SecretKey::from_slice(&bytes) {
+            break key;
+        }
+    };
+    LESC_KEY.lock(|cell| *cell.borrow_mut() = Some(key));
+}
+
+/// The Diffie-Hellman key shared with the peer whose public key is `peer`,
+/// in the SoftDevice's byte order, or `None` if `peer` is not on the curve.
+fn dh_key(peer: &[u8; 64]) -> Option<[u8; 32]> {
+    let mut sec1 = [4; 65];
+    sec1[1..].copy_from_slice(peer);
+    sec1[1..33].reverse();
+    sec1[33..].reverse();
+    let peer = PublicKey::from_sec1_bytes(&sec1).ok()?;
+    let key = LESC_KEY.lock(|cell| cell.borrow().clone())?;
+    let shared = diffie_hellman(key.to_nonzero_scalar(), peer.as_affine());
+    let mut dh_key = [0; 32];
+    dh_key.copy_from_slice(&shared.raw_secret_bytes()[..]);
+    dh_key.reverse();
+    Some(dh_key)
+}
+
+/// Handles the SoftDevice events nrf-softdevice does not: requests for the
+/// shared key while pairing with LE Secure Connections.
+pub fn on_event(evt: *const raw::ble_evt_t) {
+    // SAFETY: the SoftDevice hands out events valid during the callback,
+    // and the union fields read are the ones the event ID says are there.
+    unsafe {
+        if u32::from((*evt).header.evt_id) != raw::BLE_GAP_EVTS_BLE_GAP_EVT_LESC_DHKEY_REQUEST {
+            return;
+        }
+        let gap_evt = (*evt).evt.gap_evt.as_ref();
+        let request = gap_evt.params.lesc_dhkey_request.as_ref();
+        // An invalid key from the peer gets a made up shared key, pairing
+        // then fails when the keys are checked.
+        let key = dh_key(&(*request.p_pk_peer).pk).unwrap_or_else(|| {
+            warn!("Server sent an invalid public key");
+            [0; 32]
+        });
+        let dh_key = raw::ble_gap_lesc_dhkey_t { key };
+        let ret = raw::sd_ble_gap_lesc_dhkey_reply(gap_evt.conn_handle, &dh_key);
+        if ret != raw::NRF_SUCCESS {
+            warn!("Answering the DH key request failed: {}", ret);
+        }
+    }
+}
+
+/// Saves the bonds to the settings whenever a new one is made.
+pub async fn save_bonds() -> ! {
+    loop {
+        BONDED.wait().await;
+        let data = BONDS.lock(|bonds| encode_bonds(&bonds.borrow()));
+        settings::set_bonds(&data).await;
+    }
+}
+
+/// Whether `mode` means the link is encrypted with an authenticated LE
+/// Secure Connections key. Legacy pairing keys can be cracked by anyone who
+/// listened in on the pairing, passkey or not.
+fn is_secure(mode: SecurityMode) -> bool {
+    matches!(mode, SecurityMode::LescMitm)
+}
+
+/// Has the server encrypt `conn`, pairing first if they are not bonded yet.
+/// Returns whether the link ended up secure.
+pub async fn secure(conn: &Connection) -> bool {
+    SECURITY.reset();
+    if is_secure(conn.security_mode()) {
+        return true;
+    }
+    if let Err(err) = conn.request_security() {
+        warn!("Requesting security failed: {}", format!("{:?}", err).as_str());
+        return false;
+    }
+    let timeout = Timer::after(PAIRING_TIMEOUT);
+    let update = async {
+        loop {
+            if is_secure(SECURITY.wait().await) {
+                return;
+            }
+        }
+    };
+    match select(update, timeout).await {
+        Either::First(()) => true,
+        Either::Second(()) => {
+            warn!("Pairing timed out");
+            false
+        }
+    }
+}
+
+/// Whether key presses should go to the passkey prompt.
+pub fn is_entering() -> bool {
+    ENTERING.load(Ordering::Relaxed)
+}
+
+/// Called by the keyboard driver for every key pressed while entering a
+/// passkey.
+pub fn type_key(code: Keycode) {
+    let _ = TYPED.try_send(code);
+}
+
+/// Waits until the SoftDevice asks for a passkey prompt.
+pub async fn requested() -> Prompt {
+    PROMPT.wait().await
+}
+
+/// Takes keys until the user submits a passkey, or returns `None` once
+/// pairing would have timed out anyway.
+async fn type_passkey(server: &str) -> Option<[u8; 6]> {
+    let mut passkey = String::new();
+    let typed = async {
+        loop {
+            {
+                let mut dp = disp().await;
+                dp.clear();
+                let _ = draw_entry(server, &passkey, &mut *dp);
+            }
+            request_redraw();
+            if edit_passkey(&mut passkey, TYPED.recv().await) {
+                return;
+            }
+        }
+    };
+    match select(typed, Timer::after(PAIRING_TIMEOUT)).await {
+        Either::First(()) => passkey_digits(&passkey),
+        Either::Second(()) => None,
+    }
+}
+
+/// Shows `prompt` and answers it. Takes over the display, the caller draws
+/// what was there again afterwards.
+pub async fn prompt(prompt: Prompt) {
+    let server = supervisor::server()
+        .map(|address| format_address(&address))
+        .unwrap_or_default();
+    // Drop anything typed before the prompt opened.
+    while TYPED.try_recv().is_ok() {}
+    ENTERING.store(true, Ordering::Relaxed);
+    match prompt {
+        Prompt::Enter(reply) => {
+            let digits = type_passkey(&server).await;
+            if let Err(err) = reply.reply(digits.as_ref()) {
+                warn!("Answering the passkey request failed: {}", format!("{:?}", err).as_str());
+            }
+        }
+        Prompt::Show(passkey) => {
+            let passkey = core::str::from_utf8(&passkey).unwrap_or("??????");
+            {
+                let mut dp = disp().await;
+                dp.clear();
+                let _ = draw_passkey(&server, passkey, &mut *dp);
+            }
+            request_redraw();
+            // Shown until the user is done typing it on the server.
+            let _ = select(TYPED.recv(), Timer::after(PAIRING_TIMEOUT)).await;
+        }
+    }
+    ENTERING.store(false, Ordering::Relaxed);
+}
@@ -0,0 +1,113 @@
+//! Finding the server by scanning for the page service.
+//!
+//! Like the address bar, the picker takes over the keyboard while it is
+//! open: the keyboard driver hands every key to [`type_key`]. The list itself
+//! is kept by [`browser::servers::Picker`].
+
+use core::cell::RefCell;
+use core::slice;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::format;
+use browser::keyboard::Keycode;
+use browser::servers::{format_address, Picker, Server};
+use embassy_futures::select::{select, select3, Either, Either3};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use nrf_softdevice::ble::central;
+use nrf_softdevice::Softdevice;
+use toekomst::display::{disp, request_redraw};
+
+use crate::pairing;
+
+/// Wait before scanning again when scanning failed.
+const RESCAN: Duration = Duration::from_secs(1);
+
+static PICKING: AtomicBool = AtomicBool::new(false);
+static TYPED: Channel<ThreadModeRawMutex, Keycode, 16> = Channel::new();
+/// Signalled when the scan found a new server.
+static FOUND: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+/// Whether key presses should go to the picker.
+pub fn is_picking() -> bool {
+    PICKING.load(Ordering::Relaxed)
+}
+
+/// Called by the keyboard driver for every key pressed while picking.
+pub fn type_key(code: Keycode) {
+    let _ = TYPED.try_send(code);
+}
+
+/// Scans until cancelled, adding every server found to `picker`.
+async fn scan(sd: &'static Softdevice, picker: &RefCell<Picker>) -> ! {
+    loop {
+        scan_once(sd, picker).await;
+        Timer::after(RESCAN).await;
+    }
+}
+
+/// Scans until scanning fails.
+async fn scan_once(sd: &'static Softdevice, picker: &RefCell<Picker>) {
+    let config = central::ScanConfig {
+        // Servers often only send their name in the scan response.
+        active: true,
+        ..Default::default()
+    };
+    let result = central::scan(sd, &config, |report| {
+        // SAFETY: the SoftDevice hands out data valid during the callback.
+        let data = unsafe { slice::from_raw_parts(report.data.p_data, report.data.len as usize) };
+        let address = report.peer_addr.addr;
+        let address_type = report.peer_addr.addr_type();
+        if picker.borrow_mut().report(address, address_type, report.rssi, data) {
+            FOUND.signal(());
+        }
+        None::<()>
+    })
+    .await;
+    if let Err(err) = result {
+        warn!("Scanning failed: {}", format!("{:?}", err).as_str());
+    }
+}
+
+async fn draw(picker: &RefCell<Picker>) {
+    {
+        let mut dp = disp().await;
+        dp.clear();
+        let _ = picker.borrow().draw(&mut *dp);
+    }
+    request_redraw();
+}
+
+/// Lists the servers around until the user picks one. `current` is the
+/// address of the server used so far, if any.
+pub async fn pick_server(sd: &'static Softdevice, current: Option<[u8; 6]>) -> Server {
+    // Drop anything typed before the picker opened.
+    while TYPED.try_recv().is_ok() {}
+    PICKING.store(true, Ordering::Relaxed);
+
+    let picker = RefCell::new(Picker::new(current));
+    let choose = async {
+        loop {
+            draw(&picker).await;
+            match select3(TYPED.recv(), FOUND.wait(), pairing::requested()).await {
+                Either3::First(code) => {
+                    if let Some(server) = picker.borrow_mut().press(code) {
+                        return server;
+                    }
+                }
+                Either3::Second(()) => {}
+                Either3::Third(prompt) => pairing::prompt(prompt).await,
+            }
+        }
+    };
+    let server = match select(choose, scan(sd, &picker)).await {
+        Either::First(server) => server,
+        Either::Second(never) => never,
+    };
+
+    PICKING.store(false, Ordering::Relaxed);
+    info!("Picked server {}", format_address(&server.address).as_str());
+    server
+}
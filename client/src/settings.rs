@@ -0,0 +1,125 @@
+//! Settings kept in flash across resets: the server picked, the page that
+//! was open and how far it was scrolled, and the keys of the servers paired
+//! with.
+//!
+//! They live in the `SETTINGS` [region](crate::flash), in a
+//! [`browser::settings::Store`]. The page changes with every link followed,
+//! so it is only saved by [`save_page`] once the user stayed on one for a
+//! while, and only if it is not the page saved already.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use browser::servers::Server;
+use browser::settings::Store;
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+
+use crate::flash::Region;
+
+const SERVER: &str = "server";
+const URL: &str = "url";
+const START_LINE: &str = "start_line";
+const BONDS: &str = "bonds";
+
+/// How long the page has to stay the same before it is saved.
+const SAVE_PAGE_AFTER: Duration = Duration::from_secs(10);
+
+static SETTINGS: Mutex<ThreadModeRawMutex, Option<Store<Region>>> = Mutex::new(None);
+
+/// The page set last, waiting for [`save_page`].
+static PAGE: Signal<ThreadModeRawMutex, (String, i32)> = Signal::new();
+
+/// Opens the settings. Until it is called, or if opening failed, nothing is
+/// remembered.
+pub async fn init() {
+    match Store::open(Region::settings()).await {
+        Ok(store) => *SETTINGS.lock().await = Some(store),
+        Err(err) => error!("Opening settings failed: {}", format!("{:?}", err).as_str()),
+    }
+}
+
+async fn get(key: &str) -> Option<Vec<u8>> {
+    let mut settings = SETTINGS.lock().await;
+    match settings.as_mut()?.get(key) {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("Reading {} failed: {}", key, format!("{:?}", err).as_str());
+            None
+        }
+    }
+}
+
+async fn set(key: &str, value: &[u8]) {
+    let mut settings = SETTINGS.lock().await;
+    if let Some(store) = settings.as_mut() {
+        if let Err(err) = store.set(key, value).await {
+            warn!("Saving {} failed: {}", key, format!("{:?}", err).as_str());
+        }
+    }
+}
+
+/// The server picked last time, if any.
+pub async fn server() -> Option<Server> {
+    let value = get(SERVER).await?;
+    let (&address_type, address) = value.split_first()?;
+    Some(Server {
+        address: address.try_into().ok()?,
+        address_type,
+        name: None,
+        rssi: 0,
+    })
+}
+
+pub async fn set_server(server: &Server) {
+    let mut value = [0; 7];
+    value[0] = server.address_type;
+    value[1..].copy_from_slice(&server.address);
+    set(SERVER, &value).await
+}
+
+/// The page open before the reset and the first line on screen.
+pub async fn page() -> Option<(String, i32)> {
+    let url = String::from_utf8(get(URL).await?).ok()?;
+    let start_line = get(START_LINE)
+        .await
+        .and_then(|value| value.try_into().ok())
+        .map_or(0, i32::from_le_bytes);
+    Some((url, start_line))
+}
+
+/// Has [`save_page`] save `url` and the first line on screen.
+pub fn set_page(url: &str, start_line: i32) {
+    PAGE.signal((String::from(url), start_line));
+}
+
+/// Saves the page passed to [`set_page`] once it stayed the same for
+/// [`SAVE_PAGE_AFTER`], unless it is saved already.
+#[embassy_executor::task]
+pub async fn save_page() {
+    let mut saved = page().await;
+    loop {
+        let mut latest = PAGE.wait().await;
+        while let Either::Second(page) = select(Timer::after(SAVE_PAGE_AFTER), PAGE.wait()).await {
+            latest = page;
+        }
+        if saved.as_ref() != Some(&latest) {
+            let (url, start_line) = &latest;
+            set(URL, url.as_bytes()).await;
+            set(START_LINE, &start_line.to_le_bytes()).await;
+            saved = Some(latest);
+        }
+    }
+}
+
+/// The bonds with servers, packed by [`browser::pairing::encode_bonds`].
+pub async fn bonds() -> Option<Vec<u8>> {
+    get(BONDS).await
+}
+
+pub async fn set_bonds(bonds: &[u8]) {
+    set(BONDS, bonds).await
+}
@@ -0,0 +1,46 @@
+//! The status bar below the page, drawn with toekomst labels.
+//!
+//! The layout comes from [`browser::status`]; this module only keeps track of
+//! the connection to the server, which the UI does not see otherwise.
+
+use core::cell::Cell;
+
+use browser::status::{Connection, Status};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use toekomst::display::disp;
+use toekomst::label::{label_once, label_once_bold};
+
+static CONNECTION: Mutex<ThreadModeRawMutex, Cell<Connection>> =
+    Mutex::new(Cell::new(Connection::Connecting));
+static CHANGED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+pub fn set_connection(connection: Connection) {
+    CONNECTION.lock(|cell| cell.set(connection));
+    CHANGED.signal(());
+}
+
+pub fn connection() -> Connection {
+    CONNECTION.lock(|cell| cell.get())
+}
+
+/// Waits until the connection state changes.
+pub async fn changed() {
+    CHANGED.wait().await
+}
+
+/// Draws `status` below the page. The caller requests the redraw.
+pub async fn draw(status: &Status) {
+    {
+        let mut dp = disp().await;
+        let _ = Status::draw_background(&mut *dp);
+    }
+    for field in status.fields() {
+        if field.bold {
+            label_once_bold(&field.text, field.bounds).await;
+        } else {
+            label_once(&field.text, field.bounds).await;
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! Keeps the connection to the server up.
+//!
+//! The browser connects by advertising to the server the user picked with
+//! [`set_server`], and has the link encrypted before anything is sent over
+//! it, pairing first if the two are not [bonded](crate::pairing) yet. When
+//! the link drops it advertises again, waiting longer after every failed
+//! attempt so a server that is gone for a while does not keep the radio
+//! busy; picking another server drops the link and starts over right away.
+//! The fetcher does not hold on to a connection: it sends frames through
+//! [`send`] and learns about new connections from [`link_changed`], after
+//! which it resumes the page it was receiving.
+//!
+//! Frames are written no larger than the ATT MTU the server agreed on for
+//! the connection, which [`on_event`] picks up from the SoftDevice.
+
+use core::cell::Cell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::format;
+use embassy_futures::select::{select, select3, Either, Either3};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use nrf_softdevice::ble::{gatt_client, peripheral, Address, AddressType, Connection};
+use nrf_softdevice::{raw, Softdevice};
+
+use browser::servers::Server;
+use browser::status::Connection as ConnectionState;
+
+use crate::fetch::{receive_frames, Frame, PageServiceClient, ATT_MTU};
+use crate::{pairing, status};
+
+/// Wait before advertising again after the first failure.
+const FIRST_RETRY: Duration = Duration::from_millis(500);
+/// Longest wait between two attempts.
+const MAX_RETRY: Duration = Duration::from_secs(30);
+
+/// Bytes of an ATT write taken by the ATT header.
+const ATT_HEADER_LEN: usize = 3;
+
+/// Largest frame a write on the current connection holds.
+static FRAME_LEN: AtomicUsize =
+    AtomicUsize::new(raw::BLE_GATT_ATT_MTU_DEFAULT as usize - ATT_HEADER_LEN);
+
+/// Frames to write to the server, sent in order while connected.
+static OUTGOING: Channel<ThreadModeRawMutex, Frame, 4> = Channel::new();
+/// Number of the current connection, `None` while disconnected.
+static SESSION: Mutex<ThreadModeRawMutex, Cell<Option<u32>>> = Mutex::new(Cell::new(None));
+static LINK_CHANGED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+/// The server to connect to, `None` until the user picks one.
+static SERVER: Mutex<ThreadModeRawMutex, Cell<Option<Address>>> = Mutex::new(Cell::new(None));
+static SERVER_CHANGED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+/// Address of the server, least significant byte first, if one was picked.
+pub fn server() -> Option<[u8; 6]> {
+    SERVER.lock(|cell| cell.get()).map(|address| address.bytes())
+}
+
+/// Connects to `server` from now on, dropping the link to the previous one.
+pub fn set_server(server: &Server) {
+    let address_type = AddressType::try_from(server.address_type).unwrap_or(AddressType::Public);
+    let address = Address::new(address_type, server.address);
+    SERVER.lock(|cell| cell.set(Some(address)));
+    SERVER_CHANGED.signal(());
+}
+
+/// Number of the current connection, if there is one. Every connection gets
+/// a new number.
+pub fn session() -> Option<u32> {
+    SESSION.lock(|cell| cell.get())
+}
+
+/// Waits until the connection is lost or a new one is made.
+pub async fn link_changed() {
+    LINK_CHANGED.wait().await
+}
+
+/// Forgets about earlier connection changes.
+pub fn clear_link_changed() {
+    LINK_CHANGED.reset();
+}
+
+/// Largest frame that fits in one write on the current connection.
+pub fn frame_len() -> usize {
+    FRAME_LEN.load(Ordering::Relaxed)
+}
+
+/// Keeps track of the ATT MTU of the connection: the default one until the
+/// server exchanges MTUs, then the smaller of its and ours.
+pub fn on_event(evt: *const raw::ble_evt_t) {
+    // SAFETY: the SoftDevice hands out events valid during the callback,
+    // and the union fields read are the ones the event ID says are there.
+    let mtu = unsafe {
+        match u32::from((*evt).header.evt_id) {
+            raw::BLE_GAP_EVTS_BLE_GAP_EVT_CONNECTED => raw::BLE_GATT_ATT_MTU_DEFAULT as u16,
+            raw::BLE_GATTS_EVTS_BLE_GATTS_EVT_EXCHANGE_MTU_REQUEST => {
+                let gatts_evt = (*evt).evt.gatts_evt.as_ref();
+                gatts_evt.params.exchange_mtu_request.as_ref().client_rx_mtu
+            }
+            raw::BLE_GATTC_EVTS_BLE_GATTC_EVT_EXCHANGE_MTU_RSP => {
+                let gattc_evt = (*evt).evt.gattc_evt.as_ref();
+                gattc_evt.params.exchange_mtu_rsp.as_ref().server_rx_mtu
+            }
+            _ => return,
+        }
+    };
+    let mtu = mtu.clamp(raw::BLE_GATT_ATT_MTU_DEFAULT as u16, ATT_MTU);
+    FRAME_LEN.store(usize::from(mtu) - ATT_HEADER_LEN, Ordering::Relaxed);
+}
+
+/// Queues `frame` for the server. Frames queued while disconnected are
+/// dropped once the next connection is made.
+pub async fn send(frame: Frame) {
+    OUTGOING.send(frame).await
+}
+
+fn set_session(session: Option<u32>) {
+    SESSION.lock(|cell| cell.set(session));
+    LINK_CHANGED.signal(());
+    status::set_connection(match session {
+        Some(_) => ConnectionState::Connected,
+        None => ConnectionState::Disconnected,
+    });
+}
+
+async fn connect(
+    sd: &'static Softdevice,
+    server: Address,
+) -> Option<(Connection, PageServiceClient)> {
+    let config = peripheral::Config::default();
+    let adv = peripheral::ConnectableAdvertisement::NonscannableDirected { peer: server };
+    let conn = match peripheral::advertise_pairable(sd, adv, &config, &pairing::BONDER).await {
+        Ok(conn) => conn,
+        Err(err) => {
+            warn!("Advertising failed: {}", format!("{:?}", err).as_str());
+            return None;
+        }
+    };
+    info!(
+        "Connected to {}",
+        format!("{:?}", conn.peer_address()).as_str()
+    );
+    // The server does not answer over a link that is not encrypted.
+    if !pairing::secure(&conn).await {
+        warn!("Link to the server is not encrypted");
+        return None;
+    }
+
+    let client: PageServiceClient = match gatt_client::discover(&conn).await {
+        Ok(client) => client,
+        Err(err) => {
+            warn!("Page service not found: {}", format!("{:?}", err).as_str());
+            return None;
+        }
+    };
+    if let Err(err) = client.page_cccd_write(true).await {
+        warn!(
+            "Subscribing to pages failed: {}",
+            format!("{:?}", err).as_str()
+        );
+        return None;
+    }
+    info!("Discovered page service");
+    Some((conn, client))
+}
+
+/// Writes queued frames to the server until writing fails.
+async fn send_frames(client: &PageServiceClient) {
+    loop {
+        let frame = OUTGOING.recv().await;
+        if let Err(err) = client.page_write_without_response(&frame).await {
+            warn!("Write failed: {}", format!("{:?}", err).as_str());
+            return;
+        }
+    }
+}
+
+/// Connects to the server, and reconnects whenever the connection drops.
+#[embassy_executor::task]
+pub async fn supervise(sd: &'static Softdevice) {
+    let mut retry = FIRST_RETRY;
+    let mut sessions = 0;
+    loop {
+        let Some(server) = SERVER.lock(|cell| cell.get()) else {
+            status::set_connection(ConnectionState::Disconnected);
+            SERVER_CHANGED.wait().await;
+            continue;
+        };
+        SERVER_CHANGED.reset();
+
+        status::set_connection(ConnectionState::Connecting);
+        let connected = match select(connect(sd, server), SERVER_CHANGED.wait()).await {
+            Either::First(connected) => connected,
+            Either::Second(()) => {
+                retry = FIRST_RETRY;
+                continue;
+            }
+        };
+        let mut changed = false;
+        if let Some((conn, client)) = connected {
+            retry = FIRST_RETRY;
+            // Whatever was queued while disconnected is sent again by the
+            // fetcher once it hears about the new connection.
+            while OUTGOING.try_recv().is_ok() {}
+            sessions += 1;
+            set_session(Some(sessions));
+
+            let session = select3(
+                receive_frames(&conn, &client),
+                send_frames(&client),
+                SERVER_CHANGED.wait(),
+            );
+            changed = matches!(session.await, Either3::Third(()));
+            // Writing may have failed on a link that is still up.
+            let _ = conn.disconnect();
+            warn!("Disconnected from the server");
+        }
+        set_session(None);
+
+        // A newly picked server is tried right away.
+        if changed {
+            retry = FIRST_RETRY;
+            continue;
+        }
+        info!("Advertising again in {} ms", retry.as_millis());
+        if let Either::Second(()) = select(Timer::after(retry), SERVER_CHANGED.wait()).await {
+            retry = FIRST_RETRY;
+            continue;
+        }
+        retry = (retry * 2).min(MAX_RETRY);
+    }
+}
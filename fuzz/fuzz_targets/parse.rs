@@ -0,0 +1,38 @@
+//! Feeds arbitrary page payloads, as received from the server, through
+//! decoding and parsing, both in one go and fragment by fragment.
+
+#![no_main]
+
+use bambi_fuzz::{within_heap, Tracking, HEAP_SIZE, MAX_PAGE_LEN};
+use browser::decode::PageDecoder;
+use browser::page::Page;
+use libfuzzer_sys::fuzz_target;
+use protocol::page::decode_page;
+
+/// Payload carried by a fragment of the largest frame the client accepts.
+const FRAGMENT_LEN: usize = 239;
+
+#[global_allocator]
+static ALLOCATOR: Tracking = Tracking;
+
+fuzz_target!(|payload: &[u8]| {
+    // The payload itself is on the heap as well.
+    if payload.len() <= MAX_PAGE_LEN {
+        within_heap(HEAP_SIZE - payload.len(), || {
+            if let Ok((program, header)) = decode_page(payload) {
+                let _ =
+                    Page::parse(program, header.links).map(|page| page.with_title(header.title));
+            }
+        });
+    }
+    // The decoder only keeps a window of long pages.
+    within_heap(HEAP_SIZE, || {
+        let mut decoder = PageDecoder::new(MAX_PAGE_LEN);
+        for chunk in payload.chunks(FRAGMENT_LEN) {
+            if decoder.push(chunk).is_err() {
+                return;
+            }
+        }
+        let _ = decoder.finish();
+    });
+});
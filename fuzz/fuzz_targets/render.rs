@@ -0,0 +1,45 @@
+//! Parses arbitrary SWB programs and renders the ones that are accepted at a
+//! few scroll offsets.
+
+#![no_main]
+
+use bambi_fuzz::{within_heap, Tracking, HEAP_SIZE, MAX_PAGE_LEN};
+use browser::framebuffer::Framebuffer;
+use browser::page::Page;
+use browser::view::{draw_error, Action, View};
+use libfuzzer_sys::fuzz_target;
+use swb_shared::Instruction;
+
+#[global_allocator]
+static ALLOCATOR: Tracking = Tracking;
+
+fuzz_target!(|swb: &[u8]| {
+    if swb.len() > MAX_PAGE_LEN {
+        return;
+    }
+    // The framebuffer stands in for the display, which has memory of its own.
+    let mut screen = Framebuffer::new();
+    within_heap(HEAP_SIZE - swb.len(), || {
+        let page = match Page::parse(swb, Vec::new()) {
+            Ok(page) => page,
+            Err(err) => {
+                draw_error("fuzz.html", &err.to_string(), &mut screen).unwrap();
+                return;
+            }
+        };
+
+        // Accepted pages only refer to text that is there.
+        for instr in &page.program.code {
+            if let Instruction::Text(address) = instr {
+                assert_eq!(page.text(address).len(), address.range as usize);
+            }
+        }
+
+        let mut view = View::new(0);
+        for action in [Action::ScrollDown, Action::ScrollDown, Action::NextLink, Action::ScrollUp] {
+            view.draw(&page, &mut screen).unwrap();
+            view.apply(&page, action);
+        }
+        View::new(1000).draw(&page, &mut screen).unwrap();
+    });
+});
@@ -0,0 +1,60 @@
+//! Shared parts of the fuzz targets.
+//!
+//! Pages arrive over the network and are parsed and rendered on a device
+//! without memory protection and with a [`HEAP_SIZE`] byte heap, so the
+//! targets check both that nothing panics and that the heap would suffice.
+//!
+//! Run them with cargo-fuzz from this directory, seeding the render target
+//! with the example pages:
+//!
+//! ```sh
+//! cargo +nightly fuzz run parse
+//! cargo +nightly fuzz run render ../client
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub use browser::HEAP_SIZE;
+
+/// Heap budget for the part of a page the client keeps, see `MAX_PAGE_LEN`
+/// in the firmware. Pages parsed in one go have to fit in it whole.
+pub const MAX_PAGE_LEN: usize = HEAP_SIZE / 2;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// Keeps track of how many bytes are allocated at most.
+pub struct Tracking;
+
+unsafe impl GlobalAlloc for Tracking {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(live, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // Growing may need the old and the new block at the same time.
+        let live = LIVE.fetch_add(new_size, Ordering::Relaxed) + new_size;
+        PEAK.fetch_max(live, Ordering::Relaxed);
+        let new = System.realloc(ptr, layout, new_size);
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        new
+    }
+}
+
+/// Runs `f` and panics if it needed more than `budget` bytes of heap.
+pub fn within_heap<R>(budget: usize, f: impl FnOnce() -> R) -> R {
+    let base = LIVE.load(Ordering::Relaxed);
+    PEAK.store(base, Ordering::Relaxed);
+    let result = f();
+    let used = PEAK.load(Ordering::Relaxed) - base;
+    assert!(used <= budget, "used {} bytes of heap, only {} available", used, budget);
+    result
+}
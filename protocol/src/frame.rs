@@ -0,0 +1,292 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{crc32, MessageType};
+
+/// Size of the header in front of every fragment.
+pub const HEADER_LEN: usize = 14;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Error {
+    /// Fewer bytes than a header, or than the header says the fragment holds.
+    Truncated,
+    UnknownType(u8),
+    /// A fragment was lost, duplicated or reordered.
+    Sequence { expected: u16, got: u16 },
+    Checksum { expected: u32, actual: u32 },
+    /// A fragment does not agree with the earlier ones about the message
+    /// type or length, or carries more data than the message has left.
+    Inconsistent,
+    /// The message is larger than the receiver is willing to buffer.
+    TooLarge { len: usize, max: usize },
+    /// A complete message whose payload does not decode.
+    Malformed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Truncated => write!(f, "truncated frame"),
+            Error::UnknownType(kind) => write!(f, "unknown message type {}", kind),
+            Error::Sequence { expected, got } => {
+                write!(f, "expected fragment {} but got {}", expected, got)
+            }
+            Error::Checksum { expected, actual } => {
+                write!(f, "checksum mismatch: {:08x} != {:08x}", expected, actual)
+            }
+            Error::Inconsistent => write!(f, "fragment does not match its message"),
+            Error::TooLarge { len, max } => {
+                write!(f, "message of {} bytes exceeds limit of {}", len, max)
+            }
+            Error::Malformed => write!(f, "malformed message payload"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Header {
+    pub kind: MessageType,
+    pub seq: u16,
+    pub len: u16,
+    pub total_len: u32,
+    pub crc: u32,
+}
+
+impl Header {
+    pub fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut out = [0; HEADER_LEN];
+        out[0] = self.kind as u8;
+        out[2..4].copy_from_slice(&self.seq.to_le_bytes());
+        out[4..6].copy_from_slice(&self.len.to_le_bytes());
+        out[6..10].copy_from_slice(&self.total_len.to_le_bytes());
+        out[10..14].copy_from_slice(&self.crc.to_le_bytes());
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let bytes = bytes.get(..HEADER_LEN).ok_or(Error::Truncated)?;
+        Ok(Self {
+            kind: MessageType::try_from(bytes[0])?,
+            seq: u16::from_le_bytes([bytes[2], bytes[3]]),
+            len: u16::from_le_bytes([bytes[4], bytes[5]]),
+            total_len: u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]),
+            crc: u32::from_le_bytes([bytes[10], bytes[11], bytes[12], bytes[13]]),
+        })
+    }
+}
+
+/// Returns the length of the first complete frame in `buf`, if there is one.
+///
+/// Packet based links deliver one frame per packet, but stream based ones
+/// (TCP, Unix sockets) do not, so their readers use this to cut the stream
+/// back into frames.
+pub fn frame_len(buf: &[u8]) -> Option<usize> {
+    let bytes = buf.get(4..6)?;
+    let len = HEADER_LEN + u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+    if buf.len() >= len {
+        Some(len)
+    } else {
+        None
+    }
+}
+
+/// Splits a message into frames no larger than `mtu` bytes.
+///
+/// An empty payload still produces a single, empty frame.
+pub fn fragments(kind: MessageType, payload: &[u8], mtu: usize) -> Fragments<'_> {
+    assert!(mtu > HEADER_LEN, "MTU too small to carry any payload");
+    Fragments {
+        kind,
+        payload,
+        chunk: (mtu - HEADER_LEN).min(u16::MAX as usize),
+        seq: 0,
+        offset: 0,
+    }
+}
+
+pub struct Fragments<'a> {
+    kind: MessageType,
+    payload: &'a [u8],
+    chunk: usize,
+    seq: u16,
+    offset: usize,
+}
+
+impl<'a> Iterator for Fragments<'a> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        if self.offset >= self.payload.len() && (self.seq > 0 || !self.payload.is_empty()) {
+            return None;
+        }
+        let end = (self.offset + self.chunk).min(self.payload.len());
+        let data = &self.payload[self.offset..end];
+        let header = Header {
+            kind: self.kind,
+            seq: self.seq,
+            len: data.len() as u16,
+            total_len: self.payload.len() as u32,
+            crc: crc32(data),
+        };
+
+        let mut frame = Vec::with_capacity(HEADER_LEN + data.len());
+        frame.extend_from_slice(&header.encode());
+        frame.extend_from_slice(data);
+
+        self.offset = end;
+        self.seq = self.seq.wrapping_add(1);
+        Some(frame)
+    }
+}
+
+/// A complete, reassembled message.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Message {
+    pub kind: MessageType,
+    pub payload: Vec<u8>,
+}
+
+/// The payload of one fragment, as handed out by [`Reassembler::push_chunk`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Chunk<'a> {
+    pub kind: MessageType,
+    /// Total length of the message this is part of.
+    pub total_len: usize,
+    pub data: &'a [u8],
+    /// Whether this is the first fragment of its message.
+    pub first: bool,
+    /// Whether this completes the message.
+    pub last: bool,
+}
+
+/// Puts messages back together from their frames.
+pub struct Reassembler {
+    max_len: usize,
+    current: Option<(MessageType, usize)>,
+    next_seq: u16,
+    received: usize,
+    buf: Vec<u8>,
+}
+
+impl Reassembler {
+    /// Creates a reassembler that rejects messages longer than `max_len`.
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            current: None,
+            next_seq: 0,
+            received: 0,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Number of payload bytes received so far for the current message, and
+    /// the total it is expected to have.
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        self.current.map(|(_, total)| (self.received, total))
+    }
+
+    /// Drops a partially received message.
+    pub fn reset(&mut self) {
+        self.current = None;
+        self.next_seq = 0;
+        self.received = 0;
+        self.buf = Vec::new();
+    }
+
+    /// Feeds one frame. Returns the message once its last fragment arrived.
+    ///
+    /// Any error discards the partial message, the next frame is expected to
+    /// start a new one.
+    pub fn push(&mut self, frame: &[u8]) -> Result<Option<Message>, Error> {
+        let chunk = self.push_chunk(frame)?;
+        if chunk.first {
+            self.buf = Vec::with_capacity(chunk.total_len);
+        }
+        self.buf.extend_from_slice(chunk.data);
+        if !chunk.last {
+            return Ok(None);
+        }
+        let payload = core::mem::take(&mut self.buf);
+        Ok(Some(Message {
+            kind: chunk.kind,
+            payload,
+        }))
+    }
+
+    /// Feeds one frame without collecting the message: the fragment's data is
+    /// returned right away so large messages can be processed as they arrive.
+    /// Errors are handled as in [`push`](Self::push).
+    pub fn push_chunk<'f>(&mut self, frame: &'f [u8]) -> Result<Chunk<'f>, Error> {
+        let result = self.accept(frame);
+        if result.is_err() {
+            self.reset();
+        }
+        result
+    }
+
+    fn accept<'f>(&mut self, frame: &'f [u8]) -> Result<Chunk<'f>, Error> {
+        let header = Header::decode(frame)?;
+        let data = frame
+            .get(HEADER_LEN..HEADER_LEN + header.len as usize)
+            .ok_or(Error::Truncated)?;
+        let actual = crc32(data);
+        if actual != header.crc {
+            return Err(Error::Checksum {
+                expected: header.crc,
+                actual,
+            });
+        }
+
+        let total = header.total_len as usize;
+        let first = self.current.is_none();
+        match self.current {
+            None => {
+                if header.seq != 0 {
+                    return Err(Error::Sequence {
+                        expected: 0,
+                        got: header.seq,
+                    });
+                }
+                if total > self.max_len {
+                    return Err(Error::TooLarge {
+                        len: total,
+                        max: self.max_len,
+                    });
+                }
+                self.current = Some((header.kind, total));
+            }
+            Some((kind, expected_total)) => {
+                if header.seq != self.next_seq {
+                    return Err(Error::Sequence {
+                        expected: self.next_seq,
+                        got: header.seq,
+                    });
+                }
+                if kind != header.kind || expected_total != total {
+                    return Err(Error::Inconsistent);
+                }
+            }
+        }
+
+        if self.received + data.len() > total {
+            return Err(Error::Inconsistent);
+        }
+        self.received += data.len();
+        self.next_seq = header.seq.wrapping_add(1);
+
+        let last = self.received == total;
+        if last {
+            self.current = None;
+            self.next_seq = 0;
+            self.received = 0;
+        }
+        Ok(Chunk {
+            kind: header.kind,
+            total_len: total,
+            data,
+            first,
+            last,
+        })
+    }
+}
@@ -0,0 +1,219 @@
+//! Wire protocol spoken between the bambi browser and its page server.
+//!
+//! Every message (a page request, a compiled page, an error) is split into
+//! frames that fit in a single ATT notification or write. Each frame carries a
+//! small header so the receiving side can put the message back together and
+//! detect lost or corrupted fragments:
+//!
+//! | offset | size | field                             |
+//! |--------|------|-----------------------------------|
+//! | 0      | 1    | [`MessageType`]                   |
+//! | 1      | 1    | flags, reserved and always zero   |
+//! | 2      | 2    | sequence number of this fragment  |
+//! | 4      | 2    | payload length of this fragment   |
+//! | 6      | 4    | total length of the whole message |
+//! | 10     | 4    | CRC-32 of this fragment's payload |
+//!
+//! All integers are little endian. Sequence numbers start at zero for every
+//! message.
+
+#![no_std]
+
+extern crate alloc;
+
+use core::ops::Range;
+
+mod crc;
+mod frame;
+pub mod page;
+
+pub use crc::{crc32, Crc32};
+pub use frame::{
+    fragments, frame_len, Chunk, Error, Fragments, Header, Message, Reassembler, HEADER_LEN,
+};
+
+/// Type of the message a frame belongs to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum MessageType {
+    /// Client asks for a page, the payload is the UTF-8 URL.
+    Request = 1,
+    /// Server answers with a compiled SWB program and its links, see [`page`].
+    Page = 2,
+    /// Server could not answer, the payload is a [`Status`] byte followed by a
+    /// UTF-8 reason.
+    Error = 3,
+    /// Client asks for the rest of a response it lost the connection during,
+    /// see [`encode_resume`]. The server answers with the missing bytes as a
+    /// message of the original type.
+    Resume = 4,
+    /// Client asks for a page it has a copy of, see [`encode_revalidate`].
+    /// The server answers with [`MessageType::NotModified`] if the copy is
+    /// still current and with the page otherwise.
+    Revalidate = 5,
+    /// The copy of the page the client has is current, the payload is empty.
+    NotModified = 6,
+    /// Client asks for part of a page it only keeps some of in memory, see
+    /// [`encode_range`]. The server answers with those bytes of the
+    /// [`MessageType::Page`] payload, as a message of that type.
+    Range = 7,
+}
+
+impl TryFrom<u8> for MessageType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Error> {
+        match value {
+            1 => Ok(MessageType::Request),
+            2 => Ok(MessageType::Page),
+            3 => Ok(MessageType::Error),
+            4 => Ok(MessageType::Resume),
+            5 => Ok(MessageType::Revalidate),
+            6 => Ok(MessageType::NotModified),
+            7 => Ok(MessageType::Range),
+            other => Err(Error::UnknownType(other)),
+        }
+    }
+}
+
+/// Reason carried by an [`MessageType::Error`] message.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Status {
+    BadRequest = 1,
+    NotFound = 2,
+    InvalidPage = 3,
+    /// The server does not have the response a client asked to resume.
+    NoTransfer = 4,
+    /// Pages are only sent over encrypted links.
+    Unencrypted = 5,
+    /// The page a client asked part of changed since it got the rest.
+    Changed = 6,
+}
+
+impl Status {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Status::BadRequest),
+            2 => Some(Status::NotFound),
+            3 => Some(Status::InvalidPage),
+            4 => Some(Status::NoTransfer),
+            5 => Some(Status::Unencrypted),
+            6 => Some(Status::Changed),
+            _ => None,
+        }
+    }
+}
+
+/// Builds the payload of an [`MessageType::Error`] message.
+pub fn encode_error(status: Status, reason: &str) -> alloc::vec::Vec<u8> {
+    let mut payload = alloc::vec::Vec::with_capacity(1 + reason.len());
+    payload.push(status as u8);
+    payload.extend_from_slice(reason.as_bytes());
+    payload
+}
+
+/// Splits the payload of an [`MessageType::Error`] message into its status
+/// and reason. Unknown statuses and malformed reasons are passed on as `None`
+/// and an empty string respectively.
+pub fn decode_error(payload: &[u8]) -> (Option<Status>, &str) {
+    match payload.split_first() {
+        Some((&status, reason)) => (
+            Status::from_u8(status),
+            core::str::from_utf8(reason).unwrap_or(""),
+        ),
+        None => (None, ""),
+    }
+}
+
+/// Builds the payload of a [`MessageType::Resume`] message: the number of
+/// bytes of the response to `url` that were received intact and their
+/// [`crc32`], followed by the URL itself. The checksum tells the server which
+/// of the responses to `url` it sent lately the bytes belong to.
+pub fn encode_resume(offset: u32, received: u32, url: &str) -> alloc::vec::Vec<u8> {
+    let mut payload = alloc::vec::Vec::with_capacity(8 + url.len());
+    payload.extend_from_slice(&offset.to_le_bytes());
+    payload.extend_from_slice(&with_url(received, url));
+    payload
+}
+
+/// Splits the payload of a [`MessageType::Resume`] message into the offset,
+/// the checksum of the bytes before it and the URL.
+pub fn decode_resume(payload: &[u8]) -> Result<(u32, u32, &str), Error> {
+    if payload.len() < 4 {
+        return Err(Error::Malformed);
+    }
+    let (offset, rest) = payload.split_at(4);
+    let (received, url) = split_url(rest)?;
+    Ok((
+        u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]),
+        received,
+        url,
+    ))
+}
+
+/// `value` followed by `url`, the tail of most client messages.
+fn with_url(value: u32, url: &str) -> alloc::vec::Vec<u8> {
+    let mut payload = alloc::vec::Vec::with_capacity(4 + url.len());
+    payload.extend_from_slice(&value.to_le_bytes());
+    payload.extend_from_slice(url.as_bytes());
+    payload
+}
+
+/// Splits what [`with_url`] built.
+fn split_url(payload: &[u8]) -> Result<(u32, &str), Error> {
+    if payload.len() < 4 {
+        return Err(Error::Malformed);
+    }
+    let (value, url) = payload.split_at(4);
+    let url = core::str::from_utf8(url).map_err(|_| Error::Malformed)?;
+    Ok((
+        u32::from_le_bytes([value[0], value[1], value[2], value[3]]),
+        url,
+    ))
+}
+
+/// The ETag of a page: the CRC-32 of its [`MessageType::Page`] payload, so
+/// the client can work it out from its copy.
+pub fn etag(page: &[u8]) -> u32 {
+    crc32(page)
+}
+
+/// Builds the payload of a [`MessageType::Revalidate`] message: the
+/// [`etag`] of the copy of `url` the client has, followed by the URL itself.
+pub fn encode_revalidate(etag: u32, url: &str) -> alloc::vec::Vec<u8> {
+    with_url(etag, url)
+}
+
+/// Splits the payload of a [`MessageType::Revalidate`] message into the ETag
+/// and the URL.
+pub fn decode_revalidate(payload: &[u8]) -> Result<(u32, &str), Error> {
+    split_url(payload)
+}
+
+/// Builds the payload of a [`MessageType::Range`] message: the [`etag`] of
+/// the page, the start and end of the range of its payload, and the URL.
+pub fn encode_range(etag: u32, range: Range<u32>, url: &str) -> alloc::vec::Vec<u8> {
+    let mut payload = alloc::vec::Vec::with_capacity(12 + url.len());
+    payload.extend_from_slice(&etag.to_le_bytes());
+    payload.extend_from_slice(&range.start.to_le_bytes());
+    payload.extend_from_slice(&with_url(range.end, url));
+    payload
+}
+
+/// Splits the payload of a [`MessageType::Range`] message into the ETag,
+/// the range and the URL.
+pub fn decode_range(payload: &[u8]) -> Result<(u32, Range<u32>, &str), Error> {
+    if payload.len() < 8 {
+        return Err(Error::Malformed);
+    }
+    let (etag, rest) = payload.split_at(4);
+    let (start, rest) = rest.split_at(4);
+    let (end, url) = split_url(rest)?;
+    let etag = u32::from_le_bytes([etag[0], etag[1], etag[2], etag[3]]);
+    let start = u32::from_le_bytes([start[0], start[1], start[2], start[3]]);
+    if start > end {
+        return Err(Error::Malformed);
+    }
+    Ok((etag, start..end, url))
+}
@@ -0,0 +1,164 @@
+//! Payload of a [`MessageType::Page`](crate::MessageType::Page) message.
+//!
+//! SWB programs only describe text and style, so the title and the
+//! hyperlinks of a page travel in front of the program, the links as a table
+//! of text ranges:
+//!
+//! | size       | field                            |
+//! |------------|----------------------------------|
+//! | 2          | title length                     |
+//! | *t*        | UTF-8 title                      |
+//! | 2          | number of links                  |
+//! | 4 + 4 + 2  | text offset, length, href length |
+//! | *m*        | UTF-8 href                       |
+//! | 4          | length of the SWB program        |
+//! | *n*        | SWB program                      |
+//!
+//! The fourth and fifth rows repeat for every link. Offsets and lengths index
+//! into the text segment of the program, the same way `Instruction::Text`
+//! addresses do. Keeping the links in front lets the client show the start of
+//! a page, links included, while the rest of the program is still arriving.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::Error;
+
+/// Longest title sent along with a page, in bytes.
+pub const MAX_TITLE_LEN: usize = 128;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Link {
+    pub start: u32,
+    pub len: u32,
+    /// Target of the link, ready to be sent back as a request.
+    pub href: String,
+}
+
+impl Link {
+    /// Whether the text at `start..start + len` is (part of) this link.
+    pub fn covers(&self, start: u32, len: u32) -> bool {
+        start < self.start.saturating_add(self.len) && self.start < start.saturating_add(len)
+    }
+}
+
+/// Everything in front of the program.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PageHeader {
+    pub title: String,
+    pub links: Vec<Link>,
+    pub program_len: usize,
+}
+
+/// Builds a page payload. Titles longer than [`MAX_TITLE_LEN`] are cut short.
+/// More links, or longer hrefs or programs, than the header can count make
+/// the page [too large](Error::TooLarge).
+pub fn encode_page(title: &str, program: &[u8], links: &[Link]) -> Result<Vec<u8>, Error> {
+    let mut end = title.len().min(MAX_TITLE_LEN);
+    while !title.is_char_boundary(end) {
+        end -= 1;
+    }
+    let title = &title[..end];
+    let links_len: usize = links.iter().map(|link| 10 + link.href.len()).sum();
+    let mut out = Vec::with_capacity(2 + title.len() + 2 + links_len + 4 + program.len());
+    out.extend_from_slice(&u16_len(title.len())?.to_le_bytes());
+    out.extend_from_slice(title.as_bytes());
+    out.extend_from_slice(&u16_len(links.len())?.to_le_bytes());
+    for link in links {
+        out.extend_from_slice(&link.start.to_le_bytes());
+        out.extend_from_slice(&link.len.to_le_bytes());
+        out.extend_from_slice(&u16_len(link.href.len())?.to_le_bytes());
+        out.extend_from_slice(link.href.as_bytes());
+    }
+    let program_len = u32::try_from(program.len()).map_err(|_| Error::TooLarge {
+        len: program.len(),
+        max: u32::MAX as usize,
+    })?;
+    out.extend_from_slice(&program_len.to_le_bytes());
+    out.extend_from_slice(program);
+    Ok(out)
+}
+
+/// `len` as a two byte count in the header.
+fn u16_len(len: usize) -> Result<u16, Error> {
+    u16::try_from(len).map_err(|_| Error::TooLarge {
+        len,
+        max: u16::MAX as usize,
+    })
+}
+
+/// Splits a page payload into the SWB program and its header.
+pub fn decode_page(payload: &[u8]) -> Result<(&[u8], PageHeader), Error> {
+    let mut reader = Reader(payload);
+    let header = reader.header().map_err(|_| Error::Malformed)?;
+    let program = reader.take(header.program_len).map_err(|_| Error::Malformed)?;
+    if !reader.0.is_empty() {
+        return Err(Error::Malformed);
+    }
+    Ok((program, header))
+}
+
+/// Decodes the header from the start of a page payload.
+///
+/// Returns `None` while `payload` is too short to hold it, otherwise the
+/// header and the number of bytes it takes up.
+pub fn decode_header(payload: &[u8]) -> Result<Option<(PageHeader, usize)>, Error> {
+    let mut reader = Reader(payload);
+    match reader.header() {
+        Ok(header) => Ok(Some((header, payload.len() - reader.0.len()))),
+        Err(Error::Truncated) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            return Err(Error::Truncated);
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    /// Reads the title, the link table and the program length.
+    fn header(&mut self) -> Result<PageHeader, Error> {
+        let title_len = self.u16()? as usize;
+        if title_len > MAX_TITLE_LEN {
+            return Err(Error::Malformed);
+        }
+        let title = core::str::from_utf8(self.take(title_len)?).map_err(|_| Error::Malformed)?;
+        let title = String::from(title);
+        let count = self.u16()?;
+        let mut links = Vec::new();
+        for _ in 0..count {
+            let start = self.u32()?;
+            let len = self.u32()?;
+            let href_len = self.u16()? as usize;
+            let href = core::str::from_utf8(self.take(href_len)?).map_err(|_| Error::Malformed)?;
+            links.push(Link {
+                start,
+                len,
+                href: String::from(href),
+            });
+        }
+        let program_len = self.u32()? as usize;
+        Ok(PageHeader {
+            title,
+            links,
+            program_len,
+        })
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
@@ -0,0 +1,192 @@
+use protocol::{
+    crc32, decode_range, decode_resume, decode_revalidate, encode_range, encode_resume,
+    encode_revalidate, etag, fragments, frame_len, Crc32, Error, MessageType, Reassembler, Status,
+    HEADER_LEN,
+};
+
+fn page(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/../client/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+fn round_trip(payload: &[u8], mtu: usize) -> Vec<u8> {
+    let mut reassembler = Reassembler::new(64 * 1024);
+    let mut result = None;
+    for frame in fragments(MessageType::Page, payload, mtu) {
+        assert!(frame.len() <= mtu);
+        assert!(result.is_none(), "message completed before its last fragment");
+        result = reassembler.push(&frame).unwrap();
+    }
+    let message = result.expect("message never completed");
+    assert_eq!(message.kind, MessageType::Page);
+    message.payload
+}
+
+#[test]
+fn crc_matches_reference_value() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+}
+
+#[test]
+fn pages_survive_fragmentation() {
+    for name in ["ab.swb", "ns.swb", "rust_datatypes.swb", "output.swb"] {
+        let payload = page(name);
+        // Default ATT MTU, the client's configured MTU and a large one.
+        for mtu in [23, 256, 512, 4096] {
+            assert_eq!(round_trip(&payload, mtu), payload, "{} at MTU {}", name, mtu);
+        }
+    }
+}
+
+#[test]
+fn empty_and_exact_payloads() {
+    assert_eq!(fragments(MessageType::Page, &[], 64).count(), 1);
+    assert_eq!(round_trip(&[], 64), Vec::<u8>::new());
+
+    let exact = vec![0xaa; 64 - HEADER_LEN];
+    assert_eq!(fragments(MessageType::Page, &exact, 64).count(), 1);
+    assert_eq!(round_trip(&exact, 64), exact);
+
+    let one_more = vec![0xaa; 64 - HEADER_LEN + 1];
+    assert_eq!(fragments(MessageType::Page, &one_more, 64).count(), 2);
+    assert_eq!(round_trip(&one_more, 64), one_more);
+}
+
+#[test]
+fn corrupted_fragment_is_rejected() {
+    let payload = page("ab.swb");
+    let mut frames: Vec<_> = fragments(MessageType::Page, &payload, 256).collect();
+    let last = frames[1].len() - 1;
+    frames[1][last] ^= 0x01;
+
+    let mut reassembler = Reassembler::new(64 * 1024);
+    assert_eq!(reassembler.push(&frames[0]), Ok(None));
+    assert!(matches!(reassembler.push(&frames[1]), Err(Error::Checksum { .. })));
+    // The partial message is gone, continuing with it is an error.
+    assert!(matches!(reassembler.push(&frames[2]), Err(Error::Sequence { expected: 0, got: 2 })));
+}
+
+#[test]
+fn lost_fragment_is_detected() {
+    let payload = page("ab.swb");
+    let frames: Vec<_> = fragments(MessageType::Page, &payload, 256).collect();
+
+    let mut reassembler = Reassembler::new(64 * 1024);
+    reassembler.push(&frames[0]).unwrap();
+    assert_eq!(
+        reassembler.push(&frames[2]),
+        Err(Error::Sequence { expected: 1, got: 2 })
+    );
+
+    // A fresh transfer still goes through afterwards.
+    let mut result = None;
+    for frame in &frames {
+        result = reassembler.push(frame).unwrap();
+    }
+    assert_eq!(result.unwrap().payload, payload);
+}
+
+#[test]
+fn oversized_messages_are_refused() {
+    let payload = page("ns.swb");
+    let first = fragments(MessageType::Page, &payload, 256).next().unwrap();
+    let mut reassembler = Reassembler::new(16 * 1024);
+    assert_eq!(
+        reassembler.push(&first),
+        Err(Error::TooLarge { len: payload.len(), max: 16 * 1024 })
+    );
+}
+
+#[test]
+fn truncated_frames_are_rejected() {
+    let frame = fragments(MessageType::Request, b"ab.html", 64).next().unwrap();
+    let mut reassembler = Reassembler::new(1024);
+    assert_eq!(reassembler.push(&frame[..HEADER_LEN - 1]), Err(Error::Truncated));
+    assert_eq!(reassembler.push(&frame[..frame.len() - 1]), Err(Error::Truncated));
+    assert_eq!(
+        reassembler.push(&frame).unwrap().unwrap().payload,
+        b"ab.html".to_vec()
+    );
+}
+
+#[test]
+fn streams_are_cut_back_into_frames() {
+    let payload = page("ab.swb");
+    let stream: Vec<u8> = fragments(MessageType::Page, &payload, 100).flatten().collect();
+
+    let mut reassembler = Reassembler::new(64 * 1024);
+    let mut buf = &stream[..];
+    let mut result = None;
+    while let Some(len) = frame_len(buf) {
+        result = reassembler.push(&buf[..len]).unwrap();
+        buf = &buf[len..];
+    }
+    assert!(buf.is_empty());
+    assert_eq!(result.unwrap().payload, payload);
+}
+
+#[test]
+fn chunks_are_handed_out_as_they_arrive() {
+    let payload = page("ns.swb");
+    let mut reassembler = Reassembler::new(payload.len());
+    let mut received = Vec::new();
+    let frames: Vec<_> = fragments(MessageType::Page, &payload, 253).collect();
+    for (i, frame) in frames.iter().enumerate() {
+        let chunk = reassembler.push_chunk(frame).unwrap();
+        assert_eq!(chunk.kind, MessageType::Page);
+        assert_eq!(chunk.total_len, payload.len());
+        assert_eq!(chunk.first, i == 0);
+        assert_eq!(chunk.last, i == frames.len() - 1);
+        received.extend_from_slice(chunk.data);
+        if !chunk.last {
+            assert_eq!(reassembler.progress(), Some((received.len(), payload.len())));
+        }
+    }
+    assert_eq!(received, payload);
+    assert_eq!(reassembler.progress(), None);
+}
+
+#[test]
+fn resume_requests_round_trip() {
+    let payload = encode_resume(1234, 0xdead_beef, "ab.html");
+    assert_eq!(decode_resume(&payload), Ok((1234, 0xdead_beef, "ab.html")));
+    assert_eq!(decode_resume(&payload[..7]), Err(Error::Malformed));
+    assert_eq!(decode_resume(&[0, 0, 0, 0, 0, 0, 0, 0, 0xff]), Err(Error::Malformed));
+    assert_eq!(
+        MessageType::try_from(MessageType::Resume as u8),
+        Ok(MessageType::Resume)
+    );
+}
+
+#[test]
+fn range_requests_round_trip() {
+    let payload = encode_range(0xdead_beef, 100..350, "ns.html");
+    assert_eq!(decode_range(&payload), Ok((0xdead_beef, 100..350, "ns.html")));
+    assert_eq!(decode_range(&payload[..11]), Err(Error::Malformed));
+    let mut backwards = payload.clone();
+    backwards[4..8].copy_from_slice(&350u32.to_le_bytes());
+    backwards[8..12].copy_from_slice(&100u32.to_le_bytes());
+    assert_eq!(decode_range(&backwards), Err(Error::Malformed));
+    assert_eq!(
+        MessageType::try_from(MessageType::Range as u8),
+        Ok(MessageType::Range)
+    );
+    assert_eq!(Status::from_u8(Status::Changed as u8), Some(Status::Changed));
+}
+
+#[test]
+fn etags_can_be_computed_piecewise() {
+    let page = page("ab.swb");
+    let mut crc = Crc32::new();
+    for chunk in page.chunks(253) {
+        crc.update(chunk);
+    }
+    assert_eq!(crc.finish(), etag(&page));
+    assert_eq!(etag(&page), crc32(&page));
+
+    let payload = encode_revalidate(etag(&page), "ab.html");
+    assert_eq!(decode_revalidate(&payload), Ok((crc32(&page), "ab.html")));
+    for kind in [MessageType::Revalidate, MessageType::NotModified] {
+        assert_eq!(MessageType::try_from(kind as u8), Ok(kind));
+    }
+}
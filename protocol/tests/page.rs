@@ -0,0 +1,89 @@
+use protocol::page::{decode_header, decode_page, encode_page, Link, PageHeader, MAX_TITLE_LEN};
+use protocol::Error;
+
+fn links() -> Vec<Link> {
+    vec![
+        Link { start: 8, len: 11, href: "ab-05-10-2022.pdf".to_string() },
+        Link { start: 120, len: 4, href: "http://example.com/ä".to_string() },
+    ]
+}
+
+#[test]
+fn pages_round_trip() {
+    let program = std::fs::read(format!("{}/../client/ab.swb", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let payload = encode_page("AB", &program, &links()).unwrap();
+    let (decoded, header) = decode_page(&payload).unwrap();
+    assert_eq!(decoded, &program[..]);
+    assert_eq!(header.title, "AB");
+    assert_eq!(header.links, links());
+
+    let payload = encode_page("", &program, &[]).unwrap();
+    let header = PageHeader { title: String::new(), links: vec![], program_len: program.len() };
+    assert_eq!(decode_page(&payload).unwrap(), (&program[..], header));
+}
+
+#[test]
+fn long_titles_are_cut_at_a_character() {
+    let title = "ä".repeat(MAX_TITLE_LEN);
+    let payload = encode_page(&title, b"program", &[]).unwrap();
+    let (_, header) = decode_page(&payload).unwrap();
+    assert_eq!(header.title, "ä".repeat(MAX_TITLE_LEN / 2));
+}
+
+#[test]
+fn truncated_pages_are_malformed() {
+    let payload = encode_page("Title", b"program", &links()).unwrap();
+    for len in 0..payload.len() {
+        assert_eq!(decode_page(&payload[..len]), Err(Error::Malformed), "length {}", len);
+    }
+}
+
+#[test]
+fn links_cover_overlapping_text() {
+    let link = Link { start: 10, len: 5, href: String::new() };
+    assert!(link.covers(10, 5));
+    assert!(link.covers(12, 1));
+    assert!(link.covers(5, 6));
+    assert!(link.covers(14, 10));
+    assert!(!link.covers(5, 5));
+    assert!(!link.covers(15, 3));
+}
+
+#[test]
+fn headers_decode_before_the_program_arrives() {
+    let payload = encode_page("Title", b"program", &links()).unwrap();
+    let header_len = payload.len() - b"program".len();
+    for len in 0..header_len {
+        assert_eq!(decode_header(&payload[..len]), Ok(None), "length {}", len);
+    }
+    let header = PageHeader { title: "Title".to_string(), links: links(), program_len: 7 };
+    for len in header_len..=payload.len() {
+        assert_eq!(decode_header(&payload[..len]), Ok(Some((header.clone(), header_len))));
+    }
+}
+
+#[test]
+fn trailing_bytes_are_malformed() {
+    let mut payload = encode_page("Title", b"program", &links()).unwrap();
+    payload.push(0);
+    assert_eq!(decode_page(&payload), Err(Error::Malformed));
+}
+
+#[test]
+fn pages_the_header_can_not_count_are_too_large() {
+    let max = u16::MAX as usize;
+    let link = Link { start: 0, len: 1, href: "a".to_string() };
+    let links = vec![link; max + 1];
+    assert_eq!(
+        encode_page("Title", b"program", &links),
+        Err(Error::TooLarge { len: max + 1, max })
+    );
+    let payload = encode_page("Title", b"program", &links[1..]).unwrap();
+    assert_eq!(decode_page(&payload).unwrap().1.links.len(), max);
+
+    let long = Link { start: 0, len: 1, href: "a".repeat(max + 1) };
+    assert_eq!(
+        encode_page("Title", b"program", &[long]),
+        Err(Error::TooLarge { len: max + 1, max })
+    );
+}
@@ -0,0 +1,345 @@
+//! Compiles HTML pages into SWB programs.
+//!
+//! Only the visible text of a page and a little of its markup survive:
+//! `<b>`, `<i>`, `<u>` and `<code>` become bold, italic, underlined and code
+//! text, `<h1>` to `<h6>` headings, `<div>` and `<br>` start a new line, and
+//! `<head>`, `<script>`, `<style>` and `<title>` are left out.
+//! The text between two tags becomes a text run of its own, with every
+//! stretch of whitespace in it collapsed to one space, also across tags, and
+//! none at the start of a line. The client only draws ASCII, so other
+//! characters are written the closest way it can, or as `?`.
+//!
+//! Where the text of every `<a href>` ends up in the text segment is recorded
+//! along the way, see [`Anchor`].
+//!
+//! The program itself is built from, and encoded by, `swb_shared`.
+
+use swb_shared::{Address, Instruction, Program, Ptr, StyleVar};
+
+use crate::links::attribute;
+
+/// Elements whose content is not shown.
+const HIDDEN: [&str; 4] = ["head", "script", "style", "title"];
+
+/// A piece of HTML source.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Token<'a> {
+    /// The inside of a tag, without the angle brackets.
+    Tag(&'a str),
+    Text(&'a str),
+}
+
+/// Splits `html` into tags and the text between them, skipping comments.
+fn tokens(html: &str) -> impl Iterator<Item = Token<'_>> {
+    let mut rest = html;
+    std::iter::from_fn(move || loop {
+        if rest.is_empty() {
+            return None;
+        }
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        if let Some(tag) = rest.strip_prefix('<') {
+            let Some(end) = tag.find('>') else {
+                return Some(Token::Text(std::mem::take(&mut rest)));
+            };
+            rest = &tag[end + 1..];
+            return Some(Token::Tag(&tag[..end]));
+        }
+        let end = rest.find('<').unwrap_or(rest.len());
+        let (text, after) = rest.split_at(end);
+        rest = after;
+        return Some(Token::Text(text));
+    })
+}
+
+/// Returns whether `tag` closes an element, and its lowercase name.
+fn tag_name(tag: &str) -> Option<(bool, String)> {
+    let tag = tag.trim_start();
+    let (close, tag) = match tag.strip_prefix('/') {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, tag),
+    };
+    let end = tag
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '!'))
+        .unwrap_or(tag.len());
+    (end > 0).then(|| (close, tag[..end].to_ascii_lowercase()))
+}
+
+fn is_tag(tag: &str, close: bool, name: &str) -> bool {
+    matches!(tag_name(tag), Some((c, n)) if c == close && n == name)
+}
+
+fn named_entity(name: &str) -> Option<char> {
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "copy" => '©',
+        "ge" => '≥',
+        "le" => '≤',
+        "isin" => '∈',
+        _ => return None,
+    })
+}
+
+/// Replaces character references in `text`, leaving unknown ones as they are.
+pub(crate) fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest[1..].find(';').and_then(|end| {
+            let name = &rest[1..end + 1];
+            let c = if let Some(hex) = name.strip_prefix("#x") {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(dec) = name.strip_prefix('#') {
+                dec.parse().ok().and_then(char::from_u32)
+            } else {
+                named_entity(name)
+            };
+            c.map(|c| (c, end + 2))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Writes `text` in ASCII.
+fn transliterate(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii() {
+            out.push(c);
+            continue;
+        }
+        out.push_str(match c {
+            '\u{a0}' => " ",
+            'à'..='å' => "a",
+            'À'..='Å' => "A",
+            'æ' => "ae",
+            'Æ' => "AE",
+            'ç' => "c",
+            'Ç' => "C",
+            'è'..='ë' => "e",
+            'È'..='Ë' => "E",
+            'ì'..='ï' => "i",
+            'Ì'..='Ï' => "I",
+            'ł' => "l",
+            'Ł' => "L",
+            'ñ' => "n",
+            'Ñ' => "N",
+            'ò'..='ö' | 'ø' => "o",
+            'Ò'..='Ö' | 'Ø' => "O",
+            'ß' => "ss",
+            'ù'..='ü' => "u",
+            'Ù'..='Ü' => "U",
+            'ý' | 'ÿ' => "y",
+            '‐' | '‑' | '–' | '—' => "-",
+            '‘' | '’' => "'",
+            '“' | '”' => "\"",
+            '…' => "...",
+            '·' => ".",
+            '©' => "(c)",
+            '®' => "(R)",
+            '≤' => "<=",
+            '≥' => ">=",
+            _ => "?",
+        });
+    }
+    out
+}
+
+/// The text of an `<a href>` element in the text segment of a program.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Anchor {
+    /// The `href` attribute, with character references replaced.
+    pub href: String,
+    pub start: u32,
+    pub len: u32,
+}
+
+/// A page compiled by [`compile_page`].
+#[derive(Debug, Clone)]
+pub struct Compiled {
+    /// The bytes of the SWB program.
+    pub swb: Vec<u8>,
+    /// Anchors with text, in document order.
+    pub anchors: Vec<Anchor>,
+}
+
+/// Builds the text segment and the code of a program.
+#[derive(Default)]
+struct Builder {
+    text: String,
+    code: Vec<Instruction>,
+    /// Whether the text of the current line is empty or ends in a space.
+    spaced: bool,
+    anchors: Vec<Anchor>,
+    /// `href` of the anchor being read and where its text starts.
+    anchor: Option<(String, usize)>,
+}
+
+impl Builder {
+    fn endl(&mut self) {
+        self.code.push(Instruction::Endl);
+        self.spaced = true;
+    }
+
+    /// Adds a text run for `text`, unless nothing of it is left after
+    /// collapsing its whitespace.
+    fn text(&mut self, text: &str) {
+        let base = self.text.len();
+        for c in transliterate(text).chars() {
+            if !c.is_ascii_whitespace() {
+                self.text.push(c);
+                self.spaced = false;
+            } else if !self.spaced {
+                self.text.push(' ');
+                self.spaced = true;
+            }
+        }
+        if self.text.len() > base {
+            self.code.push(Instruction::Text(Address {
+                base: Ptr(base as u32),
+                range: (self.text.len() - base) as u32,
+            }));
+        }
+    }
+
+    /// Records the anchor being read, from the start of its text to here,
+    /// without the spaces around it.
+    fn end_anchor(&mut self) {
+        let Some((href, start)) = self.anchor.take() else {
+            return;
+        };
+        let text = &self.text[start..];
+        let start = start + (text.len() - text.trim_start().len());
+        let len = text.trim().len();
+        if len > 0 {
+            self.anchors.push(Anchor {
+                href,
+                start: start as u32,
+                len: len as u32,
+            });
+        }
+    }
+
+    fn finish(mut self) -> Compiled {
+        self.end_anchor();
+        self.code.push(Instruction::Stop);
+        let swb = Program {
+            code: self.code,
+            text: self.text,
+        }
+        .to_bytes();
+        Compiled {
+            swb,
+            anchors: self.anchors,
+        }
+    }
+}
+
+/// Compiles the HTML source of a page into the bytes of an SWB program.
+pub fn compile(html: &str) -> Vec<u8> {
+    compile_page(html).swb
+}
+
+/// Compiles the HTML source of a page, keeping track of its anchors.
+pub fn compile_page(html: &str) -> Compiled {
+    let mut builder = Builder {
+        spaced: true,
+        ..Builder::default()
+    };
+    // Open hidden elements, innermost last.
+    let mut hidden: Vec<String> = Vec::new();
+    // Source of the `<noscript>` element being read, which is shown as is.
+    let mut noscript: Option<String> = None;
+    let mut previous = None;
+
+    for token in tokens(html) {
+        let after_div = match previous.replace(token) {
+            Some(Token::Tag(tag)) => is_tag(tag, false, "div"),
+            _ => false,
+        };
+        if let Some(source) = &mut noscript {
+            match token {
+                Token::Tag(tag) if is_tag(tag, true, "noscript") => {
+                    builder.text(source);
+                    noscript = None;
+                }
+                Token::Tag(tag) => {
+                    source.push('<');
+                    source.push_str(tag);
+                    source.push('>');
+                }
+                Token::Text(text) => source.push_str(text),
+            }
+            continue;
+        }
+
+        let tag = match token {
+            Token::Text(text) => {
+                if hidden.is_empty() {
+                    builder.text(&decode_entities(text));
+                }
+                continue;
+            }
+            Token::Tag(tag) => tag,
+        };
+        let Some((close, name)) = tag_name(tag) else {
+            continue;
+        };
+        if HIDDEN.contains(&name.as_str()) {
+            if !close {
+                hidden.push(name);
+            } else if hidden.last() == Some(&name) {
+                hidden.pop();
+            }
+            continue;
+        }
+        if !hidden.is_empty() {
+            continue;
+        }
+        let style = match name.as_str() {
+            "b" => Some(StyleVar::Bold),
+            "i" => Some(StyleVar::Italic),
+            "u" => Some(StyleVar::Underline),
+            "code" => Some(StyleVar::Code),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => Some(StyleVar::Heading),
+            _ => None,
+        };
+        match (name.as_str(), style) {
+            (_, Some(style)) if close => builder.code.push(Instruction::Pop(style)),
+            (_, Some(style)) => builder.code.push(Instruction::Push(style)),
+            ("noscript", _) if !close => noscript = Some(String::new()),
+            // Anchors do not nest, a new one ends the last.
+            ("a", _) => {
+                builder.end_anchor();
+                if !close {
+                    builder.anchor = attribute(tag, "href").map(|href| (href, builder.text.len()));
+                }
+            }
+            // Nested blocks that open together start a single line.
+            ("div", _) if !close && !after_div => builder.endl(),
+            ("br", _) if !close => builder.endl(),
+            _ => {}
+        }
+    }
+    builder.finish()
+}
@@ -0,0 +1,320 @@
+//! Server configuration, read from a TOML file and the command line.
+//!
+//! Every setting has a default, so the file only needs what differs from
+//! them. Flags given on the command line override the file:
+//!
+//! ```toml
+//! # Directory pages are served from, or the web server to fetch them from.
+//! root = "/srv/pages"
+//! proxy = "http://localhost:8000"
+//! # Transports to accept clients on: "ble", "tcp:<addr>" or "unix:<path>".
+//! listen = ["ble", "tcp:127.0.0.1:7878"]
+//! # Serve clients on sockets, which are not encrypted. Only for sockets that
+//! # nobody but trusted clients can reach.
+//! trust_sockets = true
+//! log_level = "info"
+//! # Run until SIGTERM instead of until a line is read from stdin.
+//! daemon = true
+//!
+//! [ble]
+//! adapter = "hci0"
+//! name = "bambi_gatt_server"
+//! service_uuid = "feed"
+//! characteristic_uuid = "f00d"
+//! ```
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+pub const USAGE: &str = "\
+Usage: server [options] [content root]
+       server compile <in.html> <out.swb>
+
+Options:
+  --config <file>               read settings from a TOML file
+  --ble                         accept clients over Bluetooth
+  --tcp <addr>                  accept clients on a TCP socket
+  --unix <path>                 accept clients on a Unix socket
+  --trust-sockets               serve socket clients, which are not encrypted
+  --proxy <url>                 serve the pages of an http:// server
+  --adapter <name>              Bluetooth adapter to use
+  --name <name>                 name to advertise
+  --service-uuid <uuid>         UUID of the page service
+  --characteristic-uuid <uuid>  UUID of the page characteristic
+  --log-level <level>           error, warn, info, debug or trace
+  --daemon                      run until SIGTERM";
+
+/// A 128-bit UUID, written out in full or as a 16-bit Bluetooth UUID.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Uuid(pub u128);
+
+impl Uuid {
+    /// The 128-bit form of 16-bit Bluetooth UUID `short`, which stands for
+    /// `0000xxxx-0000-1000-8000-00805f9b34fb`.
+    pub const fn from_u16(short: u16) -> Self {
+        Uuid(0x0000_0000_0000_1000_8000_0080_5f9b_34fb | ((short as u128) << 96))
+    }
+}
+
+impl FromStr for Uuid {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, ConfigError> {
+        let invalid = || ConfigError::Invalid(format!("invalid UUID {:?}", s));
+        let short = s.trim_start_matches("0x");
+        if short.len() == 4 {
+            let short = u16::from_str_radix(short, 16).map_err(|_| invalid())?;
+            return Ok(Uuid::from_u16(short));
+        }
+        let dashes = [8, 13, 18, 23];
+        let well_formed = s.len() == 36
+            && s.char_indices()
+                .all(|(i, c)| if dashes.contains(&i) { c == '-' } else { c.is_ascii_hexdigit() });
+        if !well_formed {
+            return Err(invalid());
+        }
+        u128::from_str_radix(&s.replace('-', ""), 16).map(Uuid).map_err(|_| invalid())
+    }
+}
+
+impl TryFrom<String> for Uuid {
+    type Error = ConfigError;
+
+    fn try_from(s: String) -> Result<Self, ConfigError> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = format!("{:032x}", self.0);
+        write!(f, "{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+    }
+}
+
+/// A way for clients to reach the server.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Listen {
+    Ble,
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl FromStr for Listen {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, ConfigError> {
+        if s == "ble" {
+            return Ok(Listen::Ble);
+        }
+        match s.split_once(':') {
+            Some(("tcp", addr)) => Ok(Listen::Tcp(addr.to_string())),
+            Some(("unix", path)) => Ok(Listen::Unix(PathBuf::from(path))),
+            _ => Err(ConfigError::Invalid(format!("unknown transport {:?}", s))),
+        }
+    }
+}
+
+impl TryFrom<String> for Listen {
+    type Error = ConfigError;
+
+    fn try_from(s: String) -> Result<Self, ConfigError> {
+        s.parse()
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BleConfig {
+    /// Adapter to advertise on, the default adapter if `None`.
+    pub adapter: Option<String>,
+    /// Local name in the advertisement.
+    pub name: String,
+    pub service_uuid: Uuid,
+    pub characteristic_uuid: Uuid,
+}
+
+impl Default for BleConfig {
+    fn default() -> Self {
+        Self {
+            adapter: None,
+            name: "bambi_gatt_server".to_string(),
+            service_uuid: Uuid::from_u16(0xfeed),
+            characteristic_uuid: Uuid::from_u16(0xf00d),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory pages are served from.
+    pub root: PathBuf,
+    /// Base URL of a web server to serve the pages of instead of `root`.
+    pub proxy: Option<String>,
+    pub listen: Vec<Listen>,
+    /// Serve clients on TCP and Unix sockets. Nothing sent over them is
+    /// encrypted, so by default they get no pages.
+    pub trust_sockets: bool,
+    /// Filter for the log, in `env_logger` syntax. `RUST_LOG` overrides it.
+    pub log_level: String,
+    /// Run until SIGTERM rather than until a line is read from stdin.
+    pub daemon: bool,
+    pub ble: BleConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("."),
+            proxy: None,
+            listen: vec![Listen::Ble],
+            trust_sockets: false,
+            log_level: "info".to_string(),
+            daemon: false,
+            ble: BleConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The command line could not be parsed.
+    Usage(String),
+    /// A setting has a value that makes no sense.
+    Invalid(String),
+    /// The configuration file is not valid TOML or has unknown settings.
+    Parse(toml::de::Error),
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Usage(reason) => write!(f, "{}", reason),
+            ConfigError::Invalid(reason) => write!(f, "{}", reason),
+            ConfigError::Parse(err) => write!(f, "{}", err),
+            ConfigError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn from_toml(toml: &str) -> Result<Self, ConfigError> {
+        toml::from_str(toml).map_err(ConfigError::Parse)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let toml = std::fs::read_to_string(path)
+            .map_err(|err| ConfigError::Io(path.to_path_buf(), err))?;
+        Self::from_toml(&toml)
+    }
+
+    /// Builds the configuration from command line arguments, without the
+    /// program name: the file given with `--config` if any, overridden by the
+    /// other flags. Transports given on the command line replace those in
+    /// the file.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        // Flags are only collected here, as they override the file that may
+        // come after them.
+        let mut file = None;
+        let mut flags: Vec<Flag> = Vec::new();
+        let mut listen = Vec::new();
+        let mut root = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| missing(&arg));
+            match arg.as_str() {
+                "--config" if file.is_some() => {
+                    return Err(ConfigError::Usage("--config given twice".to_string()));
+                }
+                "--config" => file = Some(PathBuf::from(value()?)),
+                "--ble" => listen.push(Listen::Ble),
+                "--tcp" => listen.push(Listen::Tcp(value()?)),
+                "--unix" => listen.push(Listen::Unix(PathBuf::from(value()?))),
+                "--trust-sockets" => flags.push(Box::new(|config| config.trust_sockets = true)),
+                "--proxy" => {
+                    let proxy = value()?;
+                    flags.push(Box::new(|config| config.proxy = Some(proxy)));
+                }
+                "--adapter" => {
+                    let adapter = value()?;
+                    flags.push(Box::new(|config| config.ble.adapter = Some(adapter)));
+                }
+                "--name" => {
+                    let name = value()?;
+                    flags.push(Box::new(|config| config.ble.name = name));
+                }
+                "--service-uuid" => {
+                    let uuid = value()?.parse()?;
+                    flags.push(Box::new(move |config| config.ble.service_uuid = uuid));
+                }
+                "--characteristic-uuid" => {
+                    let uuid = value()?.parse()?;
+                    flags.push(Box::new(move |config| config.ble.characteristic_uuid = uuid));
+                }
+                "--log-level" => {
+                    let level = value()?;
+                    flags.push(Box::new(|config| config.log_level = level));
+                }
+                "--daemon" => flags.push(Box::new(|config| config.daemon = true)),
+                _ if arg.starts_with('-') => {
+                    return Err(ConfigError::Usage(format!("unknown option {}", arg)));
+                }
+                _ if root.is_some() => {
+                    return Err(ConfigError::Usage(format!("unexpected argument {}", arg)));
+                }
+                _ => root = Some(PathBuf::from(arg)),
+            }
+        }
+
+        let mut config = match file {
+            Some(path) => Self::load(&path)?,
+            None => Self::default(),
+        };
+        for flag in flags {
+            flag(&mut config);
+        }
+        if let Some(root) = root {
+            config.root = root;
+        }
+        if !listen.is_empty() {
+            config.listen = listen;
+        }
+        config.check()?;
+        Ok(config)
+    }
+
+    /// Rejects settings that can not work together.
+    pub fn check(&self) -> Result<(), ConfigError> {
+        if self.listen.is_empty() {
+            return Err(ConfigError::Invalid("no transports to listen on".to_string()));
+        }
+        if let Some(proxy) = &self.proxy {
+            if !proxy.starts_with("http://") {
+                return Err(ConfigError::Invalid(format!("proxy {} is not an http:// URL", proxy)));
+            }
+        }
+        if self.ble.service_uuid == self.ble.characteristic_uuid {
+            return Err(ConfigError::Invalid(
+                "service and characteristic need different UUIDs".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A setting given on the command line, applied once the file is loaded.
+type Flag = Box<dyn FnOnce(&mut Config)>;
+
+fn missing(flag: &str) -> ConfigError {
+    ConfigError::Usage(format!("{} needs a value", flag))
+}
@@ -0,0 +1,13 @@
+//! Request handling for the bambi browser server.
+//!
+//! The binary in `main.rs` only picks a transport; everything that decides
+//! what bytes go back to the client lives here so it can be exercised without
+//! an adapter.
+
+pub mod compile;
+pub mod config;
+pub mod links;
+pub mod pages;
+pub mod session;
+pub mod transport;
+pub mod upstream;
@@ -0,0 +1,105 @@
+//! Recovers the hyperlinks and the title of a page from its HTML source.
+//!
+//! Where the text of every `<a href>` ends up in the program is recorded by
+//! the [compiler](crate::compile), this resolves the links for the client.
+
+use protocol::page::Link;
+
+use crate::compile::{decode_entities, Anchor};
+
+/// Finds the text of the `<title>` element in `html`.
+pub fn title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let start = lower.find("<title")?;
+    let start = start + lower[start..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = collapse_whitespace(&decode_entities(&html[start..end]));
+    (!title.is_empty()).then_some(title)
+}
+
+/// Returns the value of attribute `name` in the inside of a start tag.
+pub(crate) fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut pos = 0;
+    while let Some(i) = lower[pos..].find(name).map(|i| pos + i) {
+        pos = i + name.len();
+        let preceded_by_space = lower[..i].ends_with(|c: char| c.is_ascii_whitespace());
+        let rest = lower[pos..].trim_start();
+        if !preceded_by_space || !rest.starts_with('=') {
+            continue;
+        }
+        let value_start = tag.len() - rest.len() + 1;
+        let value = tag[value_start..].trim_start();
+        let value = match value.chars().next()? {
+            quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+            _ => value.split(|c: char| c.is_ascii_whitespace()).next()?,
+        };
+        return Some(decode_entities(value.trim()));
+    }
+    None
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Resolves `href` relative to the page at `base`, the way the client will
+/// request it again.
+///
+/// Links within the same page have no use without anchors in SWB and are
+/// returned as `None`.
+pub fn resolve_href(base: &str, href: &str) -> Option<String> {
+    if href.is_empty() || href.starts_with('#') {
+        return None;
+    }
+    if href.contains("://") {
+        return Some(href.to_string());
+    }
+
+    let base = base.split(['?', '#']).next().unwrap_or("");
+    let (prefix, base_path) = match base.split_once("://") {
+        Some((scheme, rest)) => match rest.find('/') {
+            Some(i) => (format!("{}://{}", scheme, &rest[..i]), &rest[i..]),
+            None => (format!("{}://{}", scheme, rest), "/"),
+        },
+        None => (String::new(), base),
+    };
+
+    let mut parts: Vec<&str> = if href.starts_with('/') {
+        Vec::new()
+    } else {
+        let dir = base_path.rfind('/').map_or("", |i| &base_path[..i]);
+        dir.split('/').filter(|p| !p.is_empty()).collect()
+    };
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+
+    let path = parts.join("/");
+    if prefix.is_empty() {
+        Some(path)
+    } else {
+        Some(format!("{}/{}", prefix, path))
+    }
+}
+
+/// The links of the page at `base`, from the `anchors` recorded while
+/// compiling it.
+pub fn links(base: &str, anchors: &[Anchor]) -> Vec<Link> {
+    anchors
+        .iter()
+        .filter_map(|anchor| {
+            Some(Link {
+                start: anchor.start,
+                len: anchor.len,
+                href: resolve_href(base, &anchor.href)?,
+            })
+        })
+        .collect()
+}
@@ -1,140 +1,138 @@
-//! Serves a Bluetooth GATT echo server.
+//! Serves SWB pages to the bambi browser.
+//!
+//! Usage: `server [options] [content root]`, see [`server::config`] for the
+//! options and the configuration file. Bluetooth is used unless other
+//! transports are given, the content root defaults to the current directory.
+//!
+//! `server compile <in.html> <out.swb>` compiles a single page instead.
 
 #![feature(async_closure)]
 
-use bluer::{
-    adv::Advertisement,
-    Uuid,
-    gatt::{
-        local::{
-            characteristic_control, Application, Characteristic, CharacteristicControlEvent,
-            CharacteristicNotify, CharacteristicNotifyMethod, CharacteristicWrite, CharacteristicWriteMethod,
-            Service, CharacteristicRead,
-        },
-        CharacteristicReader, CharacteristicWriter,
-    }, UuidExt,
-};
-use futures::{future, pin_mut, StreamExt};
+use futures::future::try_join_all;
+use log::{info, warn};
+use server::compile::compile;
+use server::config::{Config, ConfigError, Listen, USAGE};
+use server::pages::ContentRoot;
+use server::session::{self, Transfers};
+use server::transport::{BleTransport, TcpTransport, Transport, UnixTransport};
 use std::time::Duration;
 use tokio::{
-    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncBufReadExt, BufReader},
+    signal::unix::{signal, SignalKind},
     time::sleep,
 };
 
-#[tokio::main]
-async fn main() -> bluer::Result<()> {
-    /// Service UUID for GATT example.
-    let SERVICE_UUID: Uuid = Uuid::from_u16(0xfeed);
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+/// Runs `server compile`, with `args` the arguments after the subcommand.
+fn compile_page(args: impl Iterator<Item = String>) -> std::io::Result<()> {
+    let paths: Vec<String> = args.collect();
+    if paths.iter().any(|arg| arg.starts_with('-')) {
+        usage();
+    }
+    let [input, output] = <[String; 2]>::try_from(paths).unwrap_or_else(|_| usage());
+    let html = std::fs::read(&input)?;
+    let swb = compile(&String::from_utf8_lossy(&html));
+    std::fs::write(&output, &swb)?;
+    println!("Compiled {} to {} ({} bytes)", input, output, swb.len());
+    Ok(())
+}
 
-    /// Characteristic UUID for GATT example.
-    let CHARACTERISTIC_UUID: Uuid = Uuid::from_u16(0xf00d);
-    env_logger::init();
-    let session = bluer::Session::new().await?;
-    let adapter = session.default_adapter().await?;
-    adapter.set_powered(true).await?;
+async fn open(listen: &Listen, config: &Config) -> Result<Box<dyn Transport>, Box<dyn std::error::Error>> {
+    Ok(match listen {
+        Listen::Ble => Box::new(BleTransport::new(&config.ble).await?),
+        Listen::Tcp(addr) => {
+            let transport = TcpTransport::bind(addr.as_str())
+                .await?
+                .with_trusted(config.trust_sockets);
+            info!("Listening on tcp://{}", transport.local_addr()?);
+            Box::new(transport)
+        }
+        Listen::Unix(path) => {
+            let transport = UnixTransport::bind(path)?.with_trusted(config.trust_sockets);
+            info!("Listening on unix:{}", transport.path().display());
+            Box::new(transport)
+        }
+    })
+}
 
-    println!("Advertising on Bluetooth adapter {} with address {}", adapter.name(), adapter.address().await?);
-    let le_advertisement = Advertisement {
-        service_uuids: vec![SERVICE_UUID].into_iter().collect(),
-        discoverable: Some(true),
-        local_name: Some("bambi_gatt_server".to_string()),
-        ..Default::default()
+/// Waits until the server is asked to stop: by SIGTERM or SIGINT, or
+/// unless running as a daemon, by a line on stdin.
+async fn stopped(daemon: bool) -> std::io::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let enter = async {
+        if daemon {
+            futures::future::pending::<()>().await;
+        }
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let _ = lines.next_line().await;
     };
-    let adv_handle = adapter.advertise(le_advertisement).await?;
+    tokio::select! {
+        _ = terminate.recv() => info!("Received SIGTERM"),
+        _ = interrupt.recv() => info!("Received SIGINT"),
+        _ = enter => {}
+    }
+    Ok(())
+}
 
-    println!("Serving GATT echo service on Bluetooth adapter {}", adapter.name());
-    let (char_control, char_handle) = characteristic_control();
-    let app = Application {
-        services: vec![Service {
-            uuid: SERVICE_UUID,
-            primary: true,
-            characteristics: vec![Characteristic {
-                uuid: CHARACTERISTIC_UUID,
-                write: Some(CharacteristicWrite {
-                    write_without_response: true,
-                    method: CharacteristicWriteMethod::Io,
-                    ..Default::default()
-                }),
-                read: Some(CharacteristicRead {
-                    read: true,
-                    fun: Box::new(|req| Box::pin(async move {
-                        Ok(vec![5u8])
-                    })),
-                    ..Default::default()
-                }),
-                notify: Some(CharacteristicNotify {
-                    notify: true,
-                    ..Default::default()
-                }),
-                control_handle: char_handle,
-                ..Default::default()
-            }],
-            ..Default::default()
-        }],
-        ..Default::default()
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("compile") {
+        compile_page(args.skip(1))?;
+        return Ok(());
+    }
+    let config = match Config::from_args(args) {
+        Ok(config) => config,
+        Err(ConfigError::Usage(reason)) => {
+            eprintln!("{}", reason);
+            usage();
+        }
+        Err(err) => return Err(err.into()),
     };
-    let app_handle = adapter.serve_gatt_application(app).await?;
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(&config.log_level))
+        .init();
 
-    println!("Echo service ready. Press enter to quit.");
-    let stdin = BufReader::new(tokio::io::stdin());
-    let mut lines = stdin.lines();
+    let mut content = ContentRoot::new(&config.root);
+    match &config.proxy {
+        Some(upstream) => {
+            content = content.with_upstream(upstream);
+            info!("Serving pages from {}", upstream);
+        }
+        None => info!("Serving pages from {}", content.path().display()),
+    }
 
-    let mut read_buf = Vec::new();
-    let mut reader_opt: Option<CharacteristicReader> = None;
-    let mut writer_opt: Option<CharacteristicWriter> = None;
-    pin_mut!(char_control);
+    if !config.trust_sockets && config.listen.iter().any(|listen| *listen != Listen::Ble) {
+        warn!("Socket clients get no pages unless the sockets are trusted");
+    }
+    let mut transports = Vec::new();
+    for listen in &config.listen {
+        transports.push(open(listen, &config).await?);
+    }
 
-    loop {
+    if config.daemon {
+        info!("Page service ready");
+    } else {
+        info!("Page service ready. Press enter to quit.");
+    }
+    {
+        let transfers = Transfers::new();
+        let sessions = try_join_all(transports.iter_mut().map(|transport| {
+            session::run_with(transport.as_mut(), content.clone(), transfers.clone())
+        }));
         tokio::select! {
-            _ = lines.next_line() => break,
-            evt = char_control.next() => {
-                match evt {
-                    Some(CharacteristicControlEvent::Write(req)) => {
-                        println!("Accepting write request event with MTU {}", req.mtu());
-                        read_buf = vec![0; req.mtu()];
-                        reader_opt = Some(req.accept()?);
-                    },
-                    Some(CharacteristicControlEvent::Notify(writer)) => {
-
-                    },
-                    None => break,
-                }
-            },
-            read_res = async {
-                match &mut reader_opt {
-                    Some(reader) if writer_opt.is_some() => reader.read(&mut read_buf).await,
-                    _ => future::pending().await,
-                }
-            } => {
-                match read_res {
-                    Ok(0) => {
-                        println!("Read stream ended");
-                        reader_opt = None;
-                    }
-                    Ok(n) => {
-                        let value = read_buf[..n].to_vec();
-                        println!("Echoing {} bytes: {:x?} ... {:x?}", value.len(), &value[0..4.min(value.len())], &value[value.len().saturating_sub(4) ..]);
-                        if value.len() < 512 {
-                            println!();
-                        }
-                        if let Err(err) = writer_opt.as_mut().unwrap().write_all(&value).await {
-                            println!("Write failed: {}", &err);
-                            writer_opt = None;
-                        }
-                    }
-                    Err(err) => {
-                        println!("Read stream error: {}", &err);
-                        reader_opt = None;
-                    }
-                }
-            }
+            res = stopped(config.daemon) => res?,
+            res = sessions => { res?; }
         }
     }
 
-    println!("Removing service and advertisement");
-    drop(app_handle);
-    drop(adv_handle);
+    info!("Removing service and advertisement");
+    drop(transports);
     sleep(Duration::from_secs(1)).await;
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,299 @@
+//! Resolves page requests against a local content root and produces the SWB
+//! bytes that are sent back to the client.
+//!
+//! A request names the page, either as a path relative to the content root
+//! (`ab.html`) or as a URL whose path component is used
+//! (`http://localhost/ab.html`). HTML pages are compiled when they are
+//! requested, with their title and links taken from the HTML itself, and
+//! pages without an HTML source are served from their precompiled `.swb`
+//! sibling. A content root with an upstream server fetches and compiles every
+//! page from there instead.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use log::{info, warn};
+use protocol::page::{encode_page, Link};
+use protocol::{encode_error, MessageType, Status};
+use swb_shared::Program;
+
+use crate::compile;
+use crate::links;
+use crate::upstream::{self, UpstreamError};
+
+/// Page served when the client requests the root of the content directory.
+pub const INDEX_PAGE: &str = "index.html";
+
+#[derive(Debug)]
+pub enum PageError {
+    /// The request was not valid UTF-8 or tried to escape the content root.
+    BadRequest(String),
+    /// There is no page at the requested location.
+    NotFound(String),
+    /// Fetching the page from the upstream server failed.
+    Upstream(UpstreamError),
+    /// The page exists but is not a valid SWB program.
+    InvalidPage(String),
+    Io(io::Error),
+}
+
+impl PageError {
+    pub fn status(&self) -> Status {
+        match self {
+            PageError::BadRequest(_) => Status::BadRequest,
+            PageError::NotFound(_) => Status::NotFound,
+            PageError::Upstream(_) => Status::NotFound,
+            PageError::InvalidPage(_) => Status::InvalidPage,
+            PageError::Io(_) => Status::NotFound,
+        }
+    }
+}
+
+impl fmt::Display for PageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageError::BadRequest(reason) => write!(f, "bad request: {}", reason),
+            PageError::NotFound(page) => write!(f, "page not found: {}", page),
+            PageError::Upstream(err) => write!(f, "upstream: {}", err),
+            PageError::InvalidPage(reason) => write!(f, "invalid page: {}", reason),
+            PageError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for PageError {}
+
+impl From<io::Error> for PageError {
+    fn from(err: io::Error) -> Self {
+        PageError::Io(err)
+    }
+}
+
+/// A page as it is sent to the client.
+struct Page {
+    title: String,
+    swb: Vec<u8>,
+    links: Vec<Link>,
+}
+
+impl Page {
+    /// Compiles the HTML source of the page at `request`.
+    fn compile(request: &str, html: &str) -> Self {
+        let compiled = compile::compile_page(html);
+        Self {
+            title: links::title(html).unwrap_or_default(),
+            swb: compiled.swb,
+            links: links::links(request, &compiled.anchors),
+        }
+    }
+}
+
+/// Directory from which pages are served.
+#[derive(Debug, Clone)]
+pub struct ContentRoot {
+    root: PathBuf,
+    /// Base URL of the server pages are fetched from instead.
+    upstream: Option<String>,
+}
+
+impl ContentRoot {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            upstream: None,
+        }
+    }
+
+    /// Serves the pages of the web server at `base` instead of local files.
+    pub fn with_upstream(mut self, base: impl Into<String>) -> Self {
+        self.upstream = Some(base.into());
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn upstream(&self) -> Option<&str> {
+        self.upstream.as_deref()
+    }
+
+    /// Maps a request onto a file below the content root.
+    ///
+    /// Only the percent-decoded path of a URL is considered, and any attempt
+    /// to leave the content root with `..` or an absolute path is rejected.
+    pub fn resolve(&self, request: &str) -> Result<PathBuf, PageError> {
+        let mut resolved = self.root.clone();
+        resolved.extend(path_segments(request)?);
+        if resolved == self.root || resolved.is_dir() {
+            resolved.push(INDEX_PAGE);
+        }
+        Ok(resolved)
+    }
+
+    /// Loads the SWB program for `request`, compiled from the HTML source of
+    /// the page if it has one.
+    pub fn load(&self, request: &str) -> Result<Vec<u8>, PageError> {
+        self.page(request).map(|page| page.swb)
+    }
+
+    /// Loads the page at `request`.
+    ///
+    /// Pages with an HTML source are compiled from it. Requests for a `.swb`
+    /// file, and pages with nothing but a precompiled program, get that
+    /// program as it is, without a title or links.
+    fn page(&self, request: &str) -> Result<Page, PageError> {
+        let path = self.resolve(request)?;
+        let swb = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("swb") => path,
+            _ => match fs::read(path.with_extension("html")) {
+                Ok(html) => return Ok(Page::compile(request, &String::from_utf8_lossy(&html))),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => path.with_extension("swb"),
+                Err(err) => return Err(err.into()),
+            },
+        };
+        let bytes = match fs::read(&swb) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(PageError::NotFound(request.to_string()))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        // Refuse to send anything the client would not be able to parse.
+        if let Err(swb_shared::Error(e)) = Program::try_from(bytes.as_slice()) {
+            return Err(PageError::InvalidPage(format!("{}: {}", swb.display(), e)));
+        }
+        Ok(Page {
+            title: String::new(),
+            swb: bytes,
+            links: Vec::new(),
+        })
+    }
+
+    /// Fetches the page at `request` from the server at `base` and compiles
+    /// it. Requests are checked like in [`ContentRoot::resolve`] first.
+    async fn proxy(&self, base: &str, request: &str) -> Result<Page, PageError> {
+        let url = upstream::join(base, &upstream_path(request)?);
+        let html = match upstream::get(&url).await {
+            Ok(html) => String::from_utf8_lossy(&html).into_owned(),
+            Err(UpstreamError::Status(404)) => return Err(PageError::NotFound(request.to_string())),
+            Err(err) => return Err(PageError::Upstream(err)),
+        };
+        Ok(Page::compile(request, &html))
+    }
+
+    /// Builds the response message for the payload of a request message.
+    pub async fn respond(&self, request: &[u8]) -> (MessageType, Vec<u8>) {
+        let result = match parse_request(request) {
+            Ok(request) => {
+                info!("Serving {}", request);
+                let page = match &self.upstream {
+                    Some(base) => self.proxy(base, request).await,
+                    None => self.page(request),
+                };
+                page.and_then(|page| {
+                    encode_page(&page.title, &page.swb, &page.links)
+                        .map_err(|err| PageError::InvalidPage(err.to_string()))
+                })
+            }
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(page) => (MessageType::Page, page),
+            Err(err) => {
+                warn!("Request failed: {}", err);
+                (MessageType::Error, encode_error(err.status(), &err.to_string()))
+            }
+        }
+    }
+}
+
+/// Decodes a request and strips surrounding whitespace from it.
+pub fn parse_request(request: &[u8]) -> Result<&str, PageError> {
+    let request = std::str::from_utf8(request)
+        .map_err(|e| PageError::BadRequest(format!("request is not UTF-8: {}", e)))?;
+    Ok(request.trim())
+}
+
+/// Returns the path component of a URL, or the request itself if it is not one.
+fn request_path(request: &str) -> &str {
+    let path = match request.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |start| &rest[start..]),
+        None => request,
+    };
+    let end = path.find(['?', '#']).unwrap_or(path.len());
+    &path[..end]
+}
+
+/// Splits the path of `request` into its percent-decoded segments.
+///
+/// Segments that would leave the content root, or that hold control
+/// characters, make the request a bad one. `.` and empty segments are
+/// dropped.
+fn path_segments(request: &str) -> Result<Vec<String>, PageError> {
+    let path = percent_decode(request_path(request))
+        .ok_or_else(|| PageError::BadRequest(format!("{} is not a valid path", request)))?;
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        if segment.chars().any(char::is_control) {
+            return Err(PageError::BadRequest(format!(
+                "{} contains control characters",
+                request.escape_debug()
+            )));
+        }
+        for component in Path::new(segment).components() {
+            match component {
+                Component::Normal(part) => segments.push(part.to_string_lossy().into_owned()),
+                Component::CurDir => {}
+                Component::RootDir => {}
+                Component::ParentDir | Component::Prefix(_) => {
+                    return Err(PageError::BadRequest(format!(
+                        "{} is outside the content root",
+                        request
+                    )));
+                }
+            }
+        }
+    }
+    Ok(segments)
+}
+
+/// The path of `request` as sent to the upstream server: its segments
+/// percent-encoded again, so nothing in it can end the request line.
+fn upstream_path(request: &str) -> Result<String, PageError> {
+    let mut path = String::new();
+    for segment in path_segments(request)? {
+        path.push('/');
+        for byte in segment.bytes() {
+            if byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&byte) {
+                path.push(byte as char);
+            } else {
+                path.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    if request_path(request).ends_with('/') {
+        path.push('/');
+    }
+    Ok(path)
+}
+
+/// Decodes the `%XX` escapes in `path`. Returns `None` for escapes that are
+/// cut short or do not decode to UTF-8.
+fn percent_decode(path: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
@@ -0,0 +1,297 @@
+//! Request/response loop run for every connected client.
+//!
+//! The responses sent last are kept in [`Transfers`], so a client that loses
+//! the connection halfway through a page can reconnect and ask for the rest
+//! with a [`MessageType::Resume`] message. Responses are found by their URL
+//! and the checksum of the bytes the client got, not by [`Link::peer`]:
+//! socket clients get another peer name for every connection.
+//!
+//! Clients that cached a page ask for it with a [`MessageType::Revalidate`]
+//! message instead, and only get it again if it changed since.
+//!
+//! Clients only keep part of long pages in memory, and ask for the rest with
+//! [`MessageType::Range`] messages as the user scrolls to it.
+//!
+//! Nothing is sent over links that are not [secure](Link::secure), so pages
+//! can neither be read nor forged by anyone nearby.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use protocol::{
+    crc32, decode_range, decode_resume, decode_revalidate, encode_error, etag, fragments,
+    frame_len, MessageType, Reassembler, Status,
+};
+use log::{debug, info, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::pages::ContentRoot;
+use crate::transport::{Link, Transport};
+
+/// Requests are a URL, anything longer than this is not worth buffering.
+pub const MAX_REQUEST_LEN: usize = 1024;
+
+/// Responses that are remembered, the least recently used one is forgotten
+/// first.
+pub const MAX_TRANSFERS: usize = 64;
+
+/// A response sent lately.
+struct Transfer {
+    kind: MessageType,
+    payload: Arc<Vec<u8>>,
+    last_used: Instant,
+}
+
+/// The responses sent lately, by URL and [`etag`], shared by all links.
+#[derive(Clone, Default)]
+pub struct Transfers {
+    responses: Arc<Mutex<HashMap<(String, u32), Transfer>>>,
+}
+
+impl Transfers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remembers that `payload` is being sent in answer to `url`.
+    pub fn start(&self, url: &str, kind: MessageType, payload: Arc<Vec<u8>>) {
+        let mut responses = self.responses.lock().unwrap();
+        let key = (url.to_string(), etag(&payload));
+        if responses.len() >= MAX_TRANSFERS && !responses.contains_key(&key) {
+            let oldest = responses
+                .iter()
+                .min_by_key(|(_, transfer)| transfer.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                responses.remove(&oldest);
+            }
+        }
+        let transfer = Transfer {
+            kind,
+            payload,
+            last_used: Instant::now(),
+        };
+        responses.insert(key, transfer);
+    }
+
+    /// Finds the response to `url` whose first `offset` bytes have the
+    /// [`crc32`] `received`, the latest one if there are several. Returns
+    /// its type and payload.
+    pub fn resume(
+        &self,
+        url: &str,
+        offset: usize,
+        received: u32,
+    ) -> Option<(MessageType, Arc<Vec<u8>>)> {
+        let mut responses = self.responses.lock().unwrap();
+        let transfer = responses
+            .iter_mut()
+            .filter(|((sent, _), transfer)| {
+                sent == url
+                    && transfer
+                        .payload
+                        .get(..offset)
+                        .is_some_and(|part| crc32(part) == received)
+            })
+            .map(|(_, transfer)| transfer)
+            .max_by_key(|transfer| transfer.last_used)?;
+        transfer.last_used = Instant::now();
+        Some((transfer.kind, transfer.payload.clone()))
+    }
+
+    /// The page sent in answer to `url` whose ETag is `page_etag`.
+    pub fn page(&self, url: &str, page_etag: u32) -> Option<Arc<Vec<u8>>> {
+        let responses = self.responses.lock().unwrap();
+        let transfer = responses.get(&(url.to_string(), page_etag))?;
+        (transfer.kind == MessageType::Page).then(|| transfer.payload.clone())
+    }
+}
+
+/// Answers a message from `peer` with the type and payload of the response,
+/// and the offset in it to send from.
+async fn answer(
+    content: &ContentRoot,
+    transfers: &Transfers,
+    peer: &str,
+    kind: MessageType,
+    payload: &[u8],
+) -> (MessageType, Arc<Vec<u8>>, usize) {
+    match kind {
+        MessageType::Request => {
+            let (kind, response) = content.respond(payload).await;
+            let response = Arc::new(response);
+            let url = String::from_utf8_lossy(payload);
+            transfers.start(url.trim(), kind, response.clone());
+            (kind, response, 0)
+        }
+        MessageType::Revalidate => {
+            let Ok((client_etag, url)) = decode_revalidate(payload) else {
+                let reason = "malformed revalidation";
+                return (
+                    MessageType::Error,
+                    Arc::new(encode_error(Status::BadRequest, reason)),
+                    0,
+                );
+            };
+            let (kind, response) = content.respond(url.as_bytes()).await;
+            if kind == MessageType::Page && etag(&response) == client_etag {
+                info!("{} is still current for {}", url.trim(), peer);
+                return (MessageType::NotModified, Arc::new(Vec::new()), 0);
+            }
+            let response = Arc::new(response);
+            transfers.start(url.trim(), kind, response.clone());
+            (kind, response, 0)
+        }
+        MessageType::Resume => {
+            let resumed = decode_resume(payload).ok().and_then(|(offset, received, url)| {
+                let (kind, response) = transfers.resume(url.trim(), offset as usize, received)?;
+                info!("Resuming {} for {} at byte {}", url.trim(), peer, offset);
+                Some((kind, response, offset as usize))
+            });
+            resumed.unwrap_or_else(|| {
+                let reason = "no transfer to resume";
+                (
+                    MessageType::Error,
+                    Arc::new(encode_error(Status::NoTransfer, reason)),
+                    0,
+                )
+            })
+        }
+        MessageType::Range => {
+            let Ok((page_etag, range, url)) = decode_range(payload) else {
+                let reason = "malformed range request";
+                return (
+                    MessageType::Error,
+                    Arc::new(encode_error(Status::BadRequest, reason)),
+                    0,
+                );
+            };
+            let url = url.trim();
+            // Pages are only compiled again if they were not sent lately.
+            let page = match transfers.page(url, page_etag) {
+                Some(page) => Some(page),
+                None => {
+                    let (kind, response) = content.respond(url.as_bytes()).await;
+                    let current = kind == MessageType::Page && etag(&response) == page_etag;
+                    current.then(|| Arc::new(response))
+                }
+            };
+            let Some(page) = page else {
+                let reason = "page changed";
+                return (
+                    MessageType::Error,
+                    Arc::new(encode_error(Status::Changed, reason)),
+                    0,
+                );
+            };
+            match page.get(range.start as usize..range.end as usize) {
+                Some(part) => {
+                    debug!("Sending bytes {:?} of {} to {}", range, url, peer);
+                    (MessageType::Page, Arc::new(part.to_vec()), 0)
+                }
+                None => {
+                    let reason = "range past the end of the page";
+                    (
+                        MessageType::Error,
+                        Arc::new(encode_error(Status::BadRequest, reason)),
+                        0,
+                    )
+                }
+            }
+        }
+        kind => {
+            let reason = format!("unexpected {:?} message", kind);
+            (
+                MessageType::Error,
+                Arc::new(encode_error(Status::BadRequest, &reason)),
+                0,
+            )
+        }
+    }
+}
+
+/// Sends `payload` as a framed message, one frame per write.
+pub async fn send(link: &mut Link, kind: MessageType, payload: &[u8]) -> io::Result<()> {
+    for frame in fragments(kind, payload, link.write_mtu) {
+        link.writer.write_all(&frame).await?;
+    }
+    link.writer.flush().await
+}
+
+/// Answers requests on `link` until the client goes away.
+pub async fn serve(content: &ContentRoot, transfers: &Transfers, mut link: Link) -> io::Result<()> {
+    let mut read_buf = vec![0; link.read_mtu];
+    let mut pending = Vec::new();
+    let mut reassembler = Reassembler::new(MAX_REQUEST_LEN);
+
+    loop {
+        let n = link.reader.read(&mut read_buf).await?;
+        if n == 0 {
+            info!("Read stream from {} ended", link.peer);
+            return Ok(());
+        }
+
+        pending.extend_from_slice(&read_buf[..n]);
+        while let Some(len) = frame_len(&pending) {
+            let frame: Vec<u8> = pending.drain(..len).collect();
+            let (kind, response, offset) = match reassembler.push(&frame) {
+                Ok(None) => continue,
+                Ok(Some(_)) if !link.secure => {
+                    warn!("Refusing request from {} over an unencrypted link", link.peer);
+                    let response = encode_error(Status::Unencrypted, "link is not encrypted");
+                    (MessageType::Error, Arc::new(response), 0)
+                }
+                Ok(Some(message)) => {
+                    answer(
+                        content,
+                        transfers,
+                        &link.peer,
+                        message.kind,
+                        &message.payload,
+                    )
+                    .await
+                }
+                Err(err) => {
+                    warn!("Dropping malformed request from {}: {}", link.peer, err);
+                    let response = encode_error(Status::BadRequest, &err.to_string());
+                    (MessageType::Error, Arc::new(response), 0)
+                }
+            };
+            let rest = &response[offset..];
+            debug!(
+                "Sending {} byte {:?} response to {}",
+                rest.len(),
+                kind,
+                link.peer
+            );
+            send(&mut link, kind, rest).await?;
+        }
+    }
+}
+
+/// Accepts clients from `transport` forever, serving each on its own task.
+pub async fn run(transport: &mut dyn Transport, content: ContentRoot) -> io::Result<()> {
+    run_with(transport, content, Transfers::new()).await
+}
+
+/// Like [`run`], with the transfer state shared with other transports.
+pub async fn run_with(
+    transport: &mut dyn Transport,
+    content: ContentRoot,
+    transfers: Transfers,
+) -> io::Result<()> {
+    loop {
+        let link = transport.accept().await?;
+        info!("Client connected: {}", link.peer);
+        let content = content.clone();
+        let transfers = transfers.clone();
+        tokio::spawn(async move {
+            let peer = link.peer.clone();
+            if let Err(err) = serve(&content, &transfers, link).await {
+                warn!("Connection to {} failed: {}", peer, err);
+            }
+        });
+    }
+}
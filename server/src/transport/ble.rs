@@ -0,0 +1,241 @@
+//! Bluetooth transport: a GATT service with a single characteristic that the
+//! client writes requests to and receives responses from as notifications.
+//!
+//! The characteristic only takes writes over a link encrypted with an
+//! authenticated LE Secure Connections key, BlueZ's `secure-write` flag:
+//! legacy pairing never gets that far. Setting `SecureConnections = only` in
+//! BlueZ's `main.conf` also has the adapter refuse legacy pairing outright,
+//! there is no D-Bus switch for it. Clients pair with passkey entry: the
+//! server logs a passkey which the user types on the client. BlueZ keeps the
+//! bond, so a client that paired once is encrypted right away when it
+//! reconnects.
+//!
+//! BlueZ does not tell GATT services how the link a request came in on is
+//! encrypted, but it refuses writes to a `secure-write` characteristic over
+//! any other link, so the write half of a link only exists on one. Links
+//! from bonded devices are [secure](Link::secure) as long as the
+//! characteristic keeps that flag, see [`is_secure`].
+
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+
+use bluer::{
+    adv::{Advertisement, AdvertisementHandle},
+    agent::{Agent, AgentHandle, DisplayPasskey},
+    gatt::{
+        local::{
+            characteristic_control, Application, ApplicationHandle, Characteristic,
+            CharacteristicControl, CharacteristicControlEvent, CharacteristicNotify,
+            CharacteristicWrite, CharacteristicWriteMethod, Service,
+        },
+        CharacteristicReader, CharacteristicWriter,
+    },
+    Adapter, Address, Uuid,
+};
+use futures::future::BoxFuture;
+use futures::{FutureExt, StreamExt};
+use log::{debug, info, warn};
+
+use super::{Link, Transport};
+use crate::config::BleConfig;
+
+pub struct BleTransport {
+    adapter: Adapter,
+    char_control: Pin<Box<CharacteristicControl>>,
+    /// Halves of links opened so far, by the device that opened them.
+    readers: HashMap<Address, CharacteristicReader>,
+    writers: HashMap<Address, CharacteristicWriter>,
+    // Dropping these removes the service, stops advertising and stops
+    // answering pairing requests.
+    _app_handle: ApplicationHandle,
+    _adv_handle: AdvertisementHandle,
+    _agent_handle: AgentHandle,
+}
+
+/// The write half of the page characteristic.
+pub fn page_write() -> CharacteristicWrite {
+    CharacteristicWrite {
+        write_without_response: true,
+        secure_write: true,
+        method: CharacteristicWriteMethod::Io,
+        ..Default::default()
+    }
+}
+
+/// Whether a link is secure when its requests come in through `write`, from
+/// a device that is `bonded` or not. BlueZ only takes writes with the
+/// `secure-write` flag over links encrypted with an authenticated LE Secure
+/// Connections key; bonding is what such a key comes from.
+pub fn is_secure(write: &CharacteristicWrite, bonded: bool) -> bool {
+    write.secure_write && bonded
+}
+
+/// Logs the passkey for a client that is pairing.
+fn display_passkey(req: DisplayPasskey) -> BoxFuture<'static, bluer::agent::ReqResult<()>> {
+    async move {
+        if req.entered == 0 {
+            info!("Pairing with {}, type passkey {:06} on the client", req.device, req.passkey);
+        }
+        Ok(())
+    }
+    .boxed()
+}
+
+impl BleTransport {
+    /// Advertises the page service as set up in `config`.
+    pub async fn new(config: &BleConfig) -> bluer::Result<Self> {
+        let service_uuid = Uuid::from_u128(config.service_uuid.0);
+        let characteristic_uuid = Uuid::from_u128(config.characteristic_uuid.0);
+        let session = bluer::Session::new().await?;
+        let adapter = match &config.adapter {
+            Some(name) => session.adapter(name)?,
+            None => session.default_adapter().await?,
+        };
+        adapter.set_powered(true).await?;
+        adapter.set_pairable(true).await?;
+
+        // Only showing passkeys makes BlueZ pair with passkey entry on the
+        // client, which has a keyboard. Devices that can't enter one only get
+        // unauthenticated keys, which the characteristic does not accept.
+        let agent = Agent {
+            request_default: true,
+            display_passkey: Some(Box::new(display_passkey)),
+            ..Default::default()
+        };
+        let agent_handle = session.register_agent(agent).await?;
+
+        info!("Advertising on Bluetooth adapter {} with address {}", adapter.name(), adapter.address().await?);
+        let le_advertisement = Advertisement {
+            service_uuids: vec![service_uuid].into_iter().collect(),
+            discoverable: Some(true),
+            local_name: Some(config.name.clone()),
+            ..Default::default()
+        };
+        let adv_handle = adapter.advertise(le_advertisement).await?;
+
+        info!("Serving GATT page service on Bluetooth adapter {}", adapter.name());
+        let (char_control, char_handle) = characteristic_control();
+        let app = Application {
+            services: vec![Service {
+                uuid: service_uuid,
+                primary: true,
+                characteristics: vec![Characteristic {
+                    uuid: characteristic_uuid,
+                    write: Some(page_write()),
+                    notify: Some(CharacteristicNotify {
+                        notify: true,
+                        ..Default::default()
+                    }),
+                    control_handle: char_handle,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let app_handle = adapter.serve_gatt_application(app).await?;
+
+        Ok(Self {
+            adapter,
+            char_control: Box::pin(char_control),
+            readers: HashMap::new(),
+            writers: HashMap::new(),
+            _app_handle: app_handle,
+            _adv_handle: adv_handle,
+            _agent_handle: agent_handle,
+        })
+    }
+}
+
+impl BleTransport {
+    /// Waits until some device has opened both halves of a link, and returns
+    /// them.
+    ///
+    /// Clients subscribe to notifications right after connecting, before they
+    /// write anything, so a notify half always comes first. A write half still
+    /// waiting when a notify half comes in is left over from a connection that
+    /// is gone, and so is a notify half whose subscription ended.
+    async fn next_pair(&mut self) -> io::Result<(CharacteristicReader, CharacteristicWriter)> {
+        loop {
+            let address = match self.char_control.next().await {
+                Some(CharacteristicControlEvent::Write(req)) => {
+                    debug!("Accepting write request event with MTU {}", req.mtu());
+                    let reader = match req.accept() {
+                        Ok(reader) => reader,
+                        Err(err) => {
+                            warn!("Accepting a write request failed: {}", err);
+                            continue;
+                        }
+                    };
+                    let address = reader.device_address();
+                    self.readers.insert(address, reader);
+                    address
+                }
+                Some(CharacteristicControlEvent::Notify(writer)) => {
+                    debug!("Accepting notify request event with MTU {}", writer.mtu());
+                    let address = writer.device_address();
+                    if self.readers.remove(&address).is_some() {
+                        debug!("Dropping the stale write half of {}", address);
+                    }
+                    self.writers.insert(address, writer);
+                    address
+                }
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::BrokenPipe,
+                        "GATT application was removed",
+                    ))
+                }
+            };
+            let closed = self
+                .writers
+                .get(&address)
+                .is_some_and(|writer| writer.is_closed().unwrap_or(true));
+            if closed {
+                debug!("Dropping the stale notify half of {}", address);
+                self.writers.remove(&address);
+            }
+            if self.readers.contains_key(&address) && self.writers.contains_key(&address) {
+                let reader = self.readers.remove(&address).unwrap();
+                let writer = self.writers.remove(&address).unwrap();
+                return Ok((reader, writer));
+            }
+        }
+    }
+
+    /// Whether the device at `address` is bonded with the adapter.
+    async fn is_bonded(&self, address: Address) -> bool {
+        let paired = async { self.adapter.device(address)?.is_paired().await };
+        let paired: bluer::Result<bool> = paired.await;
+        paired.unwrap_or_else(|err| {
+            warn!("Looking up {} failed: {}", address, err);
+            false
+        })
+    }
+}
+
+impl Transport for BleTransport {
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<Link>> {
+        async move {
+            // The client opens the write and notify halves separately, a link
+            // only exists once the same device opened both. Anyone else
+            // would get the pages another client asked for.
+            let (reader, writer) = self.next_pair().await?;
+            let address = reader.device_address();
+            let bonded = self.is_bonded(address).await;
+            if !bonded {
+                warn!("Link from {} is not bonded, it gets no pages", address);
+            }
+            Ok(Link {
+                read_mtu: reader.mtu(),
+                write_mtu: writer.mtu(),
+                peer: format!("{}", address),
+                secure: is_secure(&page_write(), bonded),
+                reader: Box::new(reader),
+                writer: Box::new(writer),
+            })
+        }
+        .boxed()
+    }
+}
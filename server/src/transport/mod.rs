@@ -0,0 +1,40 @@
+//! Ways for a client to reach the server.
+//!
+//! A [`Transport`] hands out [`Link`]s, each of which is a byte stream in both
+//! directions. The request handling in [`crate::session`] only ever sees a
+//! `Link`, so the same code runs over Bluetooth on the device and over a
+//! socket on a development machine.
+
+use std::io;
+
+use futures::future::BoxFuture;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+pub mod ble;
+pub mod socket;
+
+pub use ble::BleTransport;
+pub use socket::{TcpTransport, UnixTransport};
+
+/// A connected client.
+pub struct Link {
+    pub reader: Box<dyn AsyncRead + Send + Unpin>,
+    pub writer: Box<dyn AsyncWrite + Send + Unpin>,
+    /// Largest packet the reader may return at once.
+    pub read_mtu: usize,
+    /// Largest packet that may be handed to the writer at once.
+    pub write_mtu: usize,
+    /// Human readable name of the peer, for logging.
+    pub peer: String,
+    /// Whether others can neither read nor forge what is sent. Bluetooth
+    /// links are secure when they are encrypted with a key from bonding, see
+    /// [`ble::is_secure`]. Sockets are never encrypted, they only count as
+    /// secure when the server was told to trust them, see
+    /// [`TcpTransport::with_trusted`].
+    pub secure: bool,
+}
+
+pub trait Transport: Send {
+    /// Waits for the next client to connect.
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<Link>>;
+}
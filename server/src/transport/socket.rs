@@ -0,0 +1,135 @@
+//! Socket based transports for running the server without a Bluetooth adapter.
+//!
+//! Nothing sent over a socket is encrypted, so their links are not
+//! [secure](Link::secure) and get no pages, unless the transport is told to
+//! trust its clients. Only do that for sockets that nobody but trusted
+//! clients can reach, such as one on the loopback interface.
+
+use std::io;
+use std::net::SocketAddr;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::net::{TcpListener, UnixListener};
+
+use super::{Link, Transport};
+
+/// Sockets have no packet size limit, this only bounds how much is read and
+/// written at once.
+pub const SOCKET_MTU: usize = 512;
+
+pub struct TcpTransport {
+    listener: TcpListener,
+    trusted: bool,
+}
+
+impl TcpTransport {
+    pub async fn bind(addr: impl tokio::net::ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(Self {
+            listener,
+            trusted: false,
+        })
+    }
+
+    /// Counts the links of clients as secure if `trusted`.
+    pub fn with_trusted(mut self, trusted: bool) -> Self {
+        self.trusted = trusted;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<Link>> {
+        async move {
+            let (stream, peer) = self.listener.accept().await?;
+            let (reader, writer) = stream.into_split();
+            Ok(Link {
+                reader: Box::new(reader),
+                writer: Box::new(writer),
+                read_mtu: SOCKET_MTU,
+                write_mtu: SOCKET_MTU,
+                peer: peer.to_string(),
+                secure: self.trusted,
+            })
+        }
+        .boxed()
+    }
+}
+
+pub struct UnixTransport {
+    listener: UnixListener,
+    path: PathBuf,
+    /// Connections accepted so far. Unix clients have no address of their
+    /// own, so this tells them apart.
+    accepted: u64,
+    trusted: bool,
+}
+
+impl UnixTransport {
+    /// Binds to `path`, replacing a stale socket left behind by a previous run.
+    /// Anything else already at `path` is left alone and an
+    /// [`AlreadyExists`](io::ErrorKind::AlreadyExists) error returned.
+    pub fn bind(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        match std::fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(&path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                ))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        let listener = UnixListener::bind(&path)?;
+        Ok(Self {
+            listener,
+            path,
+            accepted: 0,
+            trusted: false,
+        })
+    }
+
+    /// Counts the links of clients as secure if `trusted`.
+    pub fn with_trusted(mut self, trusted: bool) -> Self {
+        self.trusted = trusted;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Transport for UnixTransport {
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<Link>> {
+        async move {
+            let (stream, _) = self.listener.accept().await?;
+            self.accepted += 1;
+            let (reader, writer) = stream.into_split();
+            Ok(Link {
+                reader: Box::new(reader),
+                writer: Box::new(writer),
+                read_mtu: SOCKET_MTU,
+                write_mtu: SOCKET_MTU,
+                peer: format!("{}#{}", self.path.display(), self.accepted),
+                secure: self.trusted,
+            })
+        }
+        .boxed()
+    }
+}
+
+impl Drop for UnixTransport {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
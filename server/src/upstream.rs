@@ -0,0 +1,127 @@
+//! Fetches HTML pages from another web server, for running the server as a
+//! proxy in front of it.
+//!
+//! Only plain `http://` is spoken, with one HTTP/1.0 request per page: enough
+//! for a web server on the same machine or network, without pulling in a TLS
+//! stack.
+
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// How long to wait for the upstream server before giving up on a page.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest page accepted from upstream.
+pub const MAX_RESPONSE_LEN: usize = 4 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum UpstreamError {
+    /// The upstream URL is not an `http://` URL, or not one that can be
+    /// sent as it is.
+    BadUrl(String),
+    /// The upstream server answered with a status other than 200.
+    Status(u16),
+    /// The response is not valid HTTP.
+    Malformed,
+    /// The upstream server took longer than [`TIMEOUT`] to send the page.
+    Timeout,
+    Io(io::Error),
+}
+
+impl fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpstreamError::BadUrl(url) => write!(f, "not an http:// URL: {}", url.escape_debug()),
+            UpstreamError::Status(status) => write!(f, "upstream answered {}", status),
+            UpstreamError::Malformed => write!(f, "malformed upstream response"),
+            UpstreamError::Timeout => write!(f, "upstream timed out"),
+            UpstreamError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for UpstreamError {}
+
+impl From<io::Error> for UpstreamError {
+    fn from(err: io::Error) -> Self {
+        UpstreamError::Io(err)
+    }
+}
+
+/// Joins the path of a request onto the base URL of the upstream server.
+pub fn join(base: &str, path: &str) -> String {
+    format!("{}/{}", base.trim_end_matches('/'), path.trim_start_matches('/'))
+}
+
+/// Fetches the body of `url`, giving up after [`TIMEOUT`].
+///
+/// URLs with whitespace or control characters are refused: they would end
+/// up in the request line and headers as they are.
+pub async fn get(url: &str) -> Result<Vec<u8>, UpstreamError> {
+    if url.contains(|c: char| c.is_whitespace() || c.is_control()) {
+        return Err(UpstreamError::BadUrl(url.to_string()));
+    }
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| UpstreamError::BadUrl(url.to_string()))?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    if host.is_empty() {
+        return Err(UpstreamError::BadUrl(url.to_string()));
+    }
+    let addr = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+
+    let mut response = timeout(TIMEOUT, exchange(&addr, host, path))
+        .await
+        .map_err(|_| UpstreamError::Timeout)??;
+    let end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or(UpstreamError::Malformed)?;
+    let head = std::str::from_utf8(&response[..end]).map_err(|_| UpstreamError::Malformed)?;
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or(UpstreamError::Malformed)?;
+    if status != 200 {
+        return Err(UpstreamError::Status(status));
+    }
+    if response.len() > MAX_RESPONSE_LEN {
+        return Err(UpstreamError::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "upstream page too large",
+        )));
+    }
+    Ok(response.split_off(end + 4))
+}
+
+/// Sends the request for `path` to `host` at `addr` and reads the whole
+/// response.
+async fn exchange(addr: &str, host: &str, path: &str) -> io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    stream
+        .take(MAX_RESPONSE_LEN as u64 + 1)
+        .read_to_end(&mut response)
+        .await?;
+    Ok(response)
+}
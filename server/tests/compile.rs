@@ -0,0 +1,211 @@
+use std::path::PathBuf;
+
+use server::compile::compile;
+use server::pages::ContentRoot;
+use swb_shared::{Instruction, Program, StyleVar};
+
+fn client_file(name: &str) -> Vec<u8> {
+    std::fs::read(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../client")
+            .join(name),
+    )
+    .unwrap()
+}
+
+fn compiled(name: &str) -> Program {
+    let html = client_file(&format!("{}.html", name));
+    Program::try_from(compile(std::str::from_utf8(&html).unwrap()).as_slice()).unwrap()
+}
+
+fn style(var: &StyleVar) -> &'static str {
+    match var {
+        StyleVar::Bold => "bold",
+        StyleVar::Italic => "italic",
+        _ => "other",
+    }
+}
+
+/// Splits `program` at every instruction other than `Text`, into that
+/// instruction and the text up to the next one, without whitespace.
+/// Underline, code and heading styles are left out: the shipped programs
+/// come from a compiler that had none.
+///
+/// The text of a part runs from the first address in it to the first address
+/// in the next, so runs that claim more than their share of the text segment
+/// do not matter.
+fn outline(program: &Program) -> Vec<(String, String)> {
+    let mut marks = vec![String::new()];
+    let mut bases = vec![None];
+    for instr in &program.code {
+        let mark = match instr {
+            Instruction::Text(address) => {
+                let base = bases.last_mut().unwrap();
+                base.get_or_insert(address.base.0 as usize);
+                continue;
+            }
+            Instruction::Push(StyleVar::Underline | StyleVar::Code | StyleVar::Heading)
+            | Instruction::Pop(StyleVar::Underline | StyleVar::Code | StyleVar::Heading) => {
+                continue
+            }
+            Instruction::Push(var) => format!("push {}", style(var)),
+            Instruction::Pop(var) => format!("pop {}", style(var)),
+            Instruction::Endl => "endl".to_string(),
+            Instruction::Stop => "stop".to_string(),
+        };
+        marks.push(mark);
+        bases.push(None);
+    }
+    let mut end = program.text.len();
+    let mut parts = Vec::new();
+    for (mark, base) in marks.into_iter().zip(bases).rev() {
+        let start = base.unwrap_or(end);
+        let text = program.text[start..end].split_whitespace().collect();
+        parts.push((mark, text));
+        end = start;
+    }
+    parts.reverse();
+    parts
+}
+
+/// Whether the characters of `part` appear in `whole`, in the same order.
+fn is_subsequence(part: &str, whole: &str) -> bool {
+    let mut whole = whole.chars();
+    part.chars().all(|c| whole.any(|w| w == c))
+}
+
+/// The ASCII contents of the `<style>` elements of `html`, without
+/// whitespace.
+fn style_sheets(html: &str) -> Vec<String> {
+    let mut sheets = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find("<style") {
+        let body = &rest[start..];
+        let body = &body[body.find('>').unwrap() + 1..];
+        let end = body.find("</style>").unwrap();
+        let sheet = body[..end].chars().filter(|c| c.is_ascii() && !c.is_ascii_whitespace());
+        sheets.push(sheet.collect());
+        rest = &body[end..];
+    }
+    sheets
+}
+
+/// The programs shipped with the client come from an older compiler, which
+/// dropped the whitespace between tags and any non-ASCII text, showed style
+/// sheets, and split the text of `ab` every 64 bytes. They can not be
+/// reproduced byte for byte, but compiling their pages must still give the
+/// same markup, and the same text apart from those differences.
+#[test]
+fn bundled_pages_compile_to_their_programs() {
+    for name in ["ab", "ns", "rust_datatypes"] {
+        let html = String::from_utf8(client_file(&format!("{}.html", name))).unwrap();
+        let sheets = style_sheets(&html);
+        let shipped = Program::try_from(client_file(&format!("{}.swb", name)).as_slice()).unwrap();
+        let shipped = outline(&shipped);
+        let compiled = outline(&compiled(name));
+        assert_eq!(shipped.len(), compiled.len(), "{}", name);
+        for (i, (shipped, compiled)) in shipped.iter().zip(&compiled).enumerate() {
+            assert_eq!(shipped.0, compiled.0, "{}: instruction {}", name, i);
+            let shipped = sheets
+                .iter()
+                .fold(shipped.1.clone(), |text, sheet| text.replace(sheet.as_str(), ""));
+            assert!(
+                is_subsequence(&shipped, &compiled.1),
+                "{}: {:?} does not hold {:?}",
+                name,
+                compiled.1,
+                shipped
+            );
+        }
+    }
+}
+
+fn code(html: &str) -> (String, Vec<Instruction>) {
+    let program = Program::try_from(compile(html).as_slice()).unwrap();
+    (program.text, program.code)
+}
+
+#[test]
+fn markup_is_reduced_to_text_styles_and_lines() {
+    let html = "<html><head><title>T</title><style>p{}</style></head>\
+        <body><script>x()</script><style>.a{}</style><!-- <b>no</b> --><div><div>one &amp; <b>two</b>\n  <i>three</i><br>four</div></div></body>";
+    let (text, code) = code(html);
+    assert_eq!(text, "one & two threefour");
+    assert!(matches!(
+        code.as_slice(),
+        [
+            Instruction::Endl,
+            Instruction::Text(_),
+            Instruction::Push(_),
+            Instruction::Text(_),
+            Instruction::Pop(_),
+            Instruction::Text(_),
+            Instruction::Push(_),
+            Instruction::Text(_),
+            Instruction::Pop(_),
+            Instruction::Endl,
+            Instruction::Text(_),
+            Instruction::Stop,
+        ]
+    ));
+}
+
+#[test]
+fn whitespace_between_tags_is_collapsed_to_one_space() {
+    assert_eq!(code("one <b>two</b>").0, "one two");
+    assert_eq!(code("one<b> two </b> three").0, "one two three");
+    assert_eq!(code("<b>one</b><i>two</i>").0, "onetwo");
+    assert_eq!(code("  one\n\n  two  <br>\n  three\n").0, "one two three ");
+}
+
+#[test]
+fn underline_code_and_headings_are_styles() {
+    let (text, code) = code("<h2>Types</h2>\n<u>Use</u> <code>u8</code>");
+    assert_eq!(text, "Types Use u8");
+    assert!(matches!(
+        code.as_slice(),
+        [
+            Instruction::Push(StyleVar::Heading),
+            Instruction::Text(_),
+            Instruction::Pop(StyleVar::Heading),
+            Instruction::Text(_),
+            Instruction::Push(StyleVar::Underline),
+            Instruction::Text(_),
+            Instruction::Pop(StyleVar::Underline),
+            Instruction::Text(_),
+            Instruction::Push(StyleVar::Code),
+            Instruction::Text(_),
+            Instruction::Pop(StyleVar::Code),
+            Instruction::Stop,
+        ]
+    ));
+}
+
+#[test]
+fn non_ascii_text_is_transliterated() {
+    let program = compile("na\u{ef}ve \u{2013} <b>\u{65e5}\u{672c}</b>");
+    let program = Program::try_from(program.as_slice()).unwrap();
+    assert_eq!(program.text, "naive - ??");
+    // Every run covers exactly the text pushed for it.
+    let mut end = 0;
+    for instr in &program.code {
+        if let Instruction::Text(address) = instr {
+            assert_eq!(address.base.0, end);
+            end += address.range;
+        }
+    }
+    assert_eq!(end as usize, program.text.len());
+}
+
+#[test]
+fn pages_without_a_program_are_compiled() {
+    let dir = std::env::temp_dir().join(format!("bambi-compile-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("page.html"), "<p>Hello <b>world</b></p>").unwrap();
+    let swb = ContentRoot::new(&dir).load("page.html").unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        Program::try_from(swb.as_slice()).unwrap().text,
+        "Hello world"
+    );
+}
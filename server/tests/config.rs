@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+
+use server::config::{Config, ConfigError, Listen, Uuid};
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn defaults_match_the_client() {
+    let config = Config::from_args(Vec::new()).unwrap();
+    assert_eq!(config.root, PathBuf::from("."));
+    assert_eq!(config.listen, [Listen::Ble]);
+    assert_eq!(config.ble.name, "bambi_gatt_server");
+    assert_eq!(config.ble.service_uuid.to_string(), "0000feed-0000-1000-8000-00805f9b34fb");
+    assert_eq!(config.ble.characteristic_uuid, Uuid::from_u16(0xf00d));
+    assert!(!config.trust_sockets);
+    assert!(!config.daemon);
+}
+
+#[test]
+fn files_only_need_what_differs() {
+    let config = Config::from_toml(
+        r#"
+        root = "/srv/pages"
+        listen = ["ble", "tcp:127.0.0.1:7878", "unix:/run/bambi.sock"]
+        daemon = true
+
+        [ble]
+        adapter = "hci1"
+        service_uuid = "0xbeef"
+        characteristic_uuid = "12345678-9abc-def0-1234-56789abcdef0"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.root, PathBuf::from("/srv/pages"));
+    assert_eq!(
+        config.listen,
+        [
+            Listen::Ble,
+            Listen::Tcp("127.0.0.1:7878".to_string()),
+            Listen::Unix(PathBuf::from("/run/bambi.sock")),
+        ]
+    );
+    assert!(config.daemon);
+    assert_eq!(config.log_level, "info");
+    assert_eq!(config.ble.adapter.as_deref(), Some("hci1"));
+    assert_eq!(config.ble.name, "bambi_gatt_server");
+    assert_eq!(config.ble.service_uuid, Uuid::from_u16(0xbeef));
+    assert_eq!(config.ble.characteristic_uuid, Uuid(0x12345678_9abc_def0_1234_56789abcdef0));
+}
+
+#[test]
+fn bad_files_are_rejected() {
+    assert!(matches!(Config::from_toml("colour = \"red\""), Err(ConfigError::Parse(_))));
+    assert!(matches!(Config::from_toml("listen = [\"carrier-pigeon\"]"), Err(ConfigError::Parse(_))));
+    assert!(matches!(
+        Config::from_toml("[ble]\nservice_uuid = \"not-a-uuid\""),
+        Err(ConfigError::Parse(_))
+    ));
+}
+
+#[test]
+fn flags_override_the_file() {
+    let path = std::env::temp_dir().join(format!("bambi-config-{}.toml", std::process::id()));
+    std::fs::write(&path, "root = \"pages\"\nlisten = [\"ble\"]\nlog_level = \"warn\"\n").unwrap();
+    let config = Config::from_args(args(&[
+        "--tcp",
+        "0.0.0.0:1234",
+        "--config",
+        path.to_str().unwrap(),
+        "--unix",
+        "/tmp/s",
+        "--name",
+        "bambi",
+        "--log-level",
+        "debug",
+        "--daemon",
+        "--trust-sockets",
+        "other",
+    ]));
+    std::fs::remove_file(&path).unwrap();
+    let config = config.unwrap();
+    assert_eq!(config.root, PathBuf::from("other"));
+    assert_eq!(
+        config.listen,
+        [Listen::Tcp("0.0.0.0:1234".to_string()), Listen::Unix(PathBuf::from("/tmp/s"))]
+    );
+    assert_eq!(config.ble.name, "bambi");
+    assert_eq!(config.log_level, "debug");
+    assert!(config.daemon);
+    assert!(config.trust_sockets);
+}
+
+#[test]
+fn flag_values_are_not_taken_for_flags() {
+    let config = Config::from_args(args(&["--name", "--config", "--log-level", "--daemon"])).unwrap();
+    assert_eq!(config.ble.name, "--config");
+    assert_eq!(config.log_level, "--daemon");
+    assert!(!config.daemon);
+}
+
+#[test]
+fn bad_flags_are_usage_errors() {
+    assert!(matches!(Config::from_args(args(&["--tcp"])), Err(ConfigError::Usage(_))));
+    assert!(matches!(Config::from_args(args(&["--frobnicate"])), Err(ConfigError::Usage(_))));
+    assert!(matches!(Config::from_args(args(&["a", "b"])), Err(ConfigError::Usage(_))));
+    assert!(matches!(
+        Config::from_args(args(&["--config", "a.toml", "--config", "b.toml"])),
+        Err(ConfigError::Usage(_))
+    ));
+    assert!(matches!(
+        Config::from_args(args(&["--proxy", "https://example.com"])),
+        Err(ConfigError::Invalid(_))
+    ));
+    assert!(matches!(
+        Config::from_args(args(&["--service-uuid", "f00d"])),
+        Err(ConfigError::Invalid(_))
+    ));
+}
@@ -0,0 +1,82 @@
+use server::compile::{compile_page, Anchor};
+use server::links::{links, resolve_href, title};
+use swb_shared::Program;
+
+/// The text of every anchor of `html` and its `href`.
+fn anchors(html: &str) -> Vec<(String, String)> {
+    let compiled = compile_page(html);
+    let text = Program::try_from(compiled.swb.as_slice()).ok().unwrap().text;
+    let anchors = compiled.anchors.iter().map(|anchor| {
+        let range = anchor.start as usize..(anchor.start + anchor.len) as usize;
+        (text[range].to_string(), anchor.href.clone())
+    });
+    anchors.collect()
+}
+
+#[test]
+fn anchors_are_recorded_in_document_order() {
+    let html = r##"<p>See <A HREF="./notes.pdf"><b>the
+        notes</b></a>, <abbr>not this</abbr> or <a class='x' href='/x.html?y=1&amp;z=2'> this &amp; that </a>.
+        <a name="anchor-only">no href</a><a href="#top">top</a><a href="empty.html"> </a>
+        <head><a href="hidden.html">hidden</a></head>"##;
+    assert_eq!(
+        anchors(html),
+        [
+            ("the notes", "./notes.pdf"),
+            ("this & that", "/x.html?y=1&z=2"),
+            ("top", "#top"),
+        ]
+        .map(|(text, href)| (text.to_string(), href.to_string()))
+    );
+}
+
+#[test]
+fn titles_are_extracted() {
+    let html = "<html><HEAD><Title lang=en>\n  Tom &amp; Jerry\n</title></head>";
+    assert_eq!(title(html).as_deref(), Some("Tom & Jerry"));
+    assert_eq!(title("<title> </title>"), None);
+    assert_eq!(title("<p>no title</p>"), None);
+}
+
+#[test]
+fn hrefs_are_resolved_against_the_page() {
+    assert_eq!(resolve_href("ab.html", "./ab.pdf").as_deref(), Some("ab.pdf"));
+    assert_eq!(resolve_href("dir/a.html", "b.html").as_deref(), Some("dir/b.html"));
+    assert_eq!(resolve_href("dir/sub/a.html", "../b.html").as_deref(), Some("dir/b.html"));
+    assert_eq!(resolve_href("dir/a.html", "/b.html").as_deref(), Some("b.html"));
+    assert_eq!(
+        resolve_href("http://host/dir/a.html?q", "../b.html").as_deref(),
+        Some("http://host/b.html")
+    );
+    assert_eq!(
+        resolve_href("ab.html", "https://example.com/x").as_deref(),
+        Some("https://example.com/x")
+    );
+    assert_eq!(resolve_href("ab.html", "#plaats-0"), None);
+}
+
+#[test]
+fn links_point_at_their_text() {
+    let anchors = [
+        Anchor { href: "a.html".to_string(), start: 7, len: 9 },
+        Anchor { href: "#top".to_string(), start: 17, len: 3 },
+        Anchor { href: "b.html".to_string(), start: 21, len: 9 },
+    ];
+    let links = links("dir/index.html", &anchors);
+    assert_eq!(links.len(), 2);
+    assert_eq!((links[0].start, links[0].len, links[0].href.as_str()), (7, 9, "dir/a.html"));
+    assert_eq!((links[1].start, links[1].len, links[1].href.as_str()), (21, 9, "dir/b.html"));
+}
+
+#[test]
+fn bundled_page_links_are_found() {
+    let html = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/../client/ab.html")).unwrap();
+    let compiled = compile_page(&html);
+    let program = Program::try_from(compiled.swb.as_slice()).ok().unwrap();
+    let links = links("ab.html", &compiled.anchors);
+
+    let notes = links.iter().find(|link| link.href == "ab-05-10-2022.pdf").unwrap();
+    let range = notes.start as usize..(notes.start + notes.len) as usize;
+    assert_eq!(&program.text[range], "Cursusnotas");
+    assert!(links.iter().all(|link| !link.href.starts_with('#')));
+}
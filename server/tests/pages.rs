@@ -0,0 +1,134 @@
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use protocol::page::decode_page;
+use protocol::{decode_error, MessageType, Status};
+use server::compile::compile;
+use server::pages::{ContentRoot, PageError};
+
+/// The client crate ships a handful of HTML pages with their compiled SWB.
+fn content() -> ContentRoot {
+    ContentRoot::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../client"))
+}
+
+fn expected(name: &str) -> Vec<u8> {
+    std::fs::read(content().path().join(name)).unwrap()
+}
+
+fn compiled(name: &str) -> Vec<u8> {
+    compile(&String::from_utf8(expected(name)).unwrap())
+}
+
+#[test]
+fn html_requests_serve_the_compiled_page() {
+    assert_eq!(content().load("ab.html").unwrap(), compiled("ab.html"));
+    assert_eq!(
+        content().load("http://localhost/rust_datatypes.html?q=1#top").unwrap(),
+        compiled("rust_datatypes.html")
+    );
+    // Programs are served as they are when asked for, or without a source.
+    assert_eq!(content().load("ns.swb").unwrap(), expected("ns.swb"));
+    assert_eq!(content().load("output.html").unwrap(), expected("output.swb"));
+}
+
+#[test]
+fn requests_cannot_leave_the_content_root() {
+    assert!(matches!(
+        content().load("../server/Cargo.toml"),
+        Err(PageError::BadRequest(_))
+    ));
+    assert!(matches!(
+        content().load("img/%2e%2E/%2e%2e/server/Cargo.toml"),
+        Err(PageError::BadRequest(_))
+    ));
+    assert!(matches!(content().load("ab%2"), Err(PageError::BadRequest(_))));
+}
+
+#[test]
+fn missing_pages_are_not_found() {
+    assert!(matches!(
+        content().load("does-not-exist.html"),
+        Err(PageError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn responses_carry_the_page_or_an_error() {
+    let (kind, payload) = content().respond(b"ab.html").await;
+    assert_eq!(kind, MessageType::Page);
+    let (program, header) = decode_page(&payload).unwrap();
+    assert_eq!(program, &compiled("ab.html")[..]);
+    assert_eq!(header.title, "Thuisbladzijde van AB 2022-2023");
+    assert!(!header.links.is_empty());
+
+    let (kind, payload) = content().respond(b"missing.html").await;
+    assert_eq!(kind, MessageType::Error);
+    let (status, reason) = decode_error(&payload);
+    assert_eq!(status, Some(Status::NotFound));
+    assert!(reason.contains("missing.html"));
+}
+
+/// Serves `body` to every HTTP request on a local port, returning its base URL.
+fn http_server(body: Vec<u8>) -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = [0; 1024];
+            let n = stream.read(&mut request).unwrap();
+            let request = String::from_utf8_lossy(&request[..n]);
+            let response = if request.starts_with("GET /dir/ab.html ") {
+                let mut response = b"HTTP/1.0 200 OK\r\nContent-Type: text/html\r\n\r\n".to_vec();
+                response.extend_from_slice(&body);
+                response
+            } else {
+                b"HTTP/1.0 404 Not Found\r\n\r\n".to_vec()
+            };
+            stream.write_all(&response).unwrap();
+        }
+    });
+    base
+}
+
+#[tokio::test]
+async fn pages_are_compiled_from_upstream() {
+    let html = expected("ab.html");
+    let upstream = ContentRoot::new("/nonexistent").with_upstream(http_server(html.clone()));
+
+    let (kind, payload) = upstream.respond(b"http://localhost/dir/ab.html").await;
+    assert_eq!(kind, MessageType::Page);
+    let (program, header) = decode_page(&payload).unwrap();
+    assert_eq!(program, compile(std::str::from_utf8(&html).unwrap()));
+    assert_eq!(header.title, "Thuisbladzijde van AB 2022-2023");
+    assert!(!header.links.is_empty());
+
+    let (kind, payload) = upstream.respond(b"dir/missing.html").await;
+    assert_eq!(kind, MessageType::Error);
+    assert_eq!(decode_error(&payload).0, Some(Status::NotFound));
+}
+
+#[tokio::test]
+async fn upstream_requests_are_checked_and_encoded() {
+    let html = expected("ab.html");
+    let upstream = ContentRoot::new("/nonexistent").with_upstream(http_server(html));
+
+    let (kind, _) = upstream.respond(b"dir/%61b.html").await;
+    assert_eq!(kind, MessageType::Page);
+
+    for request in [
+        &b"dir/ab.html HTTP/1.0\r\nHost: evil\r\n\r\nGET /dir/ab.html"[..],
+        b"dir/ab.html%0d%0aHost: evil",
+        b"../dir/ab.html",
+        b"dir/%2e%2e/%2e%2e/ab.html",
+    ] {
+        let (kind, payload) = upstream.respond(request).await;
+        assert_eq!(kind, MessageType::Error);
+        assert_eq!(decode_error(&payload).0, Some(Status::BadRequest));
+    }
+
+    // Sent as `/dir/ab.html%20HTTP/1.0`, which the server does not know.
+    let (kind, payload) = upstream.respond(b"dir/ab.html HTTP/1.0").await;
+    assert_eq!(kind, MessageType::Error);
+    assert_eq!(decode_error(&payload).0, Some(Status::NotFound));
+}
@@ -0,0 +1,290 @@
+use std::path::PathBuf;
+
+use bluer::gatt::local::CharacteristicWrite;
+use protocol::page::decode_page;
+use protocol::{
+    crc32, decode_error, encode_range, encode_resume, encode_revalidate, etag, fragments,
+    frame_len, MessageType, Reassembler, Status, HEADER_LEN,
+};
+use server::pages::ContentRoot;
+use server::session::{self, Transfers};
+use server::transport::{ble, Link, TcpTransport, Transport, UnixTransport};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio::net::{TcpStream, UnixStream};
+
+fn content() -> ContentRoot {
+    ContentRoot::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../client"))
+}
+
+/// The program served for the page `name`.
+fn program(name: &str) -> Vec<u8> {
+    content().load(name).unwrap()
+}
+
+async fn fetch(stream: &mut (impl AsyncRead + AsyncWrite + Unpin), page: &str) -> (MessageType, Vec<u8>) {
+    send(stream, MessageType::Request, page.as_bytes()).await;
+    receive(stream).await
+}
+
+async fn send(stream: &mut (impl AsyncWrite + Unpin), kind: MessageType, payload: &[u8]) {
+    for frame in fragments(kind, payload, 64) {
+        stream.write_all(&frame).await.unwrap();
+    }
+}
+
+async fn receive(stream: &mut (impl AsyncRead + Unpin)) -> (MessageType, Vec<u8>) {
+    let mut reassembler = Reassembler::new(64 * 1024);
+    let mut buf = Vec::new();
+    loop {
+        let mut chunk = [0; 256];
+        let n = stream.read(&mut chunk).await.unwrap();
+        assert_ne!(n, 0, "server closed the connection");
+        buf.extend_from_slice(&chunk[..n]);
+        while let Some(len) = frame_len(&buf) {
+            let frame: Vec<u8> = buf.drain(..len).collect();
+            if let Some(message) = reassembler.push(&frame).unwrap() {
+                assert!(buf.is_empty());
+                return (message.kind, message.payload);
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn serves_pages_over_tcp() {
+    let transport = TcpTransport::bind("127.0.0.1:0").await.unwrap();
+    let mut transport = transport.with_trusted(true);
+    let addr = transport.local_addr().unwrap();
+    tokio::spawn(async move { session::run(&mut transport, content()).await });
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let (kind, body) = fetch(&mut stream, "ab.html").await;
+    assert_eq!(kind, MessageType::Page);
+    assert_eq!(decode_page(&body).unwrap().0, program("ab.html"));
+
+    // The connection stays usable for further requests.
+    let (kind, body) = fetch(&mut stream, "missing.html").await;
+    assert_eq!(kind, MessageType::Error);
+    assert_eq!(decode_error(&body).0, Some(Status::NotFound));
+    let (kind, body) = fetch(&mut stream, "ns.html").await;
+    assert_eq!(kind, MessageType::Page);
+    assert_eq!(decode_page(&body).unwrap().0, program("ns.html"));
+}
+
+#[tokio::test]
+async fn serves_pages_over_unix_sockets() {
+    let path = std::env::temp_dir().join(format!("bambi-server-{}.sock", std::process::id()));
+    let mut transport = UnixTransport::bind(&path).unwrap().with_trusted(true);
+    tokio::spawn(async move { session::run(&mut transport, content()).await });
+
+    let mut stream = UnixStream::connect(&path).await.unwrap();
+    let (kind, body) = fetch(&mut stream, "rust_datatypes.html").await;
+    assert_eq!(kind, MessageType::Page);
+    assert_eq!(decode_page(&body).unwrap().0, program("rust_datatypes.html"));
+}
+
+#[tokio::test]
+async fn unix_clients_are_told_apart() {
+    let path = std::env::temp_dir().join(format!("bambi-peers-{}.sock", std::process::id()));
+    let mut transport = UnixTransport::bind(&path).unwrap();
+    let (_first, first) = tokio::join!(UnixStream::connect(&path), transport.accept());
+    let (_second, second) = tokio::join!(UnixStream::connect(&path), transport.accept());
+    assert_ne!(first.unwrap().peer, second.unwrap().peer);
+}
+
+#[tokio::test]
+async fn unix_sockets_only_replace_sockets() {
+    let path = std::env::temp_dir().join(format!("bambi-file-{}.sock", std::process::id()));
+    std::fs::write(&path, "not a socket").unwrap();
+    let err = UnixTransport::bind(&path).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+    std::fs::remove_file(&path).unwrap();
+
+    // A socket left behind by an earlier run is replaced.
+    let path = std::env::temp_dir().join(format!("bambi-stale-{}.sock", std::process::id()));
+    std::mem::forget(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    UnixTransport::bind(&path).unwrap();
+}
+
+/// Connects a client named `peer` to a session with `transfers`, over an
+/// in-memory stream.
+fn connect(peer: &str, transfers: &Transfers) -> DuplexStream {
+    connect_with(peer, transfers, true)
+}
+
+/// Like [`connect`], over a link that is `secure` or not.
+fn connect_with(peer: &str, transfers: &Transfers, secure: bool) -> DuplexStream {
+    let (client, server) = tokio::io::duplex(4096);
+    let (reader, writer) = tokio::io::split(server);
+    let link = Link {
+        reader: Box::new(reader),
+        writer: Box::new(writer),
+        read_mtu: 256,
+        write_mtu: 256,
+        peer: peer.to_string(),
+        secure,
+    };
+    let transfers = transfers.clone();
+    tokio::spawn(async move { session::serve(&content(), &transfers, link).await });
+    client
+}
+
+#[tokio::test]
+async fn interrupted_transfers_resume_after_reconnecting() {
+    let transfers = Transfers::new();
+    let mut stream = connect("device", &transfers);
+    send(&mut stream, MessageType::Request, b"ab.html").await;
+
+    // Take a few frames of the page, then lose the connection.
+    let mut received = Vec::new();
+    let mut buf = Vec::new();
+    while received.len() < 1000 {
+        let mut chunk = [0; 256];
+        let n = stream.read(&mut chunk).await.unwrap();
+        buf.extend_from_slice(&chunk[..n]);
+        while let Some(len) = frame_len(&buf) {
+            let frame: Vec<u8> = buf.drain(..len).collect();
+            received.extend_from_slice(&frame[HEADER_LEN..]);
+        }
+    }
+    drop(stream);
+
+    // Socket clients reconnect under another name.
+    let mut stream = connect("device again", &transfers);
+    let resume = encode_resume(received.len() as u32, crc32(&received), "ab.html");
+    send(&mut stream, MessageType::Resume, &resume).await;
+    let (kind, rest) = receive(&mut stream).await;
+    assert_eq!(kind, MessageType::Page);
+    received.extend_from_slice(&rest);
+    assert_eq!(
+        decode_page(&received).unwrap().0,
+        program("ab.html")
+    );
+
+    // Bytes of another response have nothing to resume, and neither does a
+    // different page.
+    let mut stream = connect("device", &transfers);
+    send(
+        &mut stream,
+        MessageType::Resume,
+        &encode_resume(4, !crc32(&received[..4]), "ab.html"),
+    )
+    .await;
+    let (kind, body) = receive(&mut stream).await;
+    assert_eq!(kind, MessageType::Error);
+    assert_eq!(decode_error(&body).0, Some(Status::NoTransfer));
+    send(
+        &mut stream,
+        MessageType::Resume,
+        &encode_resume(0, crc32(&[]), "ns.html"),
+    )
+    .await;
+    assert_eq!(
+        decode_error(&receive(&mut stream).await.1).0,
+        Some(Status::NoTransfer)
+    );
+}
+
+#[tokio::test]
+async fn current_copies_are_not_sent_again() {
+    let transfers = Transfers::new();
+    let mut stream = connect("device", &transfers);
+    let (kind, page) = fetch(&mut stream, "ab.html").await;
+    assert_eq!(kind, MessageType::Page);
+
+    send(&mut stream, MessageType::Revalidate, &encode_revalidate(etag(&page), "ab.html")).await;
+    assert_eq!(receive(&mut stream).await, (MessageType::NotModified, Vec::new()));
+
+    // A stale copy gets the page, which can be resumed like any other.
+    send(&mut stream, MessageType::Revalidate, &encode_revalidate(!etag(&page), "ab.html")).await;
+    assert_eq!(receive(&mut stream).await, (MessageType::Page, page.clone()));
+    let resume = encode_resume(100, crc32(&page[..100]), "ab.html");
+    send(&mut stream, MessageType::Resume, &resume).await;
+    assert_eq!(receive(&mut stream).await, (MessageType::Page, page[100..].to_vec()));
+
+    send(&mut stream, MessageType::Revalidate, &encode_revalidate(0, "missing.html")).await;
+    let (kind, body) = receive(&mut stream).await;
+    assert_eq!(kind, MessageType::Error);
+    assert_eq!(decode_error(&body).0, Some(Status::NotFound));
+}
+
+#[tokio::test]
+async fn parts_of_pages_are_sent_on_request() {
+    let transfers = Transfers::new();
+    let mut stream = connect("device", &transfers);
+    let (_, page) = fetch(&mut stream, "ns.html").await;
+    let range = encode_range(etag(&page), 100..350, "ns.html");
+    send(&mut stream, MessageType::Range, &range).await;
+    assert_eq!(receive(&mut stream).await, (MessageType::Page, page[100..350].to_vec()));
+
+    // Clients that were not sent the page last get it all the same.
+    let mut other = connect("other", &transfers);
+    send(&mut other, MessageType::Range, &range).await;
+    assert_eq!(receive(&mut other).await, (MessageType::Page, page[100..350].to_vec()));
+
+    let stale = encode_range(!etag(&page), 100..350, "ns.html");
+    send(&mut stream, MessageType::Range, &stale).await;
+    assert_eq!(decode_error(&receive(&mut stream).await.1).0, Some(Status::Changed));
+    let end = page.len() as u32;
+    let past = encode_range(etag(&page), end - 10..end + 10, "ns.html");
+    send(&mut stream, MessageType::Range, &past).await;
+    assert_eq!(decode_error(&receive(&mut stream).await.1).0, Some(Status::BadRequest));
+}
+
+#[tokio::test]
+async fn unencrypted_links_get_no_pages() {
+    let transfers = Transfers::new();
+    let mut stream = connect_with("eavesdropper", &transfers, false);
+    let (kind, body) = fetch(&mut stream, "ab.html").await;
+    assert_eq!(kind, MessageType::Error);
+    assert_eq!(decode_error(&body).0, Some(Status::Unencrypted));
+    send(&mut stream, MessageType::Resume, &encode_resume(0, crc32(&[]), "ab.html")).await;
+    let (kind, body) = receive(&mut stream).await;
+    assert_eq!(kind, MessageType::Error);
+    assert_eq!(decode_error(&body).0, Some(Status::Unencrypted));
+
+    // Nothing was sent, so there is nothing to resume either.
+    let mut stream = connect("device", &transfers);
+    send(&mut stream, MessageType::Resume, &encode_resume(0, crc32(&[]), "ab.html")).await;
+    assert_eq!(decode_error(&receive(&mut stream).await.1).0, Some(Status::NoTransfer));
+}
+
+#[tokio::test]
+async fn untrusted_sockets_get_no_pages() {
+    let mut transport = TcpTransport::bind("127.0.0.1:0").await.unwrap();
+    let addr = transport.local_addr().unwrap();
+    tokio::spawn(async move { session::run(&mut transport, content()).await });
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let (kind, body) = fetch(&mut stream, "ab.html").await;
+    assert_eq!(kind, MessageType::Error);
+    assert_eq!(decode_error(&body).0, Some(Status::Unencrypted));
+}
+
+#[tokio::test]
+async fn bonded_ble_links_are_served() {
+    let transfers = Transfers::new();
+    let secure = ble::is_secure(&ble::page_write(), true);
+    let mut stream = connect_with("bonded", &transfers, secure);
+    let (kind, body) = fetch(&mut stream, "ab.html").await;
+    assert_eq!(kind, MessageType::Page);
+    assert_eq!(decode_page(&body).unwrap().0, program("ab.html"));
+
+    // Devices that are not bonded get nothing, and neither does anyone if
+    // the characteristic also took writes with a legacy pairing key.
+    let legacy = CharacteristicWrite {
+        write: true,
+        encrypt_authenticated_write: true,
+        ..Default::default()
+    };
+    for secure in [
+        ble::is_secure(&ble::page_write(), false),
+        ble::is_secure(&legacy, true),
+    ] {
+        let mut stream = connect_with("stranger", &transfers, secure);
+        let (kind, body) = fetch(&mut stream, "ab.html").await;
+        assert_eq!(kind, MessageType::Error);
+        assert_eq!(decode_error(&body).0, Some(Status::Unencrypted));
+    }
+}
@@ -0,0 +1,117 @@
+//! Flash emulated in a file, for running the settings store on the host.
+//!
+//! Writes behave like NOR flash: they can only clear bits, and have to be
+//! word aligned. Erasing sets a page back to all ones.
+
+use std::fs::{File, OpenOptions};
+use std::future::{ready, Future, Ready};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use browser::settings::{Flash, WORD};
+
+/// Same as the nRF52840.
+pub const PAGE_SIZE: u32 = 4096;
+
+pub struct FileFlash {
+    file: File,
+    size: u32,
+}
+
+impl FileFlash {
+    /// Opens the flash in `path`, `pages` pages large. A new file starts out
+    /// erased.
+    pub fn open(path: &Path, pages: u32) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let size = pages * PAGE_SIZE;
+        let len = file.metadata()?.len();
+        if len < size as u64 {
+            file.seek(SeekFrom::Start(len))?;
+            file.write_all(&vec![0xff; (size as u64 - len) as usize])?;
+        }
+        Ok(Self { file, size })
+    }
+
+    fn check(&self, offset: u32, len: usize) -> io::Result<()> {
+        if offset as u64 + len as u64 > self.size as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "out of bounds"));
+        }
+        Ok(())
+    }
+
+    fn write_now(&mut self, offset: u32, data: &[u8]) -> io::Result<()> {
+        self.check(offset, data.len())?;
+        if offset % WORD != 0 || data.len() as u32 % WORD != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "unaligned write"));
+        }
+        let mut old = vec![0; data.len()];
+        self.read(offset, &mut old)?;
+        let new: Vec<u8> = old.iter().zip(data).map(|(old, data)| old & data).collect();
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.write_all(&new)
+    }
+
+    fn erase_now(&mut self, offset: u32) -> io::Result<()> {
+        self.check(offset, PAGE_SIZE as usize)?;
+        if offset % PAGE_SIZE != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "unaligned erase"));
+        }
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.write_all(&[0xff; PAGE_SIZE as usize])
+    }
+}
+
+impl Flash for FileFlash {
+    type Error = io::Error;
+    type WriteFuture<'a> = Ready<io::Result<()>>;
+    type EraseFuture<'a> = Ready<io::Result<()>>;
+
+    const PAGE_SIZE: u32 = PAGE_SIZE;
+
+    fn size(&self) -> u32 {
+        self.size
+    }
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> io::Result<()> {
+        self.check(offset, buf.len())?;
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.read_exact(buf)
+    }
+
+    fn write<'a>(&'a mut self, offset: u32, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        ready(self.write_now(offset, data))
+    }
+
+    fn erase(&mut self, offset: u32) -> Self::EraseFuture<'_> {
+        ready(self.erase_now(offset))
+    }
+}
+
+/// Runs `future` to completion. Enough for the settings store over a
+/// [`FileFlash`], whose futures are ready straight away.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    fn raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+
+    // SAFETY: the waker does nothing, so it upholds the contract trivially.
+    let waker = unsafe { Waker::from_raw(raw_waker()) };
+    let mut context = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
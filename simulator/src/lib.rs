@@ -0,0 +1,177 @@
+//! Runs the bambi browser UI on the host.
+//!
+//! The simulator drives the same layout and key handling as the firmware,
+//! but draws into a [`Framebuffer`] that is saved as a PNG and loads pages
+//! from disk or from a server listening on TCP.
+
+pub mod flash;
+pub mod script;
+pub mod source;
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use browser::address::{self, Edit};
+use browser::framebuffer::Framebuffer;
+use browser::history::History;
+use browser::keyboard::Keycode;
+use browser::page::Page;
+use browser::status::{Connection, Status};
+use browser::view::{action_for, apply_on_error, draw_error, Navigate, View, HEIGHT, WIDTH};
+use browser::HEAP_SIZE;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+
+use source::Source;
+
+pub struct Simulator {
+    source: Source,
+    history: History,
+    /// The current page, or why it could not be loaded.
+    page: Result<Page, String>,
+    view: View,
+    /// The URL being typed while the address bar is open.
+    editing: Option<String>,
+    screen: Framebuffer,
+}
+
+impl Simulator {
+    /// Starts the browser on `url`.
+    pub fn new(source: Source, url: &str) -> Self {
+        let mut simulator = Self {
+            source,
+            history: History::new(url, HEAP_SIZE / 4),
+            page: Err(String::new()),
+            view: View::new(0),
+            editing: None,
+            screen: Framebuffer::new(),
+        };
+        simulator.open();
+        simulator
+    }
+
+    pub fn screen(&self) -> &Framebuffer {
+        &self.screen
+    }
+
+    pub fn url(&self) -> &str {
+        self.history.url()
+    }
+
+    /// Why the current page could not be loaded, if it couldn't.
+    pub fn error(&self) -> Option<&str> {
+        self.page.as_ref().err().map(String::as_str)
+    }
+
+    /// Handles one key press, like the keyboard driver and `ui` on the device.
+    pub fn press(&mut self, code: Keycode) {
+        if let Some(url) = &mut self.editing {
+            match address::edit(url, code) {
+                Edit::Typing => {}
+                Edit::Submit => {
+                    let url = self.editing.take().unwrap();
+                    if !url.is_empty() {
+                        self.leave();
+                        self.history.push(url);
+                        self.open();
+                        return;
+                    }
+                }
+                Edit::Cancel => self.editing = None,
+            }
+            self.redraw();
+            return;
+        }
+
+        let Some(action) = action_for(code) else {
+            return;
+        };
+        let navigate = match &self.page {
+            Ok(page) => self.view.apply(page, action),
+            Err(_) => apply_on_error(action),
+        };
+        let Some(navigate) = navigate else {
+            return self.redraw();
+        };
+        match navigate {
+            Navigate::To(url) => {
+                self.leave();
+                self.history.push(url);
+            }
+            Navigate::Back => {
+                self.leave();
+                self.history.back();
+            }
+            Navigate::Forward => {
+                self.leave();
+                self.history.forward();
+            }
+            Navigate::EditAddress => {
+                self.editing = Some(String::from(self.history.url()));
+                return self.redraw();
+            }
+            // There is only the one source to pick.
+            Navigate::PickServer => return self.redraw(),
+            Navigate::Reload => {}
+        }
+        self.open();
+    }
+
+    /// Hands the current page back to the history.
+    fn leave(&mut self) {
+        if let Ok(page) = std::mem::replace(&mut self.page, Err(String::new())) {
+            self.history.leave(page, self.view.start_line());
+        }
+    }
+
+    /// Shows the current history entry, loading it if it wasn't kept.
+    fn open(&mut self) {
+        self.page = match self.history.take_page() {
+            Some(page) => Ok(page),
+            None => self.source.load(self.history.url()).map_err(|err| {
+                eprintln!("Loading {} failed: {}", self.history.url(), err);
+                err.to_string()
+            }),
+        };
+        self.view = View::new(self.history.start_line());
+        self.redraw();
+    }
+
+    fn redraw(&mut self) {
+        let screen = &mut self.screen;
+        // Pages are loaded whole, there is nothing to show progress for.
+        let mut status = Status::new(Connection::Connected);
+        match (&self.editing, &self.page) {
+            (None, Ok(page)) => {
+                // The view only redraws what changed since its last draw.
+                self.view.draw(page, screen).unwrap();
+                status.title = page.title.clone();
+                status.position = Some(self.view.position());
+            }
+            (Some(url), _) => {
+                screen.clear(BinaryColor::Off).unwrap();
+                self.view.invalidate();
+                return address::draw(self.history.url(), url, screen).unwrap();
+            }
+            (None, Err(reason)) => {
+                screen.clear(BinaryColor::Off).unwrap();
+                draw_error(self.history.url(), reason, screen).unwrap();
+                status.error = true;
+            }
+        }
+        status.draw(screen).unwrap();
+    }
+}
+
+/// Saves `screen` as a black and white PNG.
+pub fn write_png(screen: &Framebuffer, path: &Path) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), WIDTH, HEIGHT);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::One);
+    let mut writer = encoder.write_header().map_err(io::Error::from)?;
+    // In a 1-bit grayscale PNG a set bit is white, on the display it is black.
+    let data: Vec<u8> = screen.as_bytes().iter().map(|byte| !byte).collect();
+    writer.write_image_data(&data).map_err(io::Error::from)?;
+    Ok(())
+}
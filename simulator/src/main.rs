@@ -0,0 +1,80 @@
+//! Usage: `simulator [--server <addr>] [--keys <script>] [--out <png>] [url]`.
+//!
+//! Pages are loaded from the current directory, or from a server started with
+//! `--tcp <addr> --trust-sockets` when `--server` is given; the URL defaults
+//! to `ab.html`.
+//! With `--keys` the script is played and the final screen saved, otherwise
+//! every line read from stdin is played as a script and the screen saved after
+//! each one. See [`simulator::script`] for the script syntax.
+
+use std::io::BufRead;
+use std::path::PathBuf;
+
+use simulator::source::Source;
+use simulator::{script, write_png, Simulator};
+
+struct Args {
+    source: Source,
+    keys: Option<String>,
+    out: PathBuf,
+    url: String,
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: simulator [--server <addr>] [--keys <script>] [--out <png>] [url]");
+    std::process::exit(2);
+}
+
+fn parse_args() -> Args {
+    let mut source = Source::Dir(PathBuf::from("."));
+    let mut keys = None;
+    let mut out = PathBuf::from("screen.png");
+    let mut url = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--server" => source = Source::Server(args.next().unwrap_or_else(|| usage())),
+            "--keys" => keys = Some(args.next().unwrap_or_else(|| usage())),
+            "--out" => out = PathBuf::from(args.next().unwrap_or_else(|| usage())),
+            _ if arg.starts_with('-') || url.is_some() => usage(),
+            _ => url = Some(arg),
+        }
+    }
+    Args {
+        source,
+        keys,
+        out,
+        url: url.unwrap_or_else(|| "ab.html".to_string()),
+    }
+}
+
+fn play(simulator: &mut Simulator, keys: &str) -> Result<(), String> {
+    for code in script::parse(keys)? {
+        simulator.press(code);
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = parse_args();
+    let mut simulator = Simulator::new(args.source, &args.url);
+
+    match args.keys {
+        Some(keys) => {
+            play(&mut simulator, &keys)?;
+            write_png(simulator.screen(), &args.out)?;
+        }
+        None => {
+            write_png(simulator.screen(), &args.out)?;
+            println!("Showing {} in {}", simulator.url(), args.out.display());
+            for line in std::io::stdin().lock().lines() {
+                if let Err(err) = play(&mut simulator, &line?) {
+                    eprintln!("{}", err);
+                }
+                write_png(simulator.screen(), &args.out)?;
+                println!("Showing {} in {}", simulator.url(), args.out.display());
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+//! Scripted key presses.
+//!
+//! A script is a string of keys: printable characters stand for themselves
+//! and everything else is written as a name in angle brackets, so
+//! `kk<right><enter>` scrolls down twice and follows the first link.
+
+use browser::keyboard::Keycode;
+
+const NAMED: &[(&str, Keycode)] = &[
+    ("up", Keycode::Up),
+    ("down", Keycode::Down),
+    ("left", Keycode::Left),
+    ("right", Keycode::Right),
+    ("select", Keycode::Select),
+    ("enter", Keycode::Enter),
+    ("bs", Keycode::Backspace),
+    ("tab", Keycode::Tab),
+    ("lt", Keycode::Char('<')),
+    ("btn0", Keycode::Button(0)),
+    ("btn1", Keycode::Button(1)),
+    ("btn2", Keycode::Button(2)),
+    ("btn3", Keycode::Button(3)),
+];
+
+/// Parses a key script. Whitespace other than a plain space is ignored so
+/// scripts can be spread over several lines.
+pub fn parse(script: &str) -> Result<Vec<Keycode>, String> {
+    let mut keys = Vec::new();
+    let mut chars = script.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '<' => {
+                let name: String = chars.by_ref().take_while(|&c| c != '>').collect();
+                let code = NAMED
+                    .iter()
+                    .find(|(known, _)| *known == name)
+                    .map(|(_, code)| *code)
+                    .ok_or_else(|| format!("unknown key <{}>", name))?;
+                keys.push(code);
+            }
+            ' ' => keys.push(Keycode::Char(' ')),
+            c if c.is_whitespace() => {}
+            c => keys.push(Keycode::Char(c)),
+        }
+    }
+    Ok(keys)
+}
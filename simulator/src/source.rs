@@ -0,0 +1,129 @@
+//! Where the simulator gets its pages from.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::{Component, Path, PathBuf};
+
+use browser::decode::PageDecoder;
+use browser::page::{Page, PageError};
+use protocol::{decode_error, fragments, frame_len, MessageType, Reassembler, Status};
+
+/// Largest page accepted from a server.
+const MAX_PAGE_LEN: usize = 1024 * 1024;
+
+/// Fragment size used when talking to a server, as for its socket transports.
+const MTU: usize = 512;
+
+pub enum Source {
+    /// Compiled `.swb` files next to the HTML they were compiled from, laid
+    /// out like a server's content root. Pages loaded this way have no links.
+    Dir(PathBuf),
+    /// A server started with `--tcp <addr> --trust-sockets`.
+    Server(String),
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    BadUrl,
+    Io(io::Error),
+    Protocol(protocol::Error),
+    Server(Option<Status>, String),
+    Unexpected,
+    Page(PageError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::BadUrl => write!(f, "bad url"),
+            LoadError::Io(err) => write!(f, "{}", err),
+            LoadError::Protocol(err) => write!(f, "{}", err),
+            LoadError::Server(status, reason) => write!(f, "server error {:?}: {}", status, reason),
+            LoadError::Unexpected => write!(f, "unexpected response"),
+            LoadError::Page(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl From<protocol::Error> for LoadError {
+    fn from(err: protocol::Error) -> Self {
+        LoadError::Protocol(err)
+    }
+}
+
+impl Source {
+    pub fn load(&self, url: &str) -> Result<Page, LoadError> {
+        match self {
+            Source::Dir(root) => {
+                let swb = std::fs::read(swb_path(root, url)?)?;
+                Page::parse(&swb, Vec::new()).map_err(LoadError::Page)
+            }
+            Source::Server(addr) => fetch(addr, url),
+        }
+    }
+}
+
+/// Maps `url` onto the compiled page in `root`, the way the server does.
+fn swb_path(root: &Path, url: &str) -> Result<PathBuf, LoadError> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let mut resolved = root.to_path_buf();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => return Err(LoadError::BadUrl),
+        }
+    }
+    if resolved == root || resolved.is_dir() {
+        resolved.push("index.html");
+    }
+    Ok(resolved.with_extension("swb"))
+}
+
+/// Requests `url` from the server at `addr`, decoding the page as it arrives
+/// like the client does.
+fn fetch(addr: &str, url: &str) -> Result<Page, LoadError> {
+    let mut stream = TcpStream::connect(addr)?;
+    for frame in fragments(MessageType::Request, url.as_bytes(), MTU) {
+        stream.write_all(&frame)?;
+    }
+
+    let mut reassembler = Reassembler::new(MAX_PAGE_LEN);
+    let mut decoder = PageDecoder::new(MAX_PAGE_LEN);
+    let mut error = Vec::new();
+    let mut buf = Vec::new();
+    loop {
+        let mut chunk = [0; MTU];
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            return Err(LoadError::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        while let Some(len) = frame_len(&buf) {
+            let frame: Vec<u8> = buf.drain(..len).collect();
+            let chunk = reassembler.push_chunk(&frame)?;
+            match chunk.kind {
+                MessageType::Page => decoder.push(chunk.data).map_err(LoadError::Page)?,
+                MessageType::Error => error.extend_from_slice(chunk.data),
+                _ => return Err(LoadError::Unexpected),
+            }
+            if !chunk.last {
+                continue;
+            }
+            return match chunk.kind {
+                MessageType::Error => {
+                    let (status, reason) = decode_error(&error);
+                    Err(LoadError::Server(status, String::from(reason)))
+                }
+                _ => decoder.finish().map_err(LoadError::Page),
+            };
+        }
+    }
+}
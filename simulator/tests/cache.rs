@@ -0,0 +1,193 @@
+use std::path::PathBuf;
+
+use browser::cache::{Cache, CacheError};
+use browser::settings::Flash;
+use protocol::etag;
+use simulator::flash::{block_on, FileFlash, PAGE_SIZE};
+
+/// Room for three small pages.
+const PAGES: u32 = 3;
+
+/// A flash file that is removed again at the end of the test.
+struct TempFlash(PathBuf);
+
+impl TempFlash {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("bambi-cache-{}-{}.flash", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        Self(path)
+    }
+
+    fn open(&self) -> Cache<FileFlash> {
+        Cache::open(FileFlash::open(&self.0, PAGES).unwrap()).unwrap()
+    }
+}
+
+impl Drop for TempFlash {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn payload(seed: u8, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i as u8).wrapping_mul(31) ^ seed).collect()
+}
+
+fn urls(cache: &Cache<FileFlash>) -> Vec<&str> {
+    let mut urls: Vec<&str> = cache.urls().collect();
+    urls.sort();
+    urls
+}
+
+#[test]
+fn pages_survive_reopening() {
+    let flash = TempFlash::new("reopen");
+    let mut cache = flash.open();
+    let page = payload(1, 3001);
+    let tag = block_on(cache.insert("ab.html", &page)).unwrap();
+    assert_eq!(tag, etag(&page));
+    drop(cache);
+
+    let mut cache = flash.open();
+    assert_eq!(cache.etag("ab.html"), Some(etag(&page)));
+    assert_eq!(cache.get("ab.html").unwrap(), Some(page));
+    assert_eq!(cache.get("ns.html").unwrap(), None);
+}
+
+#[test]
+fn parts_of_pages_can_be_read() {
+    let flash = TempFlash::new("range");
+    let mut cache = flash.open();
+    let page = payload(4, PAGE_SIZE as usize + 700);
+    block_on(cache.insert("ns.html", &page)).unwrap();
+    let mut part = Vec::new();
+    let range = 300..PAGE_SIZE + 600;
+    let read = cache.read_range("ns.html", range.clone(), |data| {
+        part.extend_from_slice(data);
+        true
+    });
+    assert!(read.unwrap());
+    assert_eq!(part, &page[range.start as usize..range.end as usize]);
+    let past = cache.read_range("ns.html", 0..page.len() as u32 + 1, |_| true);
+    assert!(!past.unwrap());
+}
+
+#[test]
+fn pages_are_written_as_they_arrive() {
+    let flash = TempFlash::new("stream");
+    let mut cache = flash.open();
+    // Spans two flash pages, arriving in pieces that are not whole words.
+    let page = payload(2, PAGE_SIZE as usize + 100);
+    block_on(cache.begin("ns.html", page.len())).unwrap();
+    for piece in page.chunks(253) {
+        block_on(cache.append(piece)).unwrap();
+    }
+    assert_eq!(block_on(cache.commit()).unwrap(), etag(&page));
+    drop(cache);
+    assert_eq!(flash.open().get("ns.html").unwrap(), Some(page));
+}
+
+#[test]
+fn the_page_used_least_recently_makes_room() {
+    let flash = TempFlash::new("lru");
+    let mut cache = flash.open();
+    for url in ["a", "b", "c"] {
+        block_on(cache.insert(url, &payload(3, 1000))).unwrap();
+    }
+    block_on(cache.touch("a")).unwrap();
+    drop(cache);
+
+    // When pages were used is remembered.
+    let mut cache = flash.open();
+    block_on(cache.insert("d", &payload(4, 1000))).unwrap();
+    assert_eq!(urls(&cache), ["a", "c", "d"]);
+    block_on(cache.insert("e", &payload(5, 1000))).unwrap();
+    assert_eq!(urls(&cache), ["a", "d", "e"]);
+
+    // Pages larger than a flash page push out as many as needed.
+    block_on(cache.insert("f", &payload(6, 2 * PAGE_SIZE as usize))).unwrap();
+    assert_eq!(urls(&cache), ["f"]);
+}
+
+#[test]
+fn replacing_a_page_keeps_one_copy() {
+    let flash = TempFlash::new("replace");
+    let mut cache = flash.open();
+    block_on(cache.insert("ab.html", &payload(7, 500))).unwrap();
+    block_on(cache.insert("ab.html", &payload(8, 600))).unwrap();
+    drop(cache);
+    let mut cache = flash.open();
+    assert_eq!(urls(&cache), ["ab.html"]);
+    assert_eq!(cache.get("ab.html").unwrap(), Some(payload(8, 600)));
+    block_on(cache.remove("ab.html")).unwrap();
+    drop(cache);
+    assert!(urls(&flash.open()).is_empty());
+}
+
+#[test]
+fn pages_being_replaced_stay_until_the_new_copy_is_complete() {
+    let flash = TempFlash::new("pending");
+    let mut cache = flash.open();
+    let old = payload(12, 500);
+    block_on(cache.insert("ab.html", &old)).unwrap();
+    block_on(cache.begin("ab.html", 600)).unwrap();
+    block_on(cache.append(&payload(13, 300))).unwrap();
+    assert_eq!(cache.etag("ab.html"), Some(etag(&old)));
+    assert_eq!(cache.get("ab.html").unwrap(), Some(old.clone()));
+    // Cut short by a reset.
+    drop(cache);
+
+    let mut cache = flash.open();
+    assert_eq!(cache.get("ab.html").unwrap(), Some(old));
+    let new = payload(14, 600);
+    block_on(cache.begin("ab.html", new.len())).unwrap();
+    block_on(cache.append(&new)).unwrap();
+    block_on(cache.commit()).unwrap();
+    assert_eq!(urls(&cache), ["ab.html"]);
+    assert_eq!(cache.get("ab.html").unwrap(), Some(new.clone()));
+    drop(cache);
+    assert_eq!(flash.open().get("ab.html").unwrap(), Some(new));
+}
+
+#[test]
+fn incomplete_and_damaged_pages_are_dropped() {
+    let flash = TempFlash::new("damaged");
+    let mut cache = flash.open();
+    block_on(cache.insert("good", &payload(9, 100))).unwrap();
+    block_on(cache.insert("bad", &payload(10, 100))).unwrap();
+    // Cut short by a reset.
+    block_on(cache.begin("torn", 100)).unwrap();
+    block_on(cache.append(&payload(11, 50))).unwrap();
+    let mut file = cache.into_inner();
+
+    // Clear a bit in the payload of "bad".
+    let mut page = vec![0; PAGE_SIZE as usize];
+    file.read(PAGE_SIZE, &mut page).unwrap();
+    let word = 64 / 4 * 4 + 20;
+    let mut damaged = page[word..word + 4].to_vec();
+    damaged[0] &= 0xfe;
+    damaged[1] &= 0xfe;
+    block_on(file.write(PAGE_SIZE + word as u32, &damaged)).unwrap();
+    drop(file);
+
+    assert_eq!(urls(&flash.open()), ["good"]);
+}
+
+#[test]
+fn wrong_lengths_are_refused() {
+    let flash = TempFlash::new("lengths");
+    let mut cache = flash.open();
+    assert!(matches!(block_on(cache.append(b"data")), Err(CacheError::NotWriting)));
+    assert!(matches!(
+        block_on(cache.begin("huge", (PAGES * PAGE_SIZE) as usize)),
+        Err(CacheError::TooLarge)
+    ));
+    assert!(matches!(block_on(cache.begin("", 10)), Err(CacheError::BadUrl)));
+
+    block_on(cache.begin("short", 10)).unwrap();
+    block_on(cache.append(b"12345")).unwrap();
+    assert!(matches!(block_on(cache.commit()), Err(CacheError::Length)));
+    block_on(cache.begin("long", 4)).unwrap();
+    assert!(matches!(block_on(cache.append(b"12345")), Err(CacheError::Length)));
+    assert!(urls(&cache).is_empty());
+}
@@ -0,0 +1,21 @@
+use browser::keyboard::Keycode;
+use simulator::script::parse;
+
+#[test]
+fn parses_characters_and_named_keys() {
+    assert_eq!(
+        parse("k <down><enter>\n<lt>").unwrap(),
+        vec![
+            Keycode::Char('k'),
+            Keycode::Char(' '),
+            Keycode::Down,
+            Keycode::Enter,
+            Keycode::Char('<'),
+        ]
+    );
+}
+
+#[test]
+fn rejects_unknown_keys() {
+    assert!(parse("<nope>").is_err());
+}
@@ -0,0 +1,127 @@
+use std::path::PathBuf;
+
+use browser::settings::{Flash, Store, StoreError};
+use simulator::flash::{block_on, FileFlash, PAGE_SIZE};
+
+const PAGES: u32 = 4;
+
+/// A flash file that is removed again at the end of the test.
+struct TempFlash(PathBuf);
+
+impl TempFlash {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("bambi-{}-{}.flash", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        Self(path)
+    }
+
+    fn open(&self) -> Store<FileFlash> {
+        block_on(Store::open(FileFlash::open(&self.0, PAGES).unwrap())).unwrap()
+    }
+}
+
+impl Drop for TempFlash {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn settings_survive_reopening() {
+    let flash = TempFlash::new("reopen");
+    let mut store = flash.open();
+    assert_eq!(store.get("url").unwrap(), None);
+    block_on(store.set("url", b"ab.html")).unwrap();
+    block_on(store.set("line", &[5, 0, 0, 0])).unwrap();
+    block_on(store.set("url", b"ns.html")).unwrap();
+    block_on(store.remove("line")).unwrap();
+    drop(store);
+
+    let mut store = flash.open();
+    assert_eq!(store.get("url").unwrap().as_deref(), Some(&b"ns.html"[..]));
+    assert_eq!(store.get("line").unwrap(), None);
+}
+
+#[test]
+fn pages_take_turns() {
+    let flash = TempFlash::new("wear");
+    let mut store = flash.open();
+    block_on(store.set("server", &[0, 1, 2, 3, 4, 5, 6])).unwrap();
+    for line in 0..2000u32 {
+        block_on(store.set("line", &line.to_le_bytes())).unwrap();
+    }
+    let mut flash = store.into_inner();
+    // Every page has been in use.
+    for page in 0..PAGES {
+        let mut magic = [0; 4];
+        flash.read(page * PAGE_SIZE, &mut magic).unwrap();
+        assert_ne!(magic, [0xff; 4], "page {}", page);
+    }
+
+    let mut store = block_on(Store::open(flash)).unwrap();
+    assert_eq!(store.get("line").unwrap(), Some(1999u32.to_le_bytes().to_vec()));
+    assert_eq!(store.get("server").unwrap(), Some(vec![0, 1, 2, 3, 4, 5, 6]));
+}
+
+#[test]
+fn unchanged_values_are_not_written_again() {
+    let flash = TempFlash::new("unchanged");
+    let mut store = flash.open();
+    block_on(store.set("url", b"ab.html")).unwrap();
+    let before = std::fs::read(&flash.0).unwrap();
+    block_on(store.set("url", b"ab.html")).unwrap();
+    block_on(store.remove("missing")).unwrap();
+    assert!(std::fs::read(&flash.0).unwrap() == before);
+}
+
+#[test]
+fn records_cut_short_are_skipped() {
+    let flash = TempFlash::new("torn");
+    let mut store = flash.open();
+    block_on(store.set("url", b"ab.html")).unwrap();
+    drop(store);
+
+    // A record for "url" without its commit word, as left by a reset.
+    let mut file = FileFlash::open(&flash.0, PAGES).unwrap();
+    let mut page = vec![0; PAGE_SIZE as usize];
+    file.read(0, &mut page).unwrap();
+    let end = (8..PAGE_SIZE as usize)
+        .step_by(4)
+        .find(|&offset| page[offset..offset + 4] == [0xff; 4])
+        .unwrap();
+    let mut record = vec![3, 0xff, 7, 0];
+    record.extend_from_slice(b"urlns.html\xff\xff");
+    block_on(file.write(end as u32, &record)).unwrap();
+    drop(file);
+
+    let mut store = flash.open();
+    assert_eq!(store.get("url").unwrap().as_deref(), Some(&b"ab.html"[..]));
+    block_on(store.set("url", b"rust_datatypes.html")).unwrap();
+    drop(store);
+    let mut store = flash.open();
+    assert_eq!(store.get("url").unwrap().as_deref(), Some(&b"rust_datatypes.html"[..]));
+}
+
+#[test]
+fn what_does_not_fit_is_refused() {
+    let flash = TempFlash::new("full");
+    let mut store = flash.open();
+    block_on(store.set("a", &[1; 2000])).unwrap();
+    assert!(matches!(block_on(store.set("b", &[2; 2500])), Err(StoreError::Full)));
+    assert!(matches!(block_on(store.set("", b"x")), Err(StoreError::BadKey)));
+    assert!(matches!(block_on(store.set(&"k".repeat(33), b"x")), Err(StoreError::BadKey)));
+    // Replacing the big value still works, its old record is not copied.
+    block_on(store.set("a", &[3; 3000])).unwrap();
+    assert_eq!(store.get("a").unwrap(), Some(vec![3; 3000]));
+    assert_eq!(store.get("b").unwrap(), None);
+}
+
+#[test]
+fn foreign_flash_is_taken_over() {
+    let flash = TempFlash::new("foreign");
+    std::fs::write(&flash.0, vec![0x5a; (PAGES * PAGE_SIZE) as usize]).unwrap();
+    let mut store = flash.open();
+    assert_eq!(store.get("url").unwrap(), None);
+    block_on(store.set("url", b"ab.html")).unwrap();
+    assert_eq!(store.get("url").unwrap().as_deref(), Some(&b"ab.html"[..]));
+}
@@ -0,0 +1,90 @@
+use std::path::PathBuf;
+
+use browser::keyboard::Keycode;
+use simulator::source::Source;
+use simulator::Simulator;
+
+fn content() -> Source {
+    Source::Dir(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../client"))
+}
+
+fn ink(simulator: &Simulator) -> usize {
+    simulator.screen().as_bytes().iter().map(|byte| byte.count_ones() as usize).sum()
+}
+
+#[test]
+fn renders_pages_from_disk() {
+    let simulator = Simulator::new(content(), "ab.html");
+    assert!(ink(&simulator) > 0);
+}
+
+#[test]
+fn scrolling_changes_the_screen() {
+    let mut simulator = Simulator::new(content(), "rust_datatypes.html");
+    let top = simulator.screen().clone();
+    simulator.press(Keycode::Down);
+    assert!(*simulator.screen() != top);
+    simulator.press(Keycode::Up);
+    assert!(*simulator.screen() == top);
+}
+
+#[test]
+fn address_bar_navigates() {
+    let mut simulator = Simulator::new(content(), "ab.html");
+    simulator.press(Keycode::Char('g'));
+    for _ in 0.."ab.html".len() {
+        simulator.press(Keycode::Backspace);
+    }
+    for c in "ns.html".chars() {
+        simulator.press(Keycode::Char(c));
+    }
+    simulator.press(Keycode::Enter);
+    assert_eq!(simulator.url(), "ns.html");
+
+    simulator.press(Keycode::Backspace);
+    assert_eq!(simulator.url(), "ab.html");
+}
+
+#[test]
+fn address_bar_can_be_cleared_and_cancelled() {
+    let mut simulator = Simulator::new(content(), "ab.html");
+    let page = simulator.screen().clone();
+    simulator.press(Keycode::Char('g'));
+    simulator.press(Keycode::Button(1));
+    for c in "ns.html".chars() {
+        simulator.press(Keycode::Char(c));
+    }
+    simulator.press(Keycode::Button(0));
+    assert_eq!(simulator.url(), "ab.html");
+    assert!(*simulator.screen() == page);
+
+    simulator.press(Keycode::Char('g'));
+    simulator.press(Keycode::Button(1));
+    for c in "ns.html".chars() {
+        simulator.press(Keycode::Char(c));
+    }
+    simulator.press(Keycode::Enter);
+    assert_eq!(simulator.url(), "ns.html");
+}
+
+#[test]
+fn error_pages_keep_the_browser_usable() {
+    let mut simulator = Simulator::new(content(), "missing.html");
+    assert!(simulator.error().is_some());
+    assert!(ink(&simulator) > 0);
+
+    simulator.press(Keycode::Char('g'));
+    for _ in 0.."missing.html".len() {
+        simulator.press(Keycode::Backspace);
+    }
+    for c in "ab.html".chars() {
+        simulator.press(Keycode::Char(c));
+    }
+    simulator.press(Keycode::Enter);
+    assert_eq!(simulator.url(), "ab.html");
+    assert!(simulator.error().is_none());
+
+    simulator.press(Keycode::Backspace);
+    assert_eq!(simulator.url(), "missing.html");
+    assert!(simulator.error().is_some());
+}